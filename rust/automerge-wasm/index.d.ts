@@ -309,6 +309,8 @@ export interface JsSyncState {
     theirHeed: Heads | undefined;
     theirHave: SyncHave[] | undefined;
     sentHashes: Heads;
+    bloomParams?: { bitsPerEntry: number, numProbes: number };
+    ibltCells?: number;
 }
 
 export class SyncState {
@@ -53,6 +53,8 @@ impl From<JS> for JsValue {
 
 impl From<am::sync::State> for JS {
     fn from(state: am::sync::State) -> Self {
+        let params = state.bloom_params();
+        let iblt_cells = state.iblt_cells();
         let shared_heads: JS = state.shared_heads.into();
         let last_sent_heads: JS = state.last_sent_heads.into();
         let their_heads: JS = state.their_heads.into();
@@ -74,6 +76,23 @@ impl From<am::sync::State> for JS {
         Reflect::set(&result, &"sentHashes".into(), &sent_hashes.0).unwrap();
         Reflect::set(&result, &"inFlight".into(), &state.in_flight.into()).unwrap();
         Reflect::set(&result, &"haveResponded".into(), &have_responded).unwrap();
+        let bloom_params: JsValue = Object::new().into();
+        Reflect::set(
+            &bloom_params,
+            &"bitsPerEntry".into(),
+            &params.bits_per_entry().into(),
+        )
+        .unwrap();
+        Reflect::set(
+            &bloom_params,
+            &"numProbes".into(),
+            &params.num_probes().into(),
+        )
+        .unwrap();
+        Reflect::set(&result, &"bloomParams".into(), &bloom_params).unwrap();
+        if let Some(cells) = iblt_cells {
+            Reflect::set(&result, &"ibltCells".into(), &(cells as f64).into()).unwrap();
+        }
         if let Some(caps) = state.their_capabilities {
             Reflect::set(
                 &result,
@@ -326,17 +345,49 @@ impl TryFrom<JS> for am::sync::State {
                 None
             }
         };
-        Ok(am::sync::State {
-            shared_heads,
-            last_sent_heads,
-            their_heads,
-            their_need,
-            their_have,
-            sent_hashes,
-            in_flight,
-            have_responded,
-            their_capabilities,
-        })
+        let bloom_params = {
+            let params = js_get(&value, "bloomParams")?;
+            if params.is_undefined() {
+                am::sync::BloomParams::default()
+            } else {
+                let bits_per_entry = js_get(&params.0, "bitsPerEntry")?
+                    .0
+                    .as_f64()
+                    .ok_or(error::BadSyncState::BadBloomParams)?;
+                let num_probes = js_get(&params.0, "numProbes")?
+                    .0
+                    .as_f64()
+                    .ok_or(error::BadSyncState::BadBloomParams)?;
+                am::sync::BloomParams::new(bits_per_entry as u32, num_probes as u32)
+            }
+        };
+        let iblt_cells = {
+            let cells = js_get(&value, "ibltCells")?;
+            if cells.is_undefined() || cells.is_null() {
+                None
+            } else {
+                Some(
+                    cells
+                        .0
+                        .as_f64()
+                        .ok_or(error::BadSyncState::IbltCellsNotNumber)?
+                        as usize,
+                )
+            }
+        };
+        let mut state = am::sync::State::new();
+        state.shared_heads = shared_heads;
+        state.last_sent_heads = last_sent_heads;
+        state.their_heads = their_heads;
+        state.their_need = their_need;
+        state.their_have = their_have;
+        state.sent_hashes = sent_hashes;
+        state.in_flight = in_flight;
+        state.have_responded = have_responded;
+        state.their_capabilities = their_capabilities;
+        state.set_bloom_params(bloom_params);
+        state.set_iblt_cells(iblt_cells);
+        Ok(state)
     }
 }
 
@@ -350,7 +401,7 @@ impl TryFrom<JS> for am::sync::Have {
         let bloom = js_get(&value.0, "bloom")?
             .try_into()
             .map_err(error::BadHave::BadBloom)?;
-        Ok(am::sync::Have { last_sync, bloom })
+        Ok(am::sync::Have::new(last_sync, bloom))
     }
 }
 
@@ -529,6 +580,7 @@ impl From<&[am::sync::Capability]> for AR {
             .filter_map(|c| match c {
                 am::sync::Capability::MessageV1 => Some(JsValue::from_str("message-v1")),
                 am::sync::Capability::MessageV2 => Some(JsValue::from_str("message-v2")),
+                am::sync::Capability::Iblt => Some(JsValue::from_str("iblt")),
                 am::sync::Capability::Unknown(_) => None,
             })
             .collect())
@@ -587,6 +639,7 @@ impl TryFrom<JS> for Vec<Capability> {
                 match as_str.as_str() {
                     "message-v1" => Ok(Capability::MessageV1),
                     "message-v2" => Ok(Capability::MessageV2),
+                    "iblt" => Ok(Capability::Iblt),
                     other => Err(error::BadCapabilities::ElemNotValid(i, other.to_string())),
                 }
             })
@@ -1792,6 +1845,10 @@ pub(crate) mod error {
         InFlightNotBoolean,
         #[error("bad theirCapabilities: {0}")]
         BadTheirCapabilities(BadCapabilities),
+        #[error("bloomParams must have numeric bitsPerEntry and numProbes")]
+        BadBloomParams,
+        #[error("ibltCells not a number")]
+        IbltCellsNotNumber,
     }
 
     impl From<BadSyncState> for JsValue {
@@ -1,22 +1,33 @@
 use std::ops::RangeBounds;
 
+mod branches;
+use branches::Branches;
+pub use branches::BranchesFromBytesError;
+
 use crate::automerge::SaveOptions;
 use crate::automerge::{current_state, diff};
+use crate::change_store::{ChangeStore, ChangeStoreError};
+use crate::error::SquashError;
 use crate::exid::ExId;
+use crate::history::HistoryIter;
 use crate::iter::Spans;
 use crate::iter::{Keys, ListRange, MapRange, Values};
 use crate::marks::{ExpandMark, Mark, MarkSet};
 use crate::patches::{PatchLog, TextRepresentation};
+use crate::storage_stats::StorageStats;
 use crate::sync::SyncDoc;
 use crate::transaction::{CommitOptions, Transactable};
 use crate::types::Clock;
 use crate::{hydrate, OnPartialLoad};
-use crate::{sync, ObjType, Parents, Patch, ReadDoc, ScalarValue};
+use crate::{sync, Compression, ObjType, Parents, Patch, ReadDoc, ScalarValue};
 use crate::{
     transaction::TransactionInner, ActorId, Automerge, AutomergeError, Change, ChangeHash, Cursor,
     Prop, Value,
 };
-use crate::{LoadOptions, VerificationMode};
+use crate::{
+    CausalOrder, LoadOptions, MergePreview, ReadAt, ReadDocAt, RedactCandidate, RedactionRecord,
+    RepairReport, VectorClock, VerificationMode,
+};
 
 /// An automerge document that automatically manages transactions.
 ///
@@ -62,6 +73,7 @@ pub struct AutoCommit {
     diff_cache: Option<(OpRange, Vec<Patch>)>,
     save_cursor: Vec<ChangeHash>,
     isolation: Option<Vec<ChangeHash>>,
+    branches: Branches,
 }
 
 /// An autocommit document with an inactive [`PatchLog`]
@@ -77,6 +89,7 @@ impl Default for AutoCommit {
             diff_cache: None,
             save_cursor: Vec::new(),
             isolation: None,
+            branches: Branches::default(),
         }
     }
 }
@@ -96,6 +109,7 @@ impl AutoCommit {
             diff_cache: None,
             save_cursor: Vec::new(),
             isolation: None,
+            branches: Branches::default(),
         })
     }
 
@@ -109,6 +123,7 @@ impl AutoCommit {
             diff_cache: None,
             save_cursor: Vec::new(),
             isolation: None,
+            branches: Branches::default(),
         })
     }
 
@@ -139,9 +154,26 @@ impl AutoCommit {
             diff_cache: None,
             save_cursor: Vec::new(),
             isolation: None,
+            branches: Branches::default(),
         })
     }
 
+    /// Recover as much as possible from damaged data, see [`Automerge::repair()`]
+    pub fn repair(data: &[u8]) -> (Self, RepairReport) {
+        let (doc, report) = Automerge::repair(data);
+        let doc = Self {
+            doc,
+            transaction: None,
+            patch_log: PatchLog::inactive(TextRepresentation::default()),
+            diff_cursor: Vec::new(),
+            diff_cache: None,
+            save_cursor: Vec::new(),
+            isolation: None,
+            branches: Branches::default(),
+        };
+        (doc, report)
+    }
+
     /// Erases the diff cursor created by [`Self::update_diff_cursor()`] and no
     /// longer indexes changes to the document.
     pub fn reset_diff_cursor(&mut self) {
@@ -264,6 +296,36 @@ impl AutoCommit {
         patches
     }
 
+    /// Create patches which turn the current state of this document into the current state of
+    /// `other`, see [`Automerge::diff_documents()`]
+    pub fn diff_documents(&mut self, other: &mut AutoCommit) -> Result<Vec<Patch>, AutomergeError> {
+        self.ensure_transaction_closed();
+        other.ensure_transaction_closed();
+        self.doc
+            .diff_documents(&other.doc, self.patch_log.text_rep())
+    }
+
+    /// See [`Automerge::preview_merge()`]
+    pub fn preview_merge(
+        &mut self,
+        other: &mut AutoCommit,
+    ) -> Result<MergePreview, AutomergeError> {
+        self.ensure_transaction_closed();
+        other.ensure_transaction_closed();
+        self.doc
+            .preview_merge(&other.doc, self.patch_log.text_rep())
+    }
+
+    /// See [`Automerge::preview_apply_changes()`]
+    pub fn preview_apply_changes(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+    ) -> Result<MergePreview, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc
+            .preview_apply_changes(changes, self.patch_log.text_rep())
+    }
+
     pub fn fork(&mut self) -> Self {
         self.ensure_transaction_closed();
         Self {
@@ -274,6 +336,7 @@ impl AutoCommit {
             diff_cache: None,
             save_cursor: vec![],
             isolation: None,
+            branches: Branches::default(),
         }
     }
 
@@ -287,9 +350,81 @@ impl AutoCommit {
             diff_cache: None,
             save_cursor: vec![],
             isolation: None,
+            branches: Branches::default(),
         })
     }
 
+    /// Rewrite the local changes made since `since_heads` into a single change, see
+    /// [`Automerge::squash_local()`]
+    ///
+    /// Changes which have been saved with [`Self::save()`] or [`Self::save_incremental()`] count
+    /// as shared. The diff cursor, the isolation heads and the heads of branches must either be
+    /// the heads of the document, in which case they move to the new heads, or not depend on any
+    /// of the squashed changes.
+    pub fn squash_local<'a, I: IntoIterator<Item = &'a sync::State>>(
+        &mut self,
+        since_heads: &[ChangeHash],
+        peers: I,
+    ) -> Result<Option<ChangeHash>, SquashError> {
+        self.ensure_transaction_closed();
+        self.record_current_branch();
+        let heads = self.doc.get_heads();
+        let mut pinned = self.save_cursor.clone();
+        let cursors = std::iter::once(&self.diff_cursor)
+            .chain(self.isolation.as_ref())
+            .chain(self.branches.heads());
+        pinned.extend(cursors.filter(|c| **c != heads).flatten().copied());
+        let squashed = self.doc.squash_local_pinned(since_heads, peers, pinned)?;
+        if squashed.is_some() {
+            let new_heads = self.doc.get_heads();
+            let cursors = std::iter::once(&mut self.diff_cursor)
+                .chain(self.isolation.as_mut())
+                .chain(self.branches.heads_mut());
+            for cursor in cursors.filter(|c| **c == heads) {
+                cursor.clone_from(&new_heads);
+            }
+            self.diff_cache = None;
+        }
+        Ok(squashed)
+    }
+
+    /// Rewrite the history with the values of the ops chosen by `select` redacted, see
+    /// [`Automerge::redact()`]
+    ///
+    /// The new document has an inactive patch log and no branches, like [`Self::fork()`].
+    pub fn redact<F>(&mut self, select: F) -> Result<(AutoCommit, RedactionRecord), AutomergeError>
+    where
+        F: FnMut(&RedactCandidate<'_>) -> bool,
+    {
+        self.ensure_transaction_closed();
+        let (doc, record) = self.doc.redact(select)?;
+        Ok((self.with_redacted_doc(doc), record))
+    }
+
+    /// Redact the ops which were redacted to produce `record`, see
+    /// [`Automerge::apply_redaction()`]
+    pub fn apply_redaction(
+        &mut self,
+        record: &RedactionRecord,
+    ) -> Result<AutoCommit, AutomergeError> {
+        self.ensure_transaction_closed();
+        let doc = self.doc.apply_redaction(record)?;
+        Ok(self.with_redacted_doc(doc))
+    }
+
+    fn with_redacted_doc(&self, doc: Automerge) -> AutoCommit {
+        Self {
+            doc,
+            transaction: None,
+            patch_log: PatchLog::inactive(self.patch_log.text_rep()),
+            diff_cursor: vec![],
+            diff_cache: None,
+            save_cursor: vec![],
+            isolation: None,
+            branches: Branches::default(),
+        }
+    }
+
     /// Get the inner document.
     #[doc(hidden)]
     pub fn document(&mut self) -> &Automerge {
@@ -309,18 +444,50 @@ impl AutoCommit {
         self
     }
 
+    /// See [`Automerge::with_change_store()`]
+    pub fn with_change_store<S: ChangeStore + 'static>(
+        mut self,
+        store: S,
+    ) -> Result<Self, ChangeStoreError> {
+        self.ensure_transaction_closed();
+        self.doc.set_change_store(store)?;
+        Ok(self)
+    }
+
+    /// See [`Automerge::set_change_store()`]
+    pub fn set_change_store<S: ChangeStore + 'static>(
+        &mut self,
+        store: S,
+    ) -> Result<&mut Self, ChangeStoreError> {
+        self.ensure_transaction_closed();
+        self.doc.set_change_store(store)?;
+        Ok(self)
+    }
+
+    /// See [`Automerge::release_cached_changes()`]
+    pub fn release_cached_changes(&mut self) {
+        self.doc.release_cached_changes()
+    }
+
+    /// See [`Automerge::materialize_all()`]
+    pub fn materialize_all(&mut self) {
+        self.doc.materialize_all()
+    }
+
     pub fn get_actor(&self) -> &ActorId {
         self.doc.get_actor()
     }
 
     pub fn isolate(&mut self, heads: &[ChangeHash]) {
         self.ensure_transaction_closed();
+        self.leave_branch();
         self.patch_to(heads);
         self.isolation = Some(heads.to_vec())
     }
 
     pub fn integrate(&mut self) {
         self.ensure_transaction_closed();
+        self.leave_branch();
         self.patch_to(self.doc.get_heads().as_slice());
         self.isolation = None;
     }
@@ -402,6 +569,24 @@ impl AutoCommit {
         bytes
     }
 
+    /// See [`Automerge::try_save()`]
+    pub fn try_save(&mut self) -> Result<Vec<u8>, ChangeStoreError> {
+        self.try_save_with_options(SaveOptions::default())
+    }
+
+    /// See [`Automerge::try_save_with_options()`]
+    pub fn try_save_with_options(
+        &mut self,
+        options: SaveOptions,
+    ) -> Result<Vec<u8>, ChangeStoreError> {
+        self.ensure_transaction_closed();
+        let bytes = self.doc.try_save_with_options(options)?;
+        if !bytes.is_empty() {
+            self.save_cursor = self.doc.get_heads()
+        }
+        Ok(bytes)
+    }
+
     /// Save the document and attempt to load it before returning - slow!
     pub fn save_and_verify(&mut self) -> Result<Vec<u8>, AutomergeError> {
         let bytes = self.save();
@@ -409,10 +594,22 @@ impl AutoCommit {
         Ok(bytes)
     }
 
-    /// Save this document, but don't run it through DEFLATE afterwards
+    /// See [`Automerge::storage_stats()`]
+    pub fn storage_stats(&mut self) -> StorageStats {
+        self.ensure_transaction_closed();
+        self.doc.storage_stats()
+    }
+
+    /// See [`Automerge::storage_stats_with_options()`]
+    pub fn storage_stats_with_options(&mut self, options: SaveOptions) -> StorageStats {
+        self.ensure_transaction_closed();
+        self.doc.storage_stats_with_options(options)
+    }
+
+    /// Save this document, but don't compress it afterwards
     pub fn save_nocompress(&mut self) -> Vec<u8> {
         self.save_with_options(SaveOptions {
-            deflate: false,
+            compression: Compression::None,
             ..Default::default()
         })
     }
@@ -438,6 +635,12 @@ impl AutoCommit {
         self.doc.save_after(heads)
     }
 
+    /// See [`Automerge::try_save_after()`]
+    pub fn try_save_after(&mut self, heads: &[ChangeHash]) -> Result<Vec<u8>, ChangeStoreError> {
+        self.ensure_transaction_closed();
+        self.doc.try_save_after(heads)
+    }
+
     pub fn get_missing_deps(&mut self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
         self.ensure_transaction_closed();
         self.doc.get_missing_deps(heads)
@@ -454,6 +657,90 @@ impl AutoCommit {
         self.doc.get_changes(have_deps)
     }
 
+    /// See [`Automerge::try_get_changes()`]
+    pub fn try_get_changes(
+        &mut self,
+        have_deps: &[ChangeHash],
+    ) -> Result<Vec<&Change>, ChangeStoreError> {
+        self.ensure_transaction_closed();
+        self.doc.try_get_changes(have_deps)
+    }
+
+    /// See [`Automerge::heads_at_time()`]
+    pub fn heads_at_time(&mut self, timestamp: i64) -> Vec<ChangeHash> {
+        self.ensure_transaction_closed();
+        self.doc.heads_at_time(timestamp)
+    }
+
+    /// See [`Automerge::view_at_time()`]
+    pub fn view_at_time(&mut self, timestamp: i64) -> ReadDocAt<'_, 'static> {
+        self.ensure_transaction_closed();
+        self.doc.view_at_time(timestamp)
+    }
+
+    /// See [`Automerge::view_at()`]
+    pub fn view_at(&mut self, heads: &[ChangeHash]) -> ReadDocAt<'_, 'static> {
+        self.ensure_transaction_closed();
+        self.doc.view_at(heads)
+    }
+
+    /// See [`Automerge::iter_history()`]
+    pub fn iter_history(&mut self) -> HistoryIter<'_> {
+        self.ensure_transaction_closed();
+        self.doc.iter_history()
+    }
+
+    /// See [`Automerge::is_ancestor()`]
+    pub fn is_ancestor(
+        &mut self,
+        ancestor: &ChangeHash,
+        descendant: &ChangeHash,
+    ) -> Result<bool, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.is_ancestor(ancestor, descendant)
+    }
+
+    /// See [`Automerge::merge_base()`]
+    pub fn merge_base(
+        &mut self,
+        heads_a: &[ChangeHash],
+        heads_b: &[ChangeHash],
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.merge_base(heads_a, heads_b)
+    }
+
+    /// See [`Automerge::changes_between()`]
+    pub fn changes_between(
+        &mut self,
+        from_heads: &[ChangeHash],
+        to_heads: &[ChangeHash],
+    ) -> Result<Vec<&Change>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.changes_between(from_heads, to_heads)
+    }
+
+    /// See [`Automerge::topo_changes()`]
+    pub fn topo_changes(&mut self) -> impl Iterator<Item = &Change> {
+        self.ensure_transaction_closed();
+        self.doc.topo_changes()
+    }
+
+    /// See [`Automerge::compare_heads()`]
+    pub fn compare_heads(
+        &mut self,
+        heads_a: &[ChangeHash],
+        heads_b: &[ChangeHash],
+    ) -> Result<CausalOrder, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.compare_heads(heads_a, heads_b)
+    }
+
+    /// See [`Automerge::vector_clock()`]
+    pub fn vector_clock(&self, heads: &[ChangeHash]) -> VectorClock {
+        self.doc.vector_clock(heads)
+    }
+
     pub fn get_change_by_hash(&mut self, hash: &ChangeHash) -> Option<&Change> {
         self.ensure_transaction_closed();
         self.doc.get_change_by_hash(hash)
@@ -632,21 +919,22 @@ impl ReadDoc for AutoCommit {
         self.doc.parents_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn parents_at<O: AsRef<ExId>>(
+    fn parents_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Parents<'_>, AutomergeError> {
         self.doc
-            .parents_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .parents_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn keys<O: AsRef<ExId>>(&self, obj: O) -> Keys<'_> {
         self.doc.keys_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn keys_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Keys<'_> {
-        self.doc.keys_for(obj.as_ref(), self.get_scope(Some(heads)))
+    fn keys_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Keys<'_> {
+        self.doc
+            .keys_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn map_range<'a, O: AsRef<ExId>, R: RangeBounds<String> + 'a>(
@@ -658,14 +946,17 @@ impl ReadDoc for AutoCommit {
             .map_range_for(obj.as_ref(), range, self.get_scope(None))
     }
 
-    fn map_range_at<'a, O: AsRef<ExId>, R: RangeBounds<String> + 'a>(
+    fn map_range_at<'a, 'h, O: AsRef<ExId>, R: RangeBounds<String> + 'a, H: Into<ReadAt<'h>>>(
         &'a self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> MapRange<'a, R> {
-        self.doc
-            .map_range_for(obj.as_ref(), range, self.get_scope(Some(heads)))
+        self.doc.map_range_for(
+            obj.as_ref(),
+            range,
+            Some(self.doc.clock_for_read(heads.into())),
+        )
     }
 
     fn list_range<O: AsRef<ExId>, R: RangeBounds<usize>>(
@@ -677,32 +968,35 @@ impl ReadDoc for AutoCommit {
             .list_range_for(obj.as_ref(), range, self.get_scope(None))
     }
 
-    fn list_range_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+    fn list_range_at<'h, O: AsRef<ExId>, R: RangeBounds<usize>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> ListRange<'_, R> {
-        self.doc
-            .list_range_for(obj.as_ref(), range, self.get_scope(Some(heads)))
+        self.doc.list_range_for(
+            obj.as_ref(),
+            range,
+            Some(self.doc.clock_for_read(heads.into())),
+        )
     }
 
     fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
         self.doc.values_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn values_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Values<'_> {
+    fn values_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Values<'_> {
         self.doc
-            .values_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .values_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
         self.doc.length_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize {
+    fn length_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> usize {
         self.doc
-            .length_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .length_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn object_type<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjType, AutomergeError> {
@@ -713,13 +1007,13 @@ impl ReadDoc for AutoCommit {
         self.doc.marks_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn marks_at<O: AsRef<ExId>>(
+    fn marks_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<Mark<'_>>, AutomergeError> {
         self.doc
-            .marks_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .marks_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn get_marks<O: AsRef<ExId>>(
@@ -736,25 +1030,26 @@ impl ReadDoc for AutoCommit {
         self.doc.text_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn text_at<O: AsRef<ExId>>(
+    fn text_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<String, AutomergeError> {
-        self.doc.text_for(obj.as_ref(), self.get_scope(Some(heads)))
+        self.doc
+            .text_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
         self.doc.spans_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn spans_at<O: AsRef<ExId>>(
+    fn spans_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Spans<'_>, AutomergeError> {
         self.doc
-            .spans_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .spans_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn get_cursor<O: AsRef<ExId>>(
@@ -794,14 +1089,17 @@ impl ReadDoc for AutoCommit {
             .get_for(obj.as_ref(), prop.into(), self.get_scope(None))
     }
 
-    fn get_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
-        self.doc
-            .get_for(obj.as_ref(), prop.into(), self.get_scope(Some(heads)))
+        self.doc.get_for(
+            obj.as_ref(),
+            prop.into(),
+            Some(self.doc.clock_for_read(heads.into())),
+        )
     }
 
     fn get_all<O: AsRef<ExId>, P: Into<Prop>>(
@@ -813,14 +1111,17 @@ impl ReadDoc for AutoCommit {
             .get_all_for(obj.as_ref(), prop.into(), self.get_scope(None))
     }
 
-    fn get_all_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_all_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError> {
-        self.doc
-            .get_all_for(obj.as_ref(), prop.into(), self.get_scope(Some(heads)))
+        self.doc.get_all_for(
+            obj.as_ref(),
+            prop.into(),
+            Some(self.doc.clock_for_read(heads.into())),
+        )
     }
 
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
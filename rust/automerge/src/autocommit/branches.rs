@@ -0,0 +1,265 @@
+//! Named branches of an [`AutoCommit`] document
+//!
+//! A branch is a name for a set of heads. Checking out a branch isolates the document at the heads
+//! of the branch (see [`AutoCommit::isolate()`]) and every commit made while the branch is checked
+//! out moves the branch forward, so commits on one branch are not visible on any other branch
+//! until the branches are merged. Outside of any branch the document shows every change.
+use std::collections::BTreeMap;
+
+use crate::error::BranchError;
+use crate::storage::versioned;
+use crate::{AutoCommit, ChangeHash};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Branches {
+    heads: BTreeMap<String, Vec<ChangeHash>>,
+    /// The branch which is checked out, its heads are the isolation heads of the document
+    current: Option<String>,
+}
+
+impl Branches {
+    pub(super) fn heads(&self) -> impl Iterator<Item = &Vec<ChangeHash>> {
+        self.heads.values()
+    }
+
+    pub(super) fn heads_mut(&mut self) -> impl Iterator<Item = &mut Vec<ChangeHash>> {
+        self.heads.values_mut()
+    }
+}
+
+impl AutoCommit {
+    /// Create a branch called `name` starting at `heads`
+    ///
+    /// # Errors
+    ///
+    /// [`BranchError::BranchExists`] if there is already a branch called `name` and
+    /// [`BranchError::Automerge`] if any of the heads are not in the document
+    pub fn create_branch(&mut self, name: &str, heads: &[ChangeHash]) -> Result<(), BranchError> {
+        self.ensure_transaction_closed();
+        if self.branches.heads.contains_key(name) {
+            return Err(BranchError::BranchExists(name.to_string()));
+        }
+        self.doc.check_hashes(heads)?;
+        self.branches.heads.insert(name.to_string(), heads.to_vec());
+        Ok(())
+    }
+
+    /// Delete the branch called `name`, the changes on it stay in the document
+    ///
+    /// # Errors
+    ///
+    /// [`BranchError::NoSuchBranch`] if there is no branch called `name` and
+    /// [`BranchError::CurrentBranch`] if the branch is checked out
+    pub fn delete_branch(&mut self, name: &str) -> Result<(), BranchError> {
+        if self.branches.current.as_deref() == Some(name) {
+            return Err(BranchError::CurrentBranch(name.to_string()));
+        }
+        self.branches
+            .heads
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| BranchError::NoSuchBranch(name.to_string()))
+    }
+
+    /// Isolate the document at the heads of the branch called `name`
+    ///
+    /// Any open transaction is committed to the branch which was checked out before. Patches for
+    /// the difference between the previous state of the document and the branch are logged as
+    /// for [`Self::isolate()`].
+    pub fn checkout_branch(&mut self, name: &str) -> Result<(), BranchError> {
+        self.ensure_transaction_closed();
+        self.record_current_branch();
+        let heads = self
+            .branches
+            .heads
+            .get(name)
+            .cloned()
+            .ok_or_else(|| BranchError::NoSuchBranch(name.to_string()))?;
+        self.patch_to(&heads);
+        self.isolation = Some(heads);
+        self.branches.current = Some(name.to_string());
+        Ok(())
+    }
+
+    /// The name of the branch which is checked out, if any
+    pub fn current_branch(&self) -> Option<&str> {
+        self.branches.current.as_deref()
+    }
+
+    /// The names and heads of every branch, in order of name
+    pub fn branches(&mut self) -> Vec<(String, Vec<ChangeHash>)> {
+        self.ensure_transaction_closed();
+        self.record_current_branch();
+        self.branches
+            .heads
+            .iter()
+            .map(|(name, heads)| (name.clone(), heads.clone()))
+            .collect()
+    }
+
+    /// The heads of the branch called `name`
+    pub fn branch_heads(&mut self, name: &str) -> Option<Vec<ChangeHash>> {
+        self.ensure_transaction_closed();
+        self.record_current_branch();
+        self.branches.heads.get(name).cloned()
+    }
+
+    /// Merge the branch called `from` into the branch called `into`
+    ///
+    /// Afterwards `into` contains every change on either branch while `from` is unchanged. If
+    /// `into` is checked out the patches for the merged changes are logged. Returns the new heads
+    /// of `into`.
+    pub fn merge_branch(&mut self, from: &str, into: &str) -> Result<Vec<ChangeHash>, BranchError> {
+        self.ensure_transaction_closed();
+        self.record_current_branch();
+        let from_heads = self
+            .branches
+            .heads
+            .get(from)
+            .ok_or_else(|| BranchError::NoSuchBranch(from.to_string()))?;
+        let into_heads = self
+            .branches
+            .heads
+            .get(into)
+            .ok_or_else(|| BranchError::NoSuchBranch(into.to_string()))?;
+        let mut candidates = from_heads.clone();
+        candidates.extend(into_heads.iter().copied());
+        candidates.sort_unstable();
+        candidates.dedup();
+        let mut heads = Vec::with_capacity(candidates.len());
+        for head in &candidates {
+            let mut covered = false;
+            for other in &candidates {
+                if other != head && self.doc.is_ancestor(head, other)? {
+                    covered = true;
+                    break;
+                }
+            }
+            if !covered {
+                heads.push(*head);
+            }
+        }
+        if self.branches.current.as_deref() == Some(into) {
+            self.patch_to(&heads);
+            self.isolation = Some(heads.clone());
+        }
+        self.branches.heads.insert(into.to_string(), heads.clone());
+        Ok(heads)
+    }
+
+    /// Encode the branches so they can be stored alongside the output of [`Self::save()`] and
+    /// restored with [`Self::load_branches()`]
+    ///
+    /// Which branch is checked out is not saved.
+    pub fn save_branches(&mut self) -> Vec<u8> {
+        // The number of branches followed by the branches, each branch is its UTF-8 name, the
+        // number of heads and the heads
+        let branches = self.branches();
+        let mut encoder = versioned::Encoder::new();
+        encoder.uleb(branches.len() as u64);
+        for (name, heads) in branches {
+            encoder.len_prefixed(name.as_bytes());
+            encoder.uleb(heads.len() as u64);
+            for head in heads {
+                encoder.hash(&head);
+            }
+        }
+        encoder.finish()
+    }
+
+    /// Restore branches encoded by [`Self::save_branches()`]
+    ///
+    /// Branches with the same name as an existing branch replace it, unless the existing branch is
+    /// checked out.
+    ///
+    /// # Errors
+    ///
+    /// [`BranchError::Load`] if the data is not valid, [`BranchError::CurrentBranch`] if it
+    /// contains the branch which is checked out and [`BranchError::Automerge`] if any of the heads
+    /// are not in the document. Nothing is restored if there is an error.
+    pub fn load_branches(&mut self, data: &[u8]) -> Result<(), BranchError> {
+        self.ensure_transaction_closed();
+        let branches = decode(data)?;
+        for (name, heads) in &branches {
+            if self.branches.current.as_ref() == Some(name) {
+                return Err(BranchError::CurrentBranch(name.clone()));
+            }
+            self.doc.check_hashes(heads)?;
+        }
+        self.branches.heads.extend(branches);
+        Ok(())
+    }
+
+    /// Record the isolation heads, which commits on the checked out branch have moved forward, as
+    /// the heads of the branch
+    pub(super) fn record_current_branch(&mut self) {
+        if let (Some(name), Some(heads)) = (&self.branches.current, &self.isolation) {
+            self.branches.heads.insert(name.clone(), heads.clone());
+        }
+    }
+
+    /// Forget which branch is checked out, called when the isolation heads are set some other way
+    pub(super) fn leave_branch(&mut self) {
+        self.record_current_branch();
+        self.branches.current = None;
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BranchesFromBytesError {
+    #[error("no version tag")]
+    NoVersion,
+    #[error("invalid version tag")]
+    InvalidVersion(u8),
+    #[error("invalid number of branches: {0}")]
+    ParseNumBranches(String),
+    #[error("invalid branch name length: {0}")]
+    ParseNameLen(String),
+    #[error("invalid branch name: {0}")]
+    ParseName(String),
+    #[error("invalid number of heads: {0}")]
+    ParseNumHeads(String),
+    #[error("not enough bytes in change hash")]
+    ParseHead,
+    #[error("trailing bytes")]
+    TrailingBytes,
+}
+
+impl From<versioned::VersionError> for BranchesFromBytesError {
+    fn from(e: versioned::VersionError) -> Self {
+        match e {
+            versioned::VersionError::NoVersion => Self::NoVersion,
+            versioned::VersionError::InvalidVersion(v) => Self::InvalidVersion(v),
+        }
+    }
+}
+
+fn decode(data: &[u8]) -> Result<Vec<(String, Vec<ChangeHash>)>, BranchesFromBytesError> {
+    let mut decoder = versioned::Decoder::new(data)?;
+    let count = decoder
+        .uleb()
+        .map_err(|e| BranchesFromBytesError::ParseNumBranches(e.to_string()))?;
+    let mut branches = Vec::new();
+    for _ in 0..count {
+        let name_len = decoder
+            .uleb()
+            .map_err(|e| BranchesFromBytesError::ParseNameLen(e.to_string()))?;
+        let name = decoder
+            .take(name_len)
+            .ok_or_else(|| BranchesFromBytesError::ParseName("not enough data".to_string()))?;
+        let name = std::str::from_utf8(name)
+            .map_err(|e| BranchesFromBytesError::ParseName(e.to_string()))?;
+        let num_heads = decoder
+            .uleb()
+            .map_err(|e| BranchesFromBytesError::ParseNumHeads(e.to_string()))?;
+        let mut heads = Vec::new();
+        for _ in 0..num_heads {
+            heads.push(decoder.hash().ok_or(BranchesFromBytesError::ParseHead)?);
+        }
+        branches.push((name.to_string(), heads));
+    }
+    if !decoder.is_empty() {
+        return Err(BranchesFromBytesError::TrailingBytes);
+    }
+    Ok(branches)
+}
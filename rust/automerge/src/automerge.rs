@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::Debug;
@@ -6,17 +7,23 @@ use std::ops::RangeBounds;
 
 use itertools::Itertools;
 
-use crate::change_graph::ChangeGraph;
+use crate::change_graph::{CausalOrder, ChangeGraph};
+use crate::change_store::{AddedChangeStore, ChangeStore, ChangeStoreError, History};
 use crate::columnar::Key as EncodedKey;
+use crate::error::{SquashError, UpdateObjectError};
 use crate::exid::ExId;
+use crate::history::HistoryIter;
 use crate::iter::{Keys, ListRange, MapRange, Spans, Values};
+use crate::legacy;
 use crate::marks::{Mark, MarkAccumulator, MarkSet, MarkStateMachine};
 use crate::op_set::{OpSet, OpSetData};
 use crate::parents::Parents;
-use crate::patches::{Patch, PatchLog, TextRepresentation};
+use crate::patches::{Patch, PatchAction, PatchLog, TextRepresentation};
 use crate::query;
 use crate::read::ReadDocInternal;
-use crate::storage::{self, load, CompressConfig, VerificationMode};
+use crate::storage::{self, load, RepairReport, VerificationMode};
+use crate::storage_stats::{ColumnStats, ObjectStats, OpStats, StorageStats};
+use crate::sync;
 use crate::transaction::{
     self, CommitOptions, Failure, Success, Transactable, Transaction, TransactionArgs,
 };
@@ -25,7 +32,10 @@ use crate::types::{
     ObjMeta, OpBuilder, OpId, OpIds, OpType, Value,
 };
 use crate::{hydrate, ScalarValue};
-use crate::{AutomergeError, Change, Cursor, ObjType, Prop, ReadDoc};
+use crate::{
+    AutomergeError, Change, Compression, Cursor, MergeConflict, MergePreview, ObjType, Prop,
+    ReadAt, ReadDoc, ReadDocAt, RedactCandidate, RedactionRecord, VectorClock,
+};
 
 pub(crate) mod current_state;
 pub(crate) mod diff;
@@ -63,6 +73,8 @@ pub struct LoadOptions<'a> {
     verification_mode: VerificationMode,
     string_migration: StringMigration,
     patch_log: Option<&'a mut PatchLog>,
+    lazy: bool,
+    change_store: Option<History>,
 }
 
 impl<'a> LoadOptions<'a> {
@@ -123,6 +135,38 @@ impl<'a> LoadOptions<'a> {
             ..self
         }
     }
+
+    /// Whether to defer building the internal index of each object until it is first read
+    ///
+    /// Loading a document chunk decodes every operation and then builds an index for each object
+    /// in the document. For large documents of which only a few objects are read the latter can
+    /// dominate the time taken to load. When this is `true` each object is indexed the first
+    /// time it is accessed instead, which is transparent to the [`ReadDoc`] methods. Use
+    /// [`Automerge::materialize_all()`] to index every remaining object up front.
+    ///
+    /// Only the initial document chunk is loaded lazily, any changes which follow it are
+    /// applied as normal. Logging patches or migrating strings reads the whole document and so
+    /// indexes every object.
+    ///
+    /// The default is `false`
+    pub fn lazy(self, lazy: bool) -> Self {
+        Self { lazy, ..self }
+    }
+
+    /// The [`ChangeStore`] to keep the history of the loaded document in
+    ///
+    /// Each change is pushed into `store` as soon as it has been decoded, so unlike loading the
+    /// document and then calling [`Automerge::with_change_store()`] the whole history is never
+    /// held in memory at once (unless `store` keeps it there). Loading fails with
+    /// [`ChangeStoreError::NotEmpty`] if `store` is not empty.
+    ///
+    /// The default is a [`MemoryChangeStore`](crate::change_store::MemoryChangeStore)
+    pub fn change_store<S: ChangeStore + 'static>(self, store: S) -> Self {
+        Self {
+            change_store: Some(History::new(store)),
+            ..self
+        }
+    }
 }
 
 impl std::default::Default for LoadOptions<'static> {
@@ -132,6 +176,8 @@ impl std::default::Default for LoadOptions<'static> {
             verification_mode: VerificationMode::Check,
             patch_log: None,
             string_migration: StringMigration::NoMigration,
+            lazy: false,
+            change_store: None,
         }
     }
 }
@@ -169,7 +215,7 @@ pub struct Automerge {
     /// The list of unapplied changes that are not causally ready.
     queue: Vec<Change>,
     /// The history of changes that form this document, topologically sorted too.
-    history: Vec<Change>,
+    history: History,
     /// Mapping from change hash to index into the history list.
     history_index: HashMap<ChangeHash, usize>,
     /// Graph of changes
@@ -184,6 +230,9 @@ pub struct Automerge {
     actor: Actor,
     /// The maximum operation counter this document has seen.
     max_op: u64,
+    /// The hashes of changes which a redaction replaced, mapped to the hashes of their
+    /// replacements.
+    replaced: HashMap<ChangeHash, ChangeHash>,
 }
 
 impl Automerge {
@@ -191,7 +240,7 @@ impl Automerge {
     pub fn new() -> Self {
         Automerge {
             queue: vec![],
-            history: vec![],
+            history: History::default(),
             history_index: HashMap::new(),
             change_graph: ChangeGraph::new(),
             states: HashMap::new(),
@@ -199,6 +248,7 @@ impl Automerge {
             deps: Default::default(),
             actor: Actor::Unused(ActorId::random()),
             max_op: 0,
+            replaced: HashMap::new(),
         }
     }
 
@@ -238,6 +288,60 @@ impl Automerge {
         self
     }
 
+    /// Store the history of this document in `store`
+    ///
+    /// See [`Self::set_change_store()`]
+    pub fn with_change_store<S: ChangeStore + 'static>(
+        mut self,
+        store: S,
+    ) -> Result<Self, ChangeStoreError> {
+        self.set_change_store(store)?;
+        Ok(self)
+    }
+
+    /// Store the history of this document in `store`
+    ///
+    /// Every change in the document is moved to `store`. See the
+    /// [`change_store`](crate::change_store) module for details.
+    ///
+    /// # Errors
+    ///
+    /// [`ChangeStoreError::NotEmpty`] if `store` already contains changes, in which case the
+    /// document keeps its current store.
+    ///
+    /// # Panics
+    ///
+    /// Methods which read the history of the document and cannot return an error, such as
+    /// [`Self::get_changes()`] or [`Self::save()`], panic if `store` fails to read a change back.
+    pub fn set_change_store<S: ChangeStore + 'static>(
+        &mut self,
+        store: S,
+    ) -> Result<&mut Self, ChangeStoreError> {
+        if !store.is_empty() {
+            return Err(ChangeStoreError::NotEmpty);
+        }
+        let mut store = History::new(store);
+        for change in self.history.iter() {
+            store.push(change.clone());
+        }
+        self.history = store;
+        Ok(self)
+    }
+
+    /// Drop any changes the change store holds in memory which it can read again later
+    ///
+    /// This does nothing for the default in memory change store.
+    pub fn release_cached_changes(&mut self) {
+        self.history.release();
+    }
+
+    /// Index every object which has not been accessed since the document was loaded lazily
+    ///
+    /// See [`LoadOptions::lazy()`]. This does nothing for documents which were not loaded lazily.
+    pub fn materialize_all(&mut self) {
+        self.ops.materialize_all();
+    }
+
     /// Get the current actor id of this document.
     pub fn get_actor(&self) -> &ActorId {
         match &self.actor {
@@ -459,7 +563,10 @@ impl Automerge {
         let mut changes = vec![];
         while let Some(hash) = heads.pop() {
             if let Some(idx) = self.history_index.get(&hash) {
-                let change = &self.history[*idx];
+                let change = self
+                    .history
+                    .get(*idx)
+                    .expect("history index should be valid");
                 for dep in change.deps() {
                     if !seen.contains(dep) {
                         heads.push(*dep);
@@ -477,6 +584,121 @@ impl Automerge {
         Ok(f)
     }
 
+    /// Rewrite the local changes made since `since_heads` into a single change
+    ///
+    /// Typing produces lots of tiny changes, each of which has its own header and hash. Squashing
+    /// them before they are sent anywhere keeps the history and the sync traffic small. Every
+    /// change since `since_heads` must have been made by the actor of this document, each one on
+    /// top of the one before, and none of them can have been seen by any of `peers`, which means
+    /// they are not in [`sync::State::sent_hashes`] and not ancestors of
+    /// [`sync::State::shared_heads`]. Changes which have been saved somewhere must not be squashed
+    /// either.
+    ///
+    /// The squashed change contains the operations of all of the changes with the same IDs, so
+    /// the document is unchanged apart from its history. It has the timestamp of the last change
+    /// and the messages of all of the changes joined with newlines. The document keeps its
+    /// [change store](crate::change_store), the squashed changes are removed from it with
+    /// [`ChangeStore::truncate()`].
+    ///
+    /// Returns the hash of the squashed change, or `None` if there were fewer than two changes to
+    /// squash.
+    ///
+    /// # Errors
+    ///
+    /// * [`SquashError::NotLocal`] if a change was made by another actor
+    /// * [`SquashError::Shared`] if a change has been seen by one of `peers`
+    /// * [`SquashError::Concurrent`] if a change depends on changes which are concurrent with the
+    ///   changes being squashed
+    /// * [`SquashError::Automerge`] if any of `since_heads` are not in the document
+    pub fn squash_local<'a, I: IntoIterator<Item = &'a sync::State>>(
+        &mut self,
+        since_heads: &[ChangeHash],
+        peers: I,
+    ) -> Result<Option<ChangeHash>, SquashError> {
+        self.squash_local_pinned(since_heads, peers, Vec::new())
+    }
+
+    /// As [`Self::squash_local()`] but `pinned` are hashes of changes which are shared as well
+    pub(crate) fn squash_local_pinned<'a, I: IntoIterator<Item = &'a sync::State>>(
+        &mut self,
+        since_heads: &[ChangeHash],
+        peers: I,
+        mut pinned: Vec<ChangeHash>,
+    ) -> Result<Option<ChangeHash>, SquashError> {
+        self.check_hashes(since_heads)?;
+        let changes = self.get_changes(since_heads);
+        if changes.len() < 2 {
+            return Ok(None);
+        }
+
+        // Every descendant of a squashed change is squashed as well, so anything a peer has seen
+        // which depends on a squashed change is itself in the squashed set
+        for state in peers {
+            pinned.extend(state.sent_hashes.iter().copied());
+            pinned.extend(state.shared_heads.iter().copied());
+        }
+        let squashed = changes.iter().map(|c| c.hash()).collect::<HashSet<_>>();
+        if let Some(hash) = pinned.iter().find(|h| squashed.contains(h)) {
+            return Err(SquashError::Shared(*hash));
+        }
+
+        let actor = self.get_actor().clone();
+        let mut previous: Option<ChangeHash> = None;
+        let mut combined: Option<crate::ExpandedChange> = None;
+        let mut messages = Vec::new();
+        for change in changes {
+            if change.actor_id() != &actor {
+                return Err(SquashError::NotLocal(change.hash()));
+            }
+            if let Some(previous) = previous {
+                // As nothing else was applied in between the op IDs carry on from the previous
+                // change so the operations can simply be concatenated
+                if change.deps() != [previous] {
+                    return Err(SquashError::Concurrent(change.hash()));
+                }
+            }
+            previous = Some(change.hash());
+            let mut expanded = change.decode();
+            messages.extend(expanded.message.take());
+            match &mut combined {
+                None => combined = Some(expanded),
+                Some(combined) => {
+                    combined.operations.extend(expanded.operations);
+                    combined.time = expanded.time;
+                }
+            }
+        }
+        let mut combined = combined.expect("there are at least two changes");
+        combined.hash = None;
+        if !messages.is_empty() {
+            combined.message = Some(messages.join("\n"));
+        }
+        let change = Change::from(combined);
+        let hash = change.hash();
+
+        let mut doc = self.fork_at(since_heads)?;
+        doc.set_actor(actor);
+        doc.apply_changes([change])?;
+        doc.queue = std::mem::take(&mut self.queue);
+        doc.replaced = std::mem::take(&mut self.replaced);
+
+        // Move the change store over, only rewriting the part of the history which changed
+        let kept = self
+            .history
+            .iter()
+            .zip(doc.history.iter())
+            .take_while(|(old, new)| old.hash() == new.hash())
+            .count();
+        let mut history = std::mem::take(&mut self.history);
+        history.truncate(kept);
+        for change in doc.history.iter().skip(kept) {
+            history.push(change.clone());
+        }
+        doc.history = history;
+        *self = doc;
+        Ok(Some(hash))
+    }
+
     pub(crate) fn exid_to_opid(&self, id: &ExId) -> Result<OpId, AutomergeError> {
         match id {
             ExId::Root => Ok(OpId::new(0, 0)),
@@ -585,13 +807,97 @@ impl Automerge {
         data: &'a [u8],
         options: LoadOptions<'b>,
     ) -> Result<Self, AutomergeError> {
+        Self::load_internal(data, options, None)
+    }
+
+    /// Load a document from a buffer of change chunks which the document keeps a reference to
+    ///
+    /// This only loads change chunks, i.e. the concatenated changes produced by e.g.
+    /// [`Self::save_after()`] or a [`FileChangeStore`](crate::change_store::FileChangeStore), not
+    /// the output of [`Self::save()`]. The raw bytes of the changes in `data` are shared with the
+    /// loaded document rather than copied, which saves time and memory for long histories. `data`
+    /// can be anything which dereferences to bytes: a `Vec<u8>`, a `&'static [u8]` or a memory
+    /// mapped file. It is dropped once the document and every [`Change`] loaded from it have been
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// The changes in a document chunk have to be reconstructed from its columns and the ops
+    /// are decoded into the document, so nothing from the chunk could be shared with `data`.
+    /// Loading a buffer which contains a document chunk this way fails with
+    /// [`AutomergeError::Load`], use [`Self::load()`] for those.
+    pub fn load_changes_shared<B: AsRef<[u8]> + Send + Sync + 'static>(
+        data: B,
+    ) -> Result<Self, AutomergeError> {
+        Self::load_changes_shared_with_options(data, Default::default())
+    }
+
+    /// Like [`Self::load_changes_shared()`] but with options
+    #[tracing::instrument(skip(data), err)]
+    pub fn load_changes_shared_with_options<B: AsRef<[u8]> + Send + Sync + 'static>(
+        data: B,
+        options: LoadOptions<'_>,
+    ) -> Result<Self, AutomergeError> {
+        let backing = storage::Backing::new(data);
+        Self::load_internal(backing.bytes(), options, Some(&backing))
+    }
+
+    /// Recover as much as possible from damaged data
+    ///
+    /// Unlike [`Self::load_with_options()`] with [`OnPartialLoad::Ignore`], which stops at the
+    /// first chunk it can't load, this scans all of `data` for valid chunks, including chunks
+    /// which follow corrupt regions. Change chunks are recovered if their checksum is valid and
+    /// document chunks are recovered if their columns decode into changes whose hashes match the
+    /// heads recorded in the chunk. The returned [`RepairReport`] lists the recovered changes and
+    /// the changes which are known to have been lost.
+    pub fn repair(data: &[u8]) -> (Self, RepairReport) {
+        let salvaged = load::repair::salvage(data);
+        let mut doc = Self::new();
+        let mut seen = HashSet::new();
+        let mut rejected = Vec::new();
+        for change in salvaged.changes {
+            let hash = change.hash();
+            if !seen.insert(hash) {
+                continue;
+            }
+            if let Err(e) = doc.apply_changes([change]) {
+                tracing::debug!(err=?e, ?hash, "unable to apply salvaged change");
+                rejected.push(hash);
+            }
+        }
+        let known = salvaged
+            .heads
+            .into_iter()
+            .chain(rejected)
+            .collect::<Vec<_>>();
+        let report = RepairReport {
+            recovered: doc.history.iter().map(|c| c.hash()).collect(),
+            orphaned: doc.queue.iter().map(|c| c.hash()).collect(),
+            lost: doc.get_missing_deps(&known),
+            corrupt: salvaged.corrupt,
+        };
+        (doc, report)
+    }
+
+    fn load_internal(
+        data: &[u8],
+        options: LoadOptions<'_>,
+        backing: Option<&storage::Backing>,
+    ) -> Result<Self, AutomergeError> {
+        let history = match options.change_store {
+            Some(store) if !store.is_empty() => return Err(ChangeStoreError::NotEmpty.into()),
+            store => store.unwrap_or_default(),
+        };
         if data.is_empty() {
             tracing::trace!("no data, initializing empty document");
-            return Ok(Self::new());
+            return Ok(Self {
+                history,
+                ..Self::new()
+            });
         }
         tracing::trace!("loading first chunk");
-        let (remaining, first_chunk) = storage::Chunk::parse(storage::parse::Input::new(data))
-            .map_err(|e| load::Error::Parse(Box::new(e)))?;
+        let (remaining, first_chunk) =
+            storage::Chunk::parse(storage::parse::Input::new(data)).map_err(load::Error::from)?;
         if !first_chunk.checksum_valid() {
             return Err(load::Error::BadChecksum.into());
         }
@@ -599,33 +905,42 @@ impl Automerge {
         let mut change: Option<Change> = None;
         let mut first_chunk_was_doc = false;
         let mut am = match first_chunk {
+            storage::Chunk::Document(_) if backing.is_some() => {
+                return Err(load::Error::SharedDocumentChunk.into());
+            }
             storage::Chunk::Document(d) => {
                 tracing::trace!("first chunk is document chunk, inflating");
                 first_chunk_was_doc = true;
-                reconstruct_document(&d, options.verification_mode)?
+                reconstruct_document(&d, options.verification_mode, options.lazy, history)?
             }
             storage::Chunk::Change(stored_change) => {
                 tracing::trace!("first chunk is change chunk");
                 change = Some(
-                    Change::new_from_unverified(stored_change.into_owned(), None)
+                    Change::new_from_unverified(stored_change.into_backed(backing), None)
                         .map_err(|e| load::Error::InvalidChangeColumns(Box::new(e)))?,
                 );
-                Self::new()
+                Self {
+                    history,
+                    ..Self::new()
+                }
             }
             storage::Chunk::CompressedChange(stored_change, compressed) => {
                 tracing::trace!("first chunk is compressed change");
                 change = Some(
                     Change::new_from_unverified(
-                        stored_change.into_owned(),
+                        stored_change.into_backed(backing),
                         Some(compressed.into_owned()),
                     )
                     .map_err(|e| load::Error::InvalidChangeColumns(Box::new(e)))?,
                 );
-                Self::new()
+                Self {
+                    history,
+                    ..Self::new()
+                }
             }
         };
         tracing::trace!("loading change chunks");
-        match load::load_changes(remaining.reset()) {
+        match load::load_changes(remaining.reset(), backing) {
             load::LoadedChanges::Complete(c) => {
                 am.apply_changes(change.into_iter().chain(c))?;
                 // Only allow missing deps if the first chunk was a document chunk
@@ -707,7 +1022,7 @@ impl Automerge {
             *self = doc;
             return Ok(self.ops.len());
         }
-        let changes = match load::load_changes(storage::parse::Input::new(data)) {
+        let changes = match load::load_changes(storage::parse::Input::new(data), None) {
             load::LoadedChanges::Complete(c) => c,
             load::LoadedChanges::Partial { error, loaded, .. } => {
                 tracing::warn!(successful_chunks=loaded.len(), err=?error, "partial load");
@@ -756,6 +1071,9 @@ impl Automerge {
         // empty document right now, once we have logic to produce the diffs between arbitrary
         // states of the OpSet we can make this cleaner.
         for c in changes {
+            let Some(c) = self.replace_redacted(c) else {
+                continue;
+            };
             if !self.history_index.contains_key(&c.hash()) {
                 if self.duplicate_seq(&c) {
                     return Err(AutomergeError::DuplicateSeqNumber(
@@ -778,6 +1096,45 @@ impl Automerge {
         Ok(())
     }
 
+    /// Drop `change` if a redaction this document knows about replaced it, or point it at the
+    /// replacements of its dependencies if a redaction replaced those
+    ///
+    /// The dependencies are rewritten the same way [`Self::apply_redaction()`] rewrites them, so
+    /// the result has the hash the sender will give the change once it applies the redaction.
+    fn replace_redacted(&mut self, change: Change) -> Option<Change> {
+        if self.replaced.contains_key(&change.hash()) {
+            return None;
+        }
+        if !change.deps().iter().any(|d| self.replaced.contains_key(d)) {
+            return Some(change);
+        }
+        let old_hash = change.hash();
+        let mut expanded = change.decode();
+        for dep in &mut expanded.deps {
+            if let Some(new_hash) = self.replaced.get(dep) {
+                *dep = *new_hash;
+            }
+        }
+        expanded.hash = None;
+        let change = Change::from(expanded);
+        self.replaced.insert(old_hash, change.hash());
+        Some(change)
+    }
+
+    /// Remember that the changes with the first hash of each pair were replaced by the changes
+    /// with the second
+    fn add_replaced(&mut self, hashes: impl IntoIterator<Item = (ChangeHash, ChangeHash)>) {
+        for (old, new) in hashes {
+            // An earlier redaction may have replaced a change with the one being replaced now
+            for replacement in self.replaced.values_mut() {
+                if *replacement == old {
+                    *replacement = new;
+                }
+            }
+            self.replaced.insert(old, new);
+        }
+    }
+
     fn apply_change(
         &mut self,
         change: Change,
@@ -891,21 +1248,34 @@ impl Automerge {
     }
 
     /// Save the entirety of this document in a compact form.
+    ///
+    /// # Panics
+    ///
+    /// If the [change store](crate::change_store) fails to read a change, use
+    /// [`Self::try_save_with_options()`] to handle that instead.
     pub fn save_with_options(&self, options: SaveOptions) -> Vec<u8> {
+        self.save_changes(self.history.iter(), options)
+    }
+
+    /// Like [`Self::save_with_options()`] but return an error if the
+    /// [change store](crate::change_store) fails to read a change
+    pub fn try_save_with_options(&self, options: SaveOptions) -> Result<Vec<u8>, ChangeStoreError> {
+        let changes = self.history.try_iter().collect::<Result<Vec<_>, _>>()?;
+        Ok(self.save_changes(changes.into_iter(), options))
+    }
+
+    fn save_changes<'a, I>(&'a self, changes: I, options: SaveOptions) -> Vec<u8>
+    where
+        I: Iterator<Item = &'a Change> + Clone + 'a,
+    {
         let heads = self.get_heads();
-        let c = self.history.iter();
-        let compress = if options.deflate {
-            None
-        } else {
-            Some(CompressConfig::None)
-        };
         let mut bytes = crate::storage::save::save_document(
-            c,
+            changes,
             self.ops.iter().map(|(objid, _, op)| (objid, op)),
             &self.ops.osd.actors,
             &self.ops.osd.props,
             &heads,
-            compress,
+            options.resolved_compression(),
         );
         if options.retain_orphans {
             for orphaned in self.queue.iter() {
@@ -915,11 +1285,79 @@ impl Automerge {
         bytes
     }
 
+    /// Report where the bytes go when this document is saved
+    ///
+    /// See the [`storage_stats`](crate::storage_stats) module for details.
+    pub fn storage_stats(&self) -> StorageStats {
+        self.storage_stats_with_options(SaveOptions::default())
+    }
+
+    /// Like [`Self::storage_stats()`] but for a document saved with `options`
+    pub fn storage_stats_with_options(&self, options: SaveOptions) -> StorageStats {
+        let heads = self.get_heads();
+        let stats = storage::save::document_stats(
+            self.history.iter(),
+            self.ops.iter().map(|(objid, _, op)| (objid, op)),
+            &self.ops.osd.actors,
+            &self.ops.osd.props,
+            &heads,
+            options.resolved_compression(),
+        );
+        let mut ops = OpStats::default();
+        let mut objects: Vec<ObjectStats> = Vec::with_capacity(stats.objects.len());
+        let mut sizes = stats.objects.into_iter();
+        let mut current = None;
+        for (objid, obj_type, op) in self.ops.iter() {
+            if current != Some(objid) {
+                current = Some(objid);
+                let (_, encoded_size) = sizes.next().unwrap_or_default();
+                objects.push(ObjectStats {
+                    obj: self.id_to_exid(objid.0),
+                    obj_type,
+                    ops: OpStats::default(),
+                    encoded_size,
+                });
+            }
+            ops.add(op);
+            if let Some(object) = objects.last_mut() {
+                object.ops.add(op);
+            }
+        }
+        let column_stats = |sizes: Vec<storage::ColumnSize>| {
+            sizes
+                .into_iter()
+                .map(|c| ColumnStats {
+                    name: c.name,
+                    raw_size: c.raw,
+                    compressed_size: c.compressed,
+                })
+                .collect()
+        };
+        StorageStats {
+            total_size: stats.total_size,
+            change_columns: column_stats(stats.change_columns),
+            op_columns: column_stats(stats.op_columns),
+            ops,
+            objects,
+        }
+    }
+
     /// Save the entirety of this document in a compact form.
+    ///
+    /// # Panics
+    ///
+    /// If the [change store](crate::change_store) fails to read a change, use
+    /// [`Self::try_save()`] to handle that instead.
     pub fn save(&self) -> Vec<u8> {
         self.save_with_options(SaveOptions::default())
     }
 
+    /// Like [`Self::save()`] but return an error if the [change store](crate::change_store) fails
+    /// to read a change
+    pub fn try_save(&self) -> Result<Vec<u8>, ChangeStoreError> {
+        self.try_save_with_options(SaveOptions::default())
+    }
+
     /// Save the document and attempt to load it before returning - slow!
     pub fn save_and_verify(&self) -> Result<Vec<u8>, AutomergeError> {
         let bytes = self.save();
@@ -927,10 +1365,10 @@ impl Automerge {
         Ok(bytes)
     }
 
-    /// Save this document, but don't run it through `DEFLATE` afterwards
+    /// Save this document, but don't compress it afterwards
     pub fn save_nocompress(&self) -> Vec<u8> {
         self.save_with_options(SaveOptions {
-            deflate: false,
+            compression: Compression::None,
             ..Default::default()
         })
     }
@@ -941,6 +1379,11 @@ impl Automerge {
     /// changes. This is useful if you know you have only made a small change since the last
     /// [`Self::save()`] and you want to immediately send it somewhere (e.g. you've inserted a
     /// single character in a text object).
+    ///
+    /// # Panics
+    ///
+    /// If the [change store](crate::change_store) fails to read a change, use
+    /// [`Self::try_save_after()`] to handle that instead.
     pub fn save_after(&self, heads: &[ChangeHash]) -> Vec<u8> {
         let changes = self.get_changes(heads);
         let mut bytes = vec![];
@@ -950,6 +1393,17 @@ impl Automerge {
         bytes
     }
 
+    /// Like [`Self::save_after()`] but return an error if the [change store](crate::change_store)
+    /// fails to read a change
+    pub fn try_save_after(&self, heads: &[ChangeHash]) -> Result<Vec<u8>, ChangeStoreError> {
+        let changes = self.try_get_changes(heads)?;
+        let mut bytes = vec![];
+        for c in changes {
+            bytes.extend(c.raw_bytes());
+        }
+        Ok(bytes)
+    }
+
     /// Filter the changes down to those that are not transitive dependencies of the heads.
     ///
     /// Thus a graph with these heads has not seen the remaining changes.
@@ -971,6 +1425,14 @@ impl Automerge {
 
     /// Get the changes since `have_deps` in this document using a clock internally.
     fn get_changes_clock(&self, have_deps: &[ChangeHash]) -> Vec<&Change> {
+        self.change_indexes_since(have_deps)
+            .into_iter()
+            .map(|i| self.history.get(i).expect("history index should be valid"))
+            .collect()
+    }
+
+    /// The indexes into the history of the changes since `have_deps`, in order
+    fn change_indexes_since(&self, have_deps: &[ChangeHash]) -> Vec<usize> {
         // get the clock for the given deps
         let clock = self.clock_at(have_deps);
 
@@ -990,11 +1452,7 @@ impl Automerge {
 
         // ensure the changes are still in sorted order
         change_indexes.sort_unstable();
-
         change_indexes
-            .into_iter()
-            .map(|i| &self.history[i])
-            .collect()
     }
 
     /// Get the last change this actor made to the document.
@@ -1010,6 +1468,20 @@ impl Automerge {
         self.change_graph.clock_for_heads(heads)
     }
 
+    pub(crate) fn clock_for_read(&self, at: ReadAt<'_>) -> Clock {
+        match at {
+            ReadAt::Heads(heads) => self.clock_at(heads),
+            ReadAt::Clock(clock) => clock.to_clock(&self.ops.osd.actors),
+        }
+    }
+
+    /// The [`VectorClock`] of the state of the document described by `heads`
+    ///
+    /// Hashes which are not in the document are ignored.
+    pub fn vector_clock(&self, heads: &[ChangeHash]) -> VectorClock {
+        VectorClock::from_clock(&self.clock_at(heads), &self.ops.osd.actors)
+    }
+
     fn get_isolated_actor_index(&mut self, level: usize) -> usize {
         if level == 0 {
             self.get_actor_index()
@@ -1051,7 +1523,7 @@ impl Automerge {
             .ok_or(AutomergeError::InvalidSeq(seq))
     }
 
-    fn max_op_for_actor(&mut self, actor_index: usize) -> u64 {
+    fn max_op_for_actor(&self, actor_index: usize) -> u64 {
         self.states
             .get(&actor_index)
             .and_then(|s| s.last())
@@ -1253,6 +1725,296 @@ impl Automerge {
         patch_log.make_patches(self)
     }
 
+    /// Create patches which turn the current state of this document into the current state of
+    /// `other`
+    ///
+    /// If the documents share any history then the changes `other` has which this document doesn't
+    /// are applied to a copy of this document and the patches are the [`Self::diff()`] between the
+    /// heads of the two documents. Otherwise the visible values of the documents are compared
+    /// structurally, in the same way as [`Transactable::update_object()`], so the patches refer to
+    /// newly created objects wherever `other` has an object this document doesn't.
+    ///
+    /// # Errors
+    ///
+    /// Any error from applying the changes of `other` to this document, for example if they each
+    /// have a different change with the same actor and sequence number.
+    pub fn diff_documents(
+        &self,
+        other: &Automerge,
+        text_rep: TextRepresentation,
+    ) -> Result<Vec<Patch>, AutomergeError> {
+        let (smaller, larger) = if self.history_index.len() < other.history_index.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let shared = smaller
+            .history_index
+            .keys()
+            .any(|hash| larger.history_index.contains_key(hash));
+        let mut merged = self.fork();
+        if shared {
+            let have_deps = self
+                .get_heads()
+                .into_iter()
+                .filter(|hash| other.history_index.contains_key(hash))
+                .collect::<Vec<_>>();
+            merged.apply_changes(other.get_changes(&have_deps).into_iter().cloned())?;
+            Ok(merged.diff(&self.get_heads(), &other.get_heads(), text_rep))
+        } else {
+            let target = other.hydrate(None);
+            let result = merged
+                .transact_and_log_patches(text_rep, |tx| tx.update_object(ExId::Root, &target));
+            match result {
+                Ok(mut success) => Ok(merged.make_patches(&mut success.patch_log)),
+                Err(failure) => match failure.error {
+                    UpdateObjectError::Automerge(e) => Err(e),
+                    UpdateObjectError::ChangeType => {
+                        unreachable!("the root of both documents is a map")
+                    }
+                },
+            }
+        }
+    }
+
+    /// Compute what merging `other` into this document would do without modifying this document
+    ///
+    /// The changes are applied to a copy of this document. The copy shares the op tree of every
+    /// object with this document until the changes modify the object, and it does not copy the
+    /// changes in the history of this document. The ops themselves and the index of change hashes
+    /// are still copied.
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [`Self::merge()`]
+    pub fn preview_merge(
+        &self,
+        other: &Automerge,
+        text_rep: TextRepresentation,
+    ) -> Result<MergePreview, AutomergeError> {
+        let have_deps = self
+            .get_heads()
+            .into_iter()
+            .filter(|hash| other.history_index.contains_key(hash))
+            .collect::<Vec<_>>();
+        let changes = other
+            .get_changes(&have_deps)
+            .into_iter()
+            .filter(|change| !self.history_index.contains_key(&change.hash()))
+            .cloned();
+        self.preview_apply_changes(changes, text_rep)
+    }
+
+    /// Like [`Self::preview_merge()`] but for applying `changes`, as [`Self::apply_changes()`] would
+    ///
+    /// Changes whose dependencies are missing are ignored.
+    pub fn preview_apply_changes(
+        &self,
+        changes: impl IntoIterator<Item = Change>,
+        text_rep: TextRepresentation,
+    ) -> Result<MergePreview, AutomergeError> {
+        let mut merged = self.preview_copy();
+        let mut patch_log = PatchLog::active(text_rep);
+        merged.apply_changes_log_patches(changes, &mut patch_log)?;
+        let patches = merged.make_patches(&mut patch_log);
+
+        let mut conflicts: Vec<MergeConflict> = Vec::new();
+        for patch in &patches {
+            let props = match &patch.action {
+                PatchAction::PutMap {
+                    key,
+                    conflict: true,
+                    ..
+                } => vec![Prop::Map(key.clone())],
+                PatchAction::PutSeq {
+                    index,
+                    conflict: true,
+                    ..
+                } => vec![Prop::Seq(*index)],
+                PatchAction::Conflict { prop } => vec![prop.clone()],
+                PatchAction::Insert { index, values } => values
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, _, conflict))| *conflict)
+                    .map(|(i, _)| Prop::Seq(index + i))
+                    .collect(),
+                _ => vec![],
+            };
+            for prop in props {
+                if conflicts
+                    .iter()
+                    .any(|c| c.obj == patch.obj && c.prop == prop)
+                {
+                    continue;
+                }
+                let values = merged
+                    .get_all(&patch.obj, prop.clone())?
+                    .into_iter()
+                    .map(|(value, id)| (value.into_owned(), id))
+                    .collect::<Vec<_>>();
+                // A conflict is new if one of the conflicting ops comes from the merged changes
+                if values.len() > 1 && values.iter().any(|(_, id)| !self.has_op(id)) {
+                    conflicts.push(MergeConflict {
+                        obj: patch.obj.clone(),
+                        prop,
+                        values,
+                    });
+                }
+            }
+        }
+        Ok(MergePreview { patches, conflicts })
+    }
+
+    /// A copy of this document for [`Self::preview_apply_changes()`] to apply changes to
+    ///
+    /// The op trees are shared with this document and the copy's history only holds the changes
+    /// applied to it, so the copy must not be used to read old changes.
+    fn preview_copy(&self) -> Self {
+        Automerge {
+            queue: self.queue.clone(),
+            history: History::new(AddedChangeStore::after(self.history.len())),
+            history_index: self.history_index.clone(),
+            change_graph: self.change_graph.clone(),
+            states: self.states.clone(),
+            deps: self.deps.clone(),
+            ops: self.ops.clone(),
+            actor: self.actor.clone(),
+            max_op: self.max_op,
+            replaced: self.replaced.clone(),
+        }
+    }
+
+    /// Whether the op `id` has been applied to this document
+    fn has_op(&self, id: &ExId) -> bool {
+        match self.exid_to_opid(id) {
+            Ok(opid) => opid.counter() <= self.max_op_for_actor(opid.actor()),
+            Err(_) => false,
+        }
+    }
+
+    /// Rewrite the history of this document with the values of the ops chosen by `select`
+    /// replaced by [`ScalarValue::redacted()`]
+    ///
+    /// `select` is called for every op in the history which sets a value other than a counter and
+    /// every op which begins a mark with a value other than null, so ops can be chosen by object,
+    /// key, mark name, op ID or value. The values are replaced in the changes
+    /// which contain the ops, which gives those changes and every change which depends on them a
+    /// new hash, and the new document is built from the rewritten changes so its ops store the
+    /// redacted values too. This document is left unchanged.
+    ///
+    /// Returns the new document and a [`RedactionRecord`] describing the redaction. Peers converge
+    /// on the redacted history by passing the record to [`Self::apply_redaction()`].
+    ///
+    /// The new document remembers which changes were replaced, so when a peer which still has
+    /// the original changes sends them (e.g. by syncing or [`Self::merge()`]) they are ignored,
+    /// and changes which depend on them are rewritten to depend on their replacements instead.
+    /// This is not saved, a document loaded from the output of [`Self::save()`] has to apply the
+    /// record again to remember it. The peer on the other hand can't apply the rewritten changes
+    /// until it has applied the record: they have the same actor and sequence numbers as the
+    /// changes they replace, so [`Self::apply_changes()`] and syncing fail with
+    /// [`AutomergeError::DuplicateSeqNumber`]. Send the record to every peer before syncing with
+    /// them.
+    pub fn redact<F>(&self, select: F) -> Result<(Automerge, RedactionRecord), AutomergeError>
+    where
+        F: FnMut(&RedactCandidate<'_>) -> bool,
+    {
+        self.rewrite_redacted(select)
+    }
+
+    /// Redact the ops which were redacted to produce `record`, see [`Self::redact()`]
+    ///
+    /// Rewriting is deterministic, so the changes in the record which this document has get the
+    /// same new hashes as they did in the document which was redacted. Changes this document has
+    /// which depend on them are rewritten as well. Like [`Self::redact()`], the new document
+    /// ignores the original changes if a peer sends them.
+    pub fn apply_redaction(&self, record: &RedactionRecord) -> Result<Automerge, AutomergeError> {
+        let ops = record.ops.iter().collect::<HashSet<_>>();
+        let (mut doc, _) = self.rewrite_redacted(|candidate| ops.contains(&candidate.id))?;
+        doc.add_replaced(record.hashes.iter().copied());
+        Ok(doc)
+    }
+
+    fn rewrite_redacted<F>(
+        &self,
+        mut select: F,
+    ) -> Result<(Automerge, RedactionRecord), AutomergeError>
+    where
+        F: FnMut(&RedactCandidate<'_>) -> bool,
+    {
+        let exid = |counter: u64, actor: &ActorId| {
+            let index = self
+                .ops
+                .osd
+                .actors
+                .lookup(actor)
+                .expect("the actors of every change are cached");
+            ExId::Id(counter, actor.clone(), index)
+        };
+        let mut record = RedactionRecord::default();
+        let mut new_hashes = HashMap::new();
+        let mut changes = Vec::with_capacity(self.history.len());
+        for change in self.history.iter() {
+            let mut expanded = change.decode();
+            let mut rewritten = false;
+            for dep in &mut expanded.deps {
+                if let Some(new_hash) = new_hashes.get(dep) {
+                    *dep = *new_hash;
+                    rewritten = true;
+                }
+            }
+            let start_op = expanded.start_op.get();
+            for (i, op) in expanded.operations.iter_mut().enumerate() {
+                let (value, mark) = match &mut op.action {
+                    legacy::OpType::Put(value) => (value, None),
+                    // A null mark value removes the mark so there is nothing to redact
+                    legacy::OpType::MarkBegin(legacy::MarkData { name, value, .. })
+                        if !value.is_null() =>
+                    {
+                        (value, Some(name.as_str()))
+                    }
+                    _ => continue,
+                };
+                if value.is_counter() || value.is_redacted() {
+                    continue;
+                }
+                let candidate = RedactCandidate {
+                    id: exid(start_op + i as u64, &expanded.actor_id),
+                    obj: match &op.obj {
+                        legacy::ObjectId::Root => ExId::Root,
+                        legacy::ObjectId::Id(legacy::OpId(counter, actor)) => exid(*counter, actor),
+                    },
+                    key: match &op.key {
+                        legacy::Key::Map(key) => Some(key.as_str()),
+                        legacy::Key::Seq(_) => None,
+                    },
+                    mark,
+                    value,
+                };
+                if select(&candidate) {
+                    record.ops.push(candidate.id);
+                    *value = ScalarValue::redacted();
+                    rewritten = true;
+                }
+            }
+            if rewritten {
+                expanded.hash = None;
+                let new_change = Change::from(expanded);
+                new_hashes.insert(change.hash(), new_change.hash());
+                record.hashes.push((change.hash(), new_change.hash()));
+                changes.push(new_change);
+            } else {
+                changes.push(change.clone());
+            }
+        }
+
+        let mut doc = Automerge::new();
+        doc.set_actor(self.get_actor().clone());
+        doc.apply_changes(changes)?;
+        doc.replaced = self.replaced.clone();
+        doc.add_replaced(record.hashes.iter().copied());
+        Ok((doc, record))
+    }
+
     /// Get the heads of this document.
     pub fn get_heads(&self) -> Vec<ChangeHash> {
         let mut deps: Vec<_> = self.deps.iter().copied().collect();
@@ -1260,10 +2022,185 @@ impl Automerge {
         deps
     }
 
+    /// Get the changes which are not (transitive) dependencies of `have_deps`
+    ///
+    /// # Panics
+    ///
+    /// If the [change store](crate::change_store) fails to read a change, use
+    /// [`Self::try_get_changes()`] to handle that instead.
     pub fn get_changes(&self, have_deps: &[ChangeHash]) -> Vec<&Change> {
         self.get_changes_clock(have_deps)
     }
 
+    /// Like [`Self::get_changes()`] but return an error if the
+    /// [change store](crate::change_store) fails to read a change
+    pub fn try_get_changes(
+        &self,
+        have_deps: &[ChangeHash],
+    ) -> Result<Vec<&Change>, ChangeStoreError> {
+        self.change_indexes_since(have_deps)
+            .into_iter()
+            .map(|i| {
+                Ok(self
+                    .history
+                    .try_get(i)?
+                    .expect("history index should be valid"))
+            })
+            .collect()
+    }
+
+    /// Like [`ReadDoc::get_change_by_hash()`] but return an error if the
+    /// [change store](crate::change_store) fails to read the change
+    pub fn try_get_change_by_hash(
+        &self,
+        hash: &ChangeHash,
+    ) -> Result<Option<&Change>, ChangeStoreError> {
+        match self.history_index.get(hash) {
+            Some(index) => self.history.try_get(*index),
+            None => Ok(None),
+        }
+    }
+
+    /// The heads of the document as it was at `timestamp`
+    ///
+    /// These are the heads of every change whose [`Change::timestamp()`] is at or before
+    /// `timestamp` and whose dependencies are all included too. A change with an earlier
+    /// timestamp than one of its dependencies (e.g. because of clock skew between peers) is
+    /// excluded along with everything which depends on it.
+    pub fn heads_at_time(&self, timestamp: i64) -> Vec<ChangeHash> {
+        let mut included = HashSet::new();
+        let mut heads = BTreeSet::new();
+        for change in self.history.iter() {
+            if change.timestamp() <= timestamp && change.deps().iter().all(|d| included.contains(d))
+            {
+                for dep in change.deps() {
+                    heads.remove(dep);
+                }
+                included.insert(change.hash());
+                heads.insert(change.hash());
+            }
+        }
+        heads.into_iter().collect()
+    }
+
+    /// A view of the document as it was at `timestamp`, see [`Self::heads_at_time()`]
+    pub fn view_at_time(&self, timestamp: i64) -> ReadDocAt<'_, 'static> {
+        self.view_at(&self.heads_at_time(timestamp))
+    }
+
+    /// A view of the document as it was at `heads`
+    ///
+    /// The view implements [`ReadDoc`] and can be passed to anything which reads a document, such
+    /// as [`AutoSerde`](crate::AutoSerde). Reading through the view is cheaper than calling the
+    /// `*_at` methods with the same heads repeatedly.
+    pub fn view_at(&self, heads: &[ChangeHash]) -> ReadDocAt<'_, 'static> {
+        ReadDocAt::new(self, Cow::Owned(heads.to_vec()))
+    }
+
+    /// Iterate over the changes in this document in causal order
+    ///
+    /// See the [`history`](crate::history) module for details.
+    pub fn iter_history(&self) -> HistoryIter<'_> {
+        HistoryIter::new(self, self.history.iter())
+    }
+
+    /// Whether `ancestor` is `descendant` or one of its transitive dependencies
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingHash`] if either hash is not in this document
+    pub fn is_ancestor(
+        &self,
+        ancestor: &ChangeHash,
+        descendant: &ChangeHash,
+    ) -> Result<bool, AutomergeError> {
+        self.check_hashes([ancestor, descendant])?;
+        Ok(self.change_graph.is_ancestor(ancestor, descendant))
+    }
+
+    /// The most recent changes which are ancestors of both `heads_a` and `heads_b`
+    ///
+    /// This is the state the two sets of heads diverged from. If the heads have no common
+    /// ancestors the result is empty.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingHash`] if any of the heads are not in this document
+    pub fn merge_base(
+        &self,
+        heads_a: &[ChangeHash],
+        heads_b: &[ChangeHash],
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        self.check_hashes(heads_a.iter().chain(heads_b))?;
+        Ok(self.change_graph.merge_base(heads_a, heads_b))
+    }
+
+    /// The changes which are ancestors of `to_heads` but not of `from_heads`
+    ///
+    /// The changes are returned in an order where every change comes after its dependencies.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingHash`] if any of the heads are not in this document
+    pub fn changes_between(
+        &self,
+        from_heads: &[ChangeHash],
+        to_heads: &[ChangeHash],
+    ) -> Result<Vec<&Change>, AutomergeError> {
+        self.check_hashes(from_heads.iter().chain(to_heads))?;
+        Ok(self
+            .change_graph
+            .changes_between(from_heads, to_heads)
+            .iter()
+            .filter_map(|hash| self.get_change_by_hash(hash))
+            .collect())
+    }
+
+    /// Every change in the document, with each change after its dependencies
+    ///
+    /// Unlike [`Self::get_changes()`], which returns changes in the order they were applied to
+    /// this document, concurrent changes are ordered by hash. Two documents with the same changes
+    /// iterate them in the same order.
+    pub fn topo_changes(&self) -> impl Iterator<Item = &Change> {
+        self.change_graph
+            .topo_order()
+            .into_iter()
+            .filter_map(move |hash| self.get_change_by_hash(&hash))
+    }
+
+    /// How the states described by `heads_a` and `heads_b` are related
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingHash`] if any of the heads are not in this document
+    pub fn compare_heads(
+        &self,
+        heads_a: &[ChangeHash],
+        heads_b: &[ChangeHash],
+    ) -> Result<CausalOrder, AutomergeError> {
+        self.check_hashes(heads_a.iter().chain(heads_b))?;
+        let a = self.clock_at(heads_a);
+        let b = self.clock_at(heads_b);
+        Ok(match a.partial_cmp(&b) {
+            Some(Ordering::Equal) => CausalOrder::Equal,
+            Some(Ordering::Less) => CausalOrder::Before,
+            Some(Ordering::Greater) => CausalOrder::After,
+            None => CausalOrder::Concurrent,
+        })
+    }
+
+    pub(crate) fn check_hashes<'a, I: IntoIterator<Item = &'a ChangeHash>>(
+        &self,
+        hashes: I,
+    ) -> Result<(), AutomergeError> {
+        for hash in hashes {
+            if !self.history_index.contains_key(hash) {
+                return Err(AutomergeError::MissingHash(*hash));
+            }
+        }
+        Ok(())
+    }
+
     /// Get changes in `other` that are not in `self`
     pub fn get_changes_added<'a>(&self, other: &'a Self) -> Vec<&'a Change> {
         // Depth-first traversal from the heads through the dependency graph,
@@ -1381,12 +2318,20 @@ impl Automerge {
         obj: &crate::ObjId,
         heads: Option<&[ChangeHash]>,
     ) -> Result<hydrate::Value, AutomergeError> {
-        let obj = self.exid_to_obj(obj)?;
         let clock = heads.map(|heads| self.clock_at(heads));
+        self.hydrate_obj_for(obj, clock.as_ref())
+    }
+
+    pub(crate) fn hydrate_obj_for(
+        &self,
+        obj: &crate::ObjId,
+        clock: Option<&Clock>,
+    ) -> Result<hydrate::Value, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
         Ok(match obj.typ {
-            ObjType::Map | ObjType::Table => self.hydrate_map(&obj.id, clock.as_ref()),
-            ObjType::List => self.hydrate_list(&obj.id, clock.as_ref()),
-            ObjType::Text => self.hydrate_text(&obj.id, clock.as_ref()),
+            ObjType::Map | ObjType::Table => self.hydrate_map(&obj.id, clock),
+            ObjType::List => self.hydrate_list(&obj.id, clock),
+            ObjType::Text => self.hydrate_text(&obj.id, clock),
         })
     }
 
@@ -1721,12 +2666,12 @@ impl ReadDoc for Automerge {
         self.parents_for(obj.as_ref(), None)
     }
 
-    fn parents_at<O: AsRef<ExId>>(
+    fn parents_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Parents<'_>, AutomergeError> {
-        let clock = self.clock_at(heads);
+        let clock = self.clock_for_read(heads.into());
         self.parents_for(obj.as_ref(), Some(clock))
     }
 
@@ -1734,8 +2679,8 @@ impl ReadDoc for Automerge {
         self.keys_for(obj.as_ref(), None)
     }
 
-    fn keys_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Keys<'_> {
-        let clock = self.clock_at(heads);
+    fn keys_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Keys<'_> {
+        let clock = self.clock_for_read(heads.into());
         self.keys_for(obj.as_ref(), Some(clock))
     }
 
@@ -1747,13 +2692,13 @@ impl ReadDoc for Automerge {
         self.map_range_for(obj.as_ref(), range, None)
     }
 
-    fn map_range_at<'a, O: AsRef<ExId>, R: RangeBounds<String> + 'a>(
+    fn map_range_at<'a, 'h, O: AsRef<ExId>, R: RangeBounds<String> + 'a, H: Into<ReadAt<'h>>>(
         &'a self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> MapRange<'a, R> {
-        let clock = self.clock_at(heads);
+        let clock = self.clock_for_read(heads.into());
         self.map_range_for(obj.as_ref(), range, Some(clock))
     }
 
@@ -1765,13 +2710,13 @@ impl ReadDoc for Automerge {
         self.list_range_for(obj.as_ref(), range, None)
     }
 
-    fn list_range_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+    fn list_range_at<'h, O: AsRef<ExId>, R: RangeBounds<usize>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> ListRange<'_, R> {
-        let clock = self.clock_at(heads);
+        let clock = self.clock_for_read(heads.into());
         self.list_range_for(obj.as_ref(), range, Some(clock))
     }
 
@@ -1779,8 +2724,8 @@ impl ReadDoc for Automerge {
         self.values_for(obj.as_ref(), None)
     }
 
-    fn values_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Values<'_> {
-        let clock = self.clock_at(heads);
+    fn values_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Values<'_> {
+        let clock = self.clock_for_read(heads.into());
         self.values_for(obj.as_ref(), Some(clock))
     }
 
@@ -1788,8 +2733,8 @@ impl ReadDoc for Automerge {
         self.length_for(obj.as_ref(), None)
     }
 
-    fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize {
-        let clock = self.clock_at(heads);
+    fn length_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> usize {
+        let clock = self.clock_for_read(heads.into());
         self.length_for(obj.as_ref(), Some(clock))
     }
 
@@ -1801,12 +2746,12 @@ impl ReadDoc for Automerge {
         self.spans_for(obj.as_ref(), None)
     }
 
-    fn spans_at<O: AsRef<ExId>>(
+    fn spans_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Spans<'_>, AutomergeError> {
-        let clock = self.clock_at(heads);
+        let clock = self.clock_for_read(heads.into());
         self.spans_for(obj.as_ref(), Some(clock))
     }
 
@@ -1816,7 +2761,7 @@ impl ReadDoc for Automerge {
         position: usize,
         at: Option<&[ChangeHash]>,
     ) -> Result<Cursor, AutomergeError> {
-        let clock = at.map(|heads| self.clock_at(heads));
+        let clock = at.map(|heads| self.clock_for_read(heads.into()));
         self.get_cursor_for(obj.as_ref(), position, clock)
     }
 
@@ -1826,16 +2771,16 @@ impl ReadDoc for Automerge {
         cursor: &Cursor,
         at: Option<&[ChangeHash]>,
     ) -> Result<usize, AutomergeError> {
-        let clock = at.map(|heads| self.clock_at(heads));
+        let clock = at.map(|heads| self.clock_for_read(heads.into()));
         self.get_cursor_position_for(obj.as_ref(), cursor, clock)
     }
 
-    fn text_at<O: AsRef<ExId>>(
+    fn text_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<String, AutomergeError> {
-        let clock = self.clock_at(heads);
+        let clock = self.clock_for_read(heads.into());
         self.text_for(obj.as_ref(), Some(clock))
     }
 
@@ -1843,12 +2788,12 @@ impl ReadDoc for Automerge {
         self.marks_for(obj.as_ref(), None)
     }
 
-    fn marks_at<O: AsRef<ExId>>(
+    fn marks_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<Mark<'_>>, AutomergeError> {
-        let clock = self.clock_at(heads);
+        let clock = self.clock_for_read(heads.into());
         self.marks_for(obj.as_ref(), Some(clock))
     }
 
@@ -1884,13 +2829,13 @@ impl ReadDoc for Automerge {
         self.get_for(obj.as_ref(), prop.into(), None)
     }
 
-    fn get_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
-        let clock = Some(self.clock_at(heads));
+        let clock = Some(self.clock_for_read(heads.into()));
         self.get_for(obj.as_ref(), prop.into(), clock)
     }
 
@@ -1902,13 +2847,13 @@ impl ReadDoc for Automerge {
         self.get_all_for(obj.as_ref(), prop.into(), None)
     }
 
-    fn get_all_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_all_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError> {
-        let clock = Some(self.clock_at(heads));
+        let clock = Some(self.clock_for_read(heads.into()));
         self.get_all_for(obj.as_ref(), prop.into(), clock)
     }
 
@@ -1967,15 +2912,37 @@ impl Default for Automerge {
 #[derive(Debug)]
 pub struct SaveOptions {
     /// Whether to apply DEFLATE compression to the RLE encoded columns in the document
+    ///
+    /// If this is `false` nothing is compressed, whatever `compression` is.
+    #[deprecated(note = "use `compression` instead, `deflate: false` is `Compression::None`")]
     pub deflate: bool,
+    /// The compression to apply to the RLE encoded columns in the document
+    ///
+    /// The default is DEFLATE at level [`Compression::DEFAULT_DEFLATE_LEVEL`]
+    pub compression: Compression,
     /// Whether to save changes which we do not have the dependencies for
     pub retain_orphans: bool,
 }
 
+impl SaveOptions {
+    /// The compression to use, taking the deprecated `deflate` flag into account
+    fn resolved_compression(&self) -> Compression {
+        #[allow(deprecated)]
+        let deflate = self.deflate;
+        if deflate {
+            self.compression
+        } else {
+            Compression::None
+        }
+    }
+}
+
 impl std::default::Default for SaveOptions {
+    #[allow(deprecated)]
     fn default() -> Self {
         Self {
             deflate: true,
+            compression: Compression::default(),
             retain_orphans: true,
         }
     }
@@ -1988,33 +2955,39 @@ pub(crate) struct Isolation {
     clock: Clock,
 }
 
+/// Load a document chunk, pushing its changes into `history` as they are rebuilt
 pub(crate) fn reconstruct_document<'a>(
     doc: &'a storage::Document<'a>,
     mode: VerificationMode,
+    lazy: bool,
+    mut history: History,
 ) -> Result<Automerge, AutomergeError> {
+    let mut history_index = HashMap::new();
+    let mut actor_to_history: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut change_graph = ChangeGraph::new();
+    let mut missing_dep = None;
     let storage::load::ReconOpSet {
-        changes,
         op_set,
         heads,
         max_op,
-    } = storage::load::reconstruct_opset(doc, mode)
-        .map_err(|e| load::Error::InflateDocument(Box::new(e)))?;
-
-    let mut hashes_by_index = HashMap::new();
-    let mut actor_to_history: HashMap<usize, Vec<usize>> = HashMap::new();
-    let mut change_graph = ChangeGraph::new();
-    for (index, change) in changes.iter().enumerate() {
-        // SAFETY: This should be fine because we just constructed an opset containing
-        // all the changes
-        let actor_index = op_set.osd.actors.lookup(change.actor_id()).unwrap();
+    } = storage::load::reconstruct_opset(doc, mode, lazy, |change, osd| {
+        let index = history.len();
+        // SAFETY: This should be fine because the change was built from the ops in the opset
+        let actor_index = osd.actors.lookup(change.actor_id()).unwrap();
         actor_to_history.entry(actor_index).or_default().push(index);
-        hashes_by_index.insert(index, change.hash());
-        change_graph.add_change(change, actor_index)?;
+        history_index.insert(change.hash(), index);
+        if let Err(e) = change_graph.add_change(&change, actor_index) {
+            missing_dep.get_or_insert(e);
+        }
+        history.push(change);
+    })
+    .map_err(|e| load::Error::InflateDocument(Box::new(e)))?;
+    if let Some(e) = missing_dep {
+        return Err(e.into());
     }
-    let history_index = hashes_by_index.into_iter().map(|(k, v)| (v, k)).collect();
     Ok(Automerge {
         queue: vec![],
-        history: changes,
+        history,
         history_index,
         states: actor_to_history,
         change_graph,
@@ -2022,5 +2995,6 @@ pub(crate) fn reconstruct_document<'a>(
         deps: heads.into_iter().collect(),
         actor: Actor::Unused(ActorId::random()),
         max_op,
+        replaced: HashMap::new(),
     })
 }
@@ -1,4 +1,5 @@
 use itertools::Itertools;
+use std::borrow::Cow;
 use std::ops::RangeBounds;
 use std::sync::Arc;
 
@@ -15,7 +16,7 @@ use crate::{
     patches::PatchLog,
     types::{Clock, ListEncoding, Op, Prop},
     value::Value,
-    Automerge, AutomergeError, ChangeHash, Cursor, ObjId as ExId, ObjType, OpType, ReadDoc,
+    Automerge, AutomergeError, ChangeHash, Cursor, ObjId as ExId, ObjType, OpType, ReadAt, ReadDoc,
 };
 
 #[derive(Clone, Debug)]
@@ -318,10 +319,37 @@ impl<'a> RichTextDiff<'a> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub(crate) struct ReadDocAt<'a, 'b> {
-    pub(crate) doc: &'a Automerge,
-    pub(crate) heads: &'b [ChangeHash],
+/// A view of a document as it was at some heads
+///
+/// Every method of [`ReadDoc`] reads the document at the heads of the view unless it is given
+/// other heads explicitly.
+///
+/// The clock for the heads is computed once when the view is created, so many reads through the
+/// same view are cheaper than the same reads through the `*_at` methods of [`ReadDoc`].
+#[derive(Debug, Clone)]
+pub struct ReadDocAt<'a, 'b> {
+    doc: &'a Automerge,
+    heads: Cow<'b, [ChangeHash]>,
+    clock: Clock,
+}
+
+impl<'a, 'b> ReadDocAt<'a, 'b> {
+    pub(crate) fn new(doc: &'a Automerge, heads: Cow<'b, [ChangeHash]>) -> Self {
+        let clock = doc.clock_at(&heads);
+        ReadDocAt { doc, heads, clock }
+    }
+
+    /// The heads this view reads the document at
+    pub fn heads(&self) -> &[ChangeHash] {
+        &self.heads
+    }
+
+    fn get_scope(&self, heads: Option<&[ChangeHash]>) -> Option<Clock> {
+        match heads {
+            Some(heads) => Some(self.doc.clock_at(heads)),
+            None => Some(self.clock.clone()),
+        }
+    }
 }
 
 impl<'a, 'b> AsRef<Automerge> for ReadDocAt<'a, 'b> {
@@ -332,10 +360,10 @@ impl<'a, 'b> AsRef<Automerge> for ReadDocAt<'a, 'b> {
 
 impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
     fn keys<O: AsRef<ExId>>(&self, obj: O) -> Keys<'_> {
-        self.doc.keys_at(obj, self.heads)
+        self.doc.keys_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn keys_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Keys<'_> {
+    fn keys_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Keys<'_> {
         self.doc.keys_at(obj, heads)
     }
 
@@ -344,14 +372,15 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         obj: O,
         range: R,
     ) -> MapRange<'c, R> {
-        self.doc.map_range_at(obj, range, self.heads)
+        self.doc
+            .map_range_for(obj.as_ref(), range, self.get_scope(None))
     }
 
-    fn map_range_at<'c, O: AsRef<ExId>, R: RangeBounds<String> + 'c>(
+    fn map_range_at<'c, 'h, O: AsRef<ExId>, R: RangeBounds<String> + 'c, H: Into<ReadAt<'h>>>(
         &'c self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> MapRange<'c, R> {
         self.doc.map_range_at(obj, range, heads)
     }
@@ -361,31 +390,32 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         obj: O,
         range: R,
     ) -> ListRange<'_, R> {
-        self.doc.list_range_at(obj, range, self.heads)
+        self.doc
+            .list_range_for(obj.as_ref(), range, self.get_scope(None))
     }
 
-    fn list_range_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+    fn list_range_at<'h, O: AsRef<ExId>, R: RangeBounds<usize>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> ListRange<'_, R> {
         self.doc.list_range_at(obj, range, heads)
     }
 
     fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
-        self.doc.values_at(obj, self.heads)
+        self.doc.values_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn values_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Values<'_> {
+    fn values_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Values<'_> {
         self.doc.values_at(obj, heads)
     }
 
     fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
-        self.doc.length_at(obj, self.heads)
+        self.doc.length_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize {
+    fn length_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> usize {
         self.doc.length_at(obj, heads)
     }
 
@@ -394,25 +424,25 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
     }
 
     fn text<O: AsRef<ExId>>(&self, obj: O) -> Result<String, AutomergeError> {
-        self.doc.text_at(obj, self.heads)
+        self.doc.text_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn text_at<O: AsRef<ExId>>(
+    fn text_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<String, AutomergeError> {
         self.doc.text_at(obj, heads)
     }
 
     fn marks<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<Mark<'_>>, AutomergeError> {
-        self.doc.marks_at(obj, self.heads)
+        self.doc.marks_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn marks_at<O: AsRef<ExId>>(
+    fn marks_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<Mark<'_>>, AutomergeError> {
         self.doc.marks_at(obj, heads)
     }
@@ -423,8 +453,7 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         index: usize,
         heads: Option<&[ChangeHash]>,
     ) -> Result<MarkSet, AutomergeError> {
-        self.doc
-            .get_marks(obj, index, Some(heads.unwrap_or(self.heads)))
+        self.doc.get_marks_for(obj, index, self.get_scope(heads))
     }
 
     fn get_cursor<O: AsRef<ExId>>(
@@ -433,7 +462,8 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         position: usize,
         at: Option<&[ChangeHash]>,
     ) -> Result<Cursor, AutomergeError> {
-        self.doc.get_cursor(obj, position, at)
+        self.doc
+            .get_cursor_for(obj.as_ref(), position, self.get_scope(at))
     }
 
     fn get_cursor_position<O: AsRef<ExId>>(
@@ -442,7 +472,8 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         cursor: &Cursor,
         at: Option<&[ChangeHash]>,
     ) -> Result<usize, AutomergeError> {
-        self.doc.get_cursor_position(obj, cursor, at)
+        self.doc
+            .get_cursor_position_for(obj.as_ref(), cursor, self.get_scope(at))
     }
 
     fn get<O: AsRef<ExId>, P: Into<Prop>>(
@@ -450,14 +481,15 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         obj: O,
         prop: P,
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
-        self.doc.get_at(obj, prop, self.heads)
+        self.doc
+            .get_for(obj.as_ref(), prop.into(), self.get_scope(None))
     }
 
-    fn get_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
         self.doc.get_at(obj, prop, heads)
     }
@@ -467,26 +499,27 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         obj: O,
         prop: P,
     ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError> {
-        self.doc.get_all_at(obj, prop, self.heads)
+        self.doc
+            .get_all_for(obj.as_ref(), prop.into(), self.get_scope(None))
     }
 
-    fn get_all_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_all_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError> {
         self.doc.get_all_at(obj, prop, heads)
     }
 
     fn parents<O: AsRef<ExId>>(&self, obj: O) -> Result<crate::Parents<'_>, AutomergeError> {
-        self.doc.parents_at(obj, self.heads)
+        self.doc.parents_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn parents_at<O: AsRef<ExId>>(
+    fn parents_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<crate::Parents<'_>, AutomergeError> {
         self.doc.parents_at(obj, heads)
     }
@@ -503,13 +536,13 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         &self,
         obj: O,
     ) -> Result<crate::iter::Spans<'_>, crate::AutomergeError> {
-        self.doc.spans(obj)
+        self.doc.spans_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn spans_at<O: AsRef<ExId>>(
+    fn spans_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<crate::iter::Spans<'_>, crate::AutomergeError> {
         self.doc.spans_at(obj, heads)
     }
@@ -519,13 +552,14 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         obj: O,
         heads: Option<&[ChangeHash]>,
     ) -> Result<crate::hydrate::Value, crate::AutomergeError> {
-        self.doc.hydrate_obj(obj.as_ref(), heads)
+        self.doc
+            .hydrate_obj_for(obj.as_ref(), self.get_scope(heads).as_ref())
     }
 }
 
 impl<'a, 'b> ReadDocInternal for ReadDocAt<'a, 'b> {
     fn live_obj_paths(&self) -> std::collections::HashMap<ExId, Vec<(ExId, Prop)>> {
-        self.doc.visible_obj_paths(Some(self.heads))
+        self.doc.visible_obj_paths(Some(&self.heads))
     }
 }
 
@@ -1791,3 +1791,243 @@ fn hash_for_opid() {
     assert_eq!(doc.hash_for_opid(&id1), hash1);
     assert_eq!(doc.hash_for_opid(&id2), hash2);
 }
+
+#[test]
+fn load_changes_shared_does_not_copy_change_bytes() {
+    let mut doc = Automerge::new();
+    for i in 0..5 {
+        let mut tx = doc.transaction();
+        tx.put(ROOT, "key", i).unwrap();
+        tx.commit();
+    }
+    let changes = doc.save_after(&[]);
+
+    let expected = Automerge::load(&changes).unwrap();
+    let range = changes.as_ptr_range();
+    let loaded = Automerge::load_changes_shared(changes).unwrap();
+    assert_eq!(loaded.get_heads(), expected.get_heads());
+    assert_eq!(loaded.save(), expected.save());
+    for change in loaded.get_changes(&[]) {
+        assert!(range.contains(&change.raw_bytes().as_ptr()));
+    }
+
+    // Changes outlive the document they were loaded from
+    let last = loaded
+        .get_change_by_hash(&loaded.get_heads()[0])
+        .unwrap()
+        .clone();
+    drop(loaded);
+    assert_eq!(last.hash(), doc.get_heads()[0]);
+}
+
+#[test]
+fn load_changes_shared_rejects_document_chunks() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "key", 1).unwrap();
+    tx.commit();
+    let heads = doc.get_heads();
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "key", 2).unwrap();
+    tx.commit();
+
+    let mut changes = doc.save_after(&[]);
+    assert!(matches!(
+        Automerge::load_changes_shared(doc.save()),
+        Err(AutomergeError::Load(load::Error::SharedDocumentChunk))
+    ));
+    // A document chunk after the first chunk is rejected too
+    let mut snapshot = doc.fork_at(&heads).unwrap().save();
+    changes.append(&mut snapshot);
+    assert!(matches!(
+        Automerge::load_changes_shared(changes),
+        Err(AutomergeError::Load(load::Error::SharedDocumentChunk))
+    ));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn load_changes_shared_from_mmap() {
+    use std::io::Write;
+
+    let mut doc = Automerge::new();
+    for i in 0..5 {
+        let mut tx = doc.transaction();
+        tx.put(ROOT, "key", i).unwrap();
+        tx.commit();
+    }
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&doc.save_after(&[])).unwrap();
+    let map = unsafe { memmap2::Mmap::map(&file) }.unwrap();
+    let loaded = Automerge::load_changes_shared(map).unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    assert_eq!(loaded.save(), doc.save());
+}
+
+#[test]
+fn lazy_load_only_builds_accessed_objects() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    let text = tx.put_object(ROOT, "text", ObjType::Text).unwrap();
+    let map = tx.insert_object(&list, 0, ObjType::Map).unwrap();
+    for i in 0..10 {
+        tx.insert(&list, i + 1, i as i64).unwrap();
+    }
+    tx.splice_text(&text, 0, 0, "hello world").unwrap();
+    tx.put(&map, "nested", "value").unwrap();
+    tx.commit();
+    let saved = doc.save();
+
+    let mut lazy = Automerge::load_with_options(&saved, LoadOptions::new().lazy(true)).unwrap();
+    assert_eq!(lazy.ops.num_materialized(), 0);
+    let pending = lazy.ops.num_pending();
+
+    assert_eq!(lazy.text(&text).unwrap(), "hello world");
+    assert_eq!(lazy.length(&text), 11);
+    assert_eq!(lazy.ops.num_materialized(), 1);
+    // the ops of the text object are only held by its tree once it has been built
+    assert_eq!(lazy.ops.num_pending(), pending - 11);
+
+    assert_eq!(lazy.list_range(&list, ..).count(), 11);
+    assert_eq!(
+        lazy.get(&map, "nested").unwrap().unwrap().0,
+        Value::str("value")
+    );
+    assert_eq!(lazy.parents(&map).unwrap().count(), 2);
+    assert_eq!(lazy.ops.num_materialized(), 4);
+
+    // Modifying a lazily loaded document behaves like modifying an eagerly loaded one
+    let mut eager = Automerge::load(&saved).unwrap();
+    for doc in [&mut lazy, &mut eager] {
+        doc.set_actor(ActorId::from([1]));
+        let mut tx = doc.transaction();
+        tx.insert(&list, 3, "new").unwrap();
+        tx.splice_text(&text, 5, 0, ",").unwrap();
+        tx.commit();
+    }
+    assert_eq!(lazy.text(&text).unwrap(), eager.text(&text).unwrap());
+    assert_eq!(
+        lazy.list_range(&list, ..)
+            .map(|i| i.value)
+            .collect::<Vec<_>>(),
+        eager
+            .list_range(&list, ..)
+            .map(|i| i.value)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(lazy.save(), eager.save());
+}
+
+#[test]
+fn materialize_all_builds_every_object() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    for i in 0..5 {
+        let obj = tx
+            .put_object(ROOT, format!("obj{}", i), ObjType::Map)
+            .unwrap();
+        tx.put(&obj, "i", i).unwrap();
+    }
+    tx.commit();
+    let saved = doc.save();
+
+    let mut lazy = Automerge::load_with_options(&saved, LoadOptions::new().lazy(true)).unwrap();
+    assert_eq!(lazy.ops.num_materialized(), 0);
+    lazy.materialize_all();
+    assert_eq!(lazy.ops.num_materialized(), 6);
+    let eager = Automerge::load(&saved).unwrap();
+    assert_eq!(lazy.ops.len(), eager.ops.len());
+    assert_eq!(lazy.save(), eager.save());
+}
+
+#[test]
+fn change_graph_queries() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    let base = doc1.get_heads();
+    let mut doc2 = doc1.fork();
+    doc1.put(ROOT, "b", 1).unwrap();
+    doc1.commit();
+    doc2.put(ROOT, "c", 1).unwrap();
+    doc2.commit();
+    let heads1 = doc1.get_heads();
+    let heads2 = doc2.get_heads();
+    doc1.merge(&mut doc2).unwrap();
+    let merged = doc1.get_heads();
+
+    assert!(doc1.is_ancestor(&base[0], &heads2[0]).unwrap());
+    assert!(!doc1.is_ancestor(&heads1[0], &heads2[0]).unwrap());
+    assert_eq!(doc1.merge_base(&heads1, &heads2).unwrap(), base);
+    assert_eq!(
+        doc1.compare_heads(&base, &merged).unwrap(),
+        CausalOrder::Before
+    );
+    assert_eq!(
+        doc1.compare_heads(&merged, &heads2).unwrap(),
+        CausalOrder::After
+    );
+    assert_eq!(
+        doc1.compare_heads(&heads1, &heads2).unwrap(),
+        CausalOrder::Concurrent
+    );
+    assert_eq!(
+        doc1.compare_heads(&merged, &merged).unwrap(),
+        CausalOrder::Equal
+    );
+
+    let between = doc1
+        .changes_between(&heads1, &merged)
+        .unwrap()
+        .iter()
+        .map(|c| c.hash())
+        .collect::<Vec<_>>();
+    assert_eq!(between, heads2);
+
+    let topo = doc1.topo_changes().map(|c| c.hash()).collect::<Vec<_>>();
+    let mut other = doc2.clone();
+    other.merge(&mut doc1).unwrap();
+    assert_eq!(
+        other.topo_changes().map(|c| c.hash()).collect::<Vec<_>>(),
+        topo
+    );
+    assert_eq!(topo[0], base[0]);
+
+    let missing = ChangeHash([0; 32]);
+    assert!(matches!(
+        doc1.merge_base(&[missing], &heads1),
+        Err(AutomergeError::MissingHash(h)) if h == missing
+    ));
+}
+
+#[test]
+fn preview_copy_shares_the_trees_of_untouched_objects() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    tx.insert(&list, 0, "a").unwrap();
+    let map = tx.put_object(ROOT, "map", ObjType::Map).unwrap();
+    tx.put(&map, "key", "value").unwrap();
+    tx.commit();
+    let mut other = doc.fork();
+    let mut tx = other.transaction();
+    tx.insert(&list, 1, "b").unwrap();
+    tx.commit();
+    let changes = other
+        .get_changes(&doc.get_heads())
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut copy = doc.preview_copy();
+    copy.apply_changes(changes).unwrap();
+    assert_eq!(copy.length(&list), 2);
+    assert_eq!(doc.length(&list), 1);
+
+    let list = doc.exid_to_obj(&list).unwrap().id;
+    let map = doc.exid_to_obj(&map).unwrap().id;
+    assert!(!copy.ops.shares_tree(&doc.ops, &list));
+    assert!(copy.ops.shares_tree(&doc.ops, &map));
+    assert!(copy.ops.shares_tree(&doc.ops, &crate::types::ObjId::root()));
+}
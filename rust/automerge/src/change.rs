@@ -4,7 +4,8 @@ use crate::{
     columnar::Key as StoredKey,
     storage::{
         change::{Unverified, Verified},
-        parse, Change as StoredChange, ChangeOp, Chunk, Compressed, ReadChangeOpError,
+        parse, Backing, Change as StoredChange, ChangeOp, Chunk, Compressed, Compression,
+        ReadChangeOpError,
     },
     types::{ActorId, ChangeHash, ElemId},
 };
@@ -90,7 +91,7 @@ impl Change {
 
     pub fn bytes(&mut self) -> Cow<'_, [u8]> {
         if let CompressionState::NotCompressed = self.compression {
-            if let Some(compressed) = self.stored.compress() {
+            if let Some(compressed) = self.stored.compress(Compression::default()) {
                 self.compression = CompressionState::Compressed(compressed);
             } else {
                 self.compression = CompressionState::TooSmallToCompress;
@@ -104,6 +105,18 @@ impl Change {
         }
     }
 
+    /// Compress this change using `compression` and return the compressed bytes
+    ///
+    /// Subsequent calls to [`Self::bytes()`] return the same bytes. Changes which are too small
+    /// to benefit from compression are returned uncompressed.
+    pub fn compress(&mut self, compression: Compression) -> Cow<'_, [u8]> {
+        self.compression = match self.stored.compress(compression) {
+            Some(compressed) => CompressionState::Compressed(compressed),
+            None => CompressionState::TooSmallToCompress,
+        };
+        self.bytes()
+    }
+
     pub fn raw_bytes(&self) -> &[u8] {
         self.stored.bytes()
     }
@@ -132,7 +145,8 @@ enum CompressionState {
     NotCompressed,
     /// We have compressed this change
     Compressed(Compressed<'static>),
-    /// We tried to compress this change but it wasn't big enough to be worth it
+    /// We tried to compress this change but it wasn't big enough to be worth it, or compression
+    /// was disabled
     TooSmallToCompress,
 }
 
@@ -156,26 +170,43 @@ pub enum LoadError {
     LeftoverData,
     #[error("wrong chunk type")]
     WrongChunkType,
+    #[error("the change is compressed with zstd but the zstd feature is not enabled")]
+    UnsupportedCompression,
+}
+
+impl Change {
+    /// Parse the change in `range` of `backing`, sharing rather than copying its bytes
+    #[cfg(feature = "mmap")]
+    pub(crate) fn from_backing(
+        backing: &Backing,
+        range: std::ops::Range<usize>,
+    ) -> Result<Self, LoadError> {
+        parse_change(&backing.bytes()[range], Some(backing))
+    }
+}
+
+fn parse_change(value: &[u8], backing: Option<&Backing>) -> Result<Change, LoadError> {
+    let input = parse::Input::new(value);
+    let (remaining, chunk) = Chunk::parse(input)?;
+    if !remaining.is_empty() {
+        return Err(LoadError::LeftoverData);
+    }
+    match chunk {
+        Chunk::Change(c) => Change::new_from_unverified(c.into_backed(backing), None)
+            .map_err(|e| LoadError::Parse(Box::new(e))),
+        Chunk::CompressedChange(c, compressed) => {
+            Change::new_from_unverified(c.into_backed(backing), Some(compressed.into_owned()))
+                .map_err(|e| LoadError::Parse(Box::new(e)))
+        }
+        _ => Err(LoadError::WrongChunkType),
+    }
 }
 
 impl<'a> TryFrom<&'a [u8]> for Change {
     type Error = LoadError;
 
     fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
-        let input = parse::Input::new(value);
-        let (remaining, chunk) = Chunk::parse(input).map_err(|e| LoadError::Parse(Box::new(e)))?;
-        if !remaining.is_empty() {
-            return Err(LoadError::LeftoverData);
-        }
-        match chunk {
-            Chunk::Change(c) => Self::new_from_unverified(c.into_owned(), None)
-                .map_err(|e| LoadError::Parse(Box::new(e))),
-            Chunk::CompressedChange(c, compressed) => {
-                Self::new_from_unverified(c.into_owned(), Some(compressed.into_owned()))
-                    .map_err(|e| LoadError::Parse(Box::new(e)))
-            }
-            _ => Err(LoadError::WrongChunkType),
-        }
+        parse_change(value, None)
     }
 }
 
@@ -1,4 +1,5 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
 
 use crate::{
     clock::{Clock, ClockData},
@@ -197,6 +198,104 @@ impl ChangeGraph {
         });
     }
 
+    /// Whether `ancestor` is `descendant` or one of its transitive dependencies
+    pub(crate) fn is_ancestor(&self, ancestor: &ChangeHash, descendant: &ChangeHash) -> bool {
+        let (Some(&target), Some(&start)) = (
+            self.nodes_by_hash.get(ancestor),
+            self.nodes_by_hash.get(descendant),
+        ) else {
+            return false;
+        };
+        let mut found = false;
+        // Nodes are added after their parents so no node before `target` can lead to it
+        self.traverse_ancestors(vec![start], |_node, idx| {
+            found |= idx == target;
+            !found && idx > target
+        });
+        found
+    }
+
+    /// The most recent changes which are ancestors of both `a` and `b`
+    pub(crate) fn merge_base(&self, a: &[ChangeHash], b: &[ChangeHash]) -> Vec<ChangeHash> {
+        let in_a = self.ancestors(a);
+        let common = self
+            .ancestors(b)
+            .intersection(&in_a)
+            .copied()
+            .collect::<BTreeSet<_>>();
+        // common is closed under taking parents, so the most recent common changes are the ones
+        // which are not the parent of another common change
+        let parents = common
+            .iter()
+            .flat_map(|idx| self.parents(*idx))
+            .collect::<BTreeSet<_>>();
+        let mut base = common
+            .difference(&parents)
+            .map(|idx| self.hash(*idx))
+            .collect::<Vec<_>>();
+        base.sort();
+        base
+    }
+
+    /// The changes which are ancestors of `to` but not of `from`, in the order they were added to
+    /// the graph
+    pub(crate) fn changes_between(
+        &self,
+        from: &[ChangeHash],
+        to: &[ChangeHash],
+    ) -> Vec<ChangeHash> {
+        let from = self.ancestors(from);
+        self.ancestors(to)
+            .difference(&from)
+            .map(|idx| self.hash(*idx))
+            .collect()
+    }
+
+    /// Every change in the graph in topological order
+    ///
+    /// Concurrent changes are ordered by hash so the order only depends on the set of changes in
+    /// the graph and not on the order they were added in.
+    pub(crate) fn topo_order(&self) -> Vec<ChangeHash> {
+        let mut children = vec![Vec::new(); self.nodes.len()];
+        let mut num_parents = vec![0; self.nodes.len()];
+        for idx in (0..self.nodes.len()).map(|i| NodeIdx(i as u32)) {
+            for parent in self.parents(idx) {
+                children[parent.0 as usize].push(idx);
+                num_parents[idx.0 as usize] += 1;
+            }
+        }
+        let mut ready = num_parents
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| **n == 0)
+            .map(|(i, _)| Reverse((self.hash(NodeIdx(i as u32)), NodeIdx(i as u32))))
+            .collect::<BinaryHeap<_>>();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(Reverse((hash, idx))) = ready.pop() {
+            order.push(hash);
+            for child in &children[idx.0 as usize] {
+                num_parents[child.0 as usize] -= 1;
+                if num_parents[child.0 as usize] == 0 {
+                    ready.push(Reverse((self.hash(*child), *child)));
+                }
+            }
+        }
+        order
+    }
+
+    fn ancestors(&self, heads: &[ChangeHash]) -> BTreeSet<NodeIdx> {
+        let mut ancestors = BTreeSet::new();
+        self.traverse_ancestors(self.heads_to_nodes(heads), |_node, idx| {
+            ancestors.insert(idx);
+            true
+        });
+        ancestors
+    }
+
+    fn hash(&self, idx: NodeIdx) -> ChangeHash {
+        self.hashes[self.nodes[idx.0 as usize].hash_idx.0 as usize]
+    }
+
     /// Call `f` for each (node, hash) in the graph, starting from the given heads
     ///
     /// No guarantees are made about the order of traversal but each node will only be visited
@@ -222,6 +321,19 @@ impl ChangeGraph {
     }
 }
 
+/// How two sets of heads are related, see [`Automerge::compare_heads()`](crate::Automerge::compare_heads)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CausalOrder {
+    /// The heads describe the same state of the document
+    Equal,
+    /// Every change in the first set of heads is an ancestor of the second
+    Before,
+    /// Every change in the second set of heads is an ancestor of the first
+    After,
+    /// Each set of heads contains changes the other has not seen
+    Concurrent,
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error("attempted to derive a clock for a change with dependencies we don't have")]
 pub struct MissingDep(ChangeHash);
@@ -287,6 +399,68 @@ mod tests {
         assert_eq!(changes, expected_changes);
     }
 
+    #[test]
+    fn ancestry_queries() {
+        let mut builder = TestGraphBuilder::new();
+        let actor1 = builder.actor();
+        let actor2 = builder.actor();
+        let actor3 = builder.actor();
+        let change1 = builder.change(&actor1, 10, &[]);
+        let change2 = builder.change(&actor2, 20, &[change1]);
+        let change3 = builder.change(&actor3, 30, &[change1]);
+        let change4 = builder.change(&actor1, 10, &[change2, change3]);
+        let change5 = builder.change(&actor2, 10, &[change2]);
+        let graph = builder.build();
+
+        assert!(graph.is_ancestor(&change1, &change4));
+        assert!(graph.is_ancestor(&change4, &change4));
+        assert!(!graph.is_ancestor(&change4, &change1));
+        assert!(!graph.is_ancestor(&change3, &change5));
+
+        assert_eq!(graph.merge_base(&[change4], &[change5]), vec![change2]);
+        assert_eq!(graph.merge_base(&[change3], &[change5]), vec![change1]);
+        let mut both = vec![change2, change3];
+        both.sort();
+        assert_eq!(graph.merge_base(&[change2, change3], &[change4]), both);
+
+        assert_eq!(
+            graph.changes_between(&[change2], &[change4]),
+            vec![change3, change4]
+        );
+        assert!(graph.changes_between(&[change4], &[change2]).is_empty());
+    }
+
+    #[test]
+    fn topo_order_does_not_depend_on_insertion_order() {
+        let mut builder = TestGraphBuilder::new();
+        let actor1 = builder.actor();
+        let actor2 = builder.actor();
+        let change1 = builder.change(&actor1, 10, &[]);
+        let change2 = builder.change(&actor1, 10, &[change1]);
+        let change3 = builder.change(&actor2, 10, &[change1]);
+        let change4 = builder.change(&actor2, 10, &[change2, change3]);
+        let graph = builder.build();
+
+        let mut reordered = ChangeGraph::new();
+        for i in [0, 2, 1, 3] {
+            let change = &builder.changes[i];
+            reordered
+                .add_change(change, builder.index(change.actor_id()))
+                .unwrap();
+        }
+
+        let order = graph.topo_order();
+        assert_eq!(order, reordered.topo_order());
+        assert_eq!(order[0], change1);
+        assert_eq!(order[3], change4);
+        let (first, second) = if change2 < change3 {
+            (change2, change3)
+        } else {
+            (change3, change2)
+        };
+        assert_eq!(&order[1..3], &[first, second]);
+    }
+
     struct TestGraphBuilder {
         actors: Vec<ActorId>,
         changes: Vec<Change>,
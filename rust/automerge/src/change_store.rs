@@ -0,0 +1,683 @@
+//! # Change stores
+//!
+//! An [`Automerge`](crate::Automerge) document keeps every [`Change`] in its history so that it
+//! can save itself, answer sync requests and look at old versions. By default these changes are
+//! held in memory in a [`MemoryChangeStore`]. For documents with long histories the changes can
+//! instead be kept in a file with a [`FileChangeStore`], in which case only the changes which
+//! are actually read are decoded and held in memory:
+//!
+//! ```no_run
+//! use automerge::{change_store::FileChangeStore, Automerge, LoadOptions};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let bytes = std::fs::read("big.automerge")?;
+//! let mut doc = Automerge::load_with_options(
+//!     &bytes,
+//!     LoadOptions::new().change_store(FileChangeStore::create("big.changes")?),
+//! )?;
+//! // ... sync, save etc.
+//! // Drop any changes which have been read back from the file
+//! doc.release_cached_changes();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The operations which make up the current state of the document are always held in memory,
+//! only the history is moved out. Loading with
+//! [`LoadOptions::change_store()`](crate::LoadOptions::change_store) pushes each change into the
+//! store as it is decoded, whereas
+//! [`Automerge::with_change_store()`](crate::Automerge::with_change_store) moves the history of
+//! a document which has already been loaded.
+//!
+//! ## Errors
+//!
+//! A store may fail to read a change back, e.g. because the file was truncated. The methods
+//! [`Automerge::try_get_changes()`](crate::Automerge::try_get_changes),
+//! [`Automerge::try_get_change_by_hash()`](crate::Automerge::try_get_change_by_hash),
+//! [`Automerge::try_save()`](crate::Automerge::try_save) and
+//! [`Automerge::try_save_after()`](crate::Automerge::try_save_after) return those errors. Every
+//! other method which reads old changes panics instead, these include `get_changes`,
+//! `get_change_by_hash`, `get_changes_added`, `get_last_local_change`, `save`, `save_after`,
+//! `storage_stats`, `iter_history`, `heads_at_time`, `fork_at`, `redact`, `hash_for_opid`,
+//! `set_change_store`, `merge` (which reads the changes of the other document) and generating
+//! sync messages.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::{Change, LoadChangeError};
+
+/// An error from a [`ChangeStore`]
+#[derive(Debug, thiserror::Error)]
+pub enum ChangeStoreError {
+    #[error("the change store is not empty")]
+    NotEmpty,
+    #[error("failed to read change from change store: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid change in change store: {0}")]
+    InvalidChange(#[from] LoadChangeError),
+}
+
+/// Storage for the history of a document
+///
+/// Changes are pushed in the order they are applied to the document and are identified by
+/// their position in that order. A store is never asked for a change it was not given.
+pub trait ChangeStore: fmt::Debug + Send + Sync {
+    /// The number of changes in the store
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the change at `index`, `Ok(None)` if there is no such change
+    fn get(&self, index: usize) -> Result<Option<&Change>, ChangeStoreError>;
+
+    /// Add a change to the end of the store
+    ///
+    /// This cannot fail, a store which fails to write the change somewhere else must keep it in
+    /// memory instead.
+    fn push(&mut self, change: Change);
+
+    /// Remove the change at `len` and every change after it
+    ///
+    /// This is used when the end of the history is rewritten, e.g. by
+    /// [`Automerge::squash_local()`](crate::Automerge::squash_local).
+    fn truncate(&mut self, len: usize);
+
+    /// Drop any changes which are held in memory but can be read again later
+    fn release(&mut self) {}
+
+    /// Clone this store, this is used when the document is cloned or forked
+    fn box_clone(&self) -> Box<dyn ChangeStore>;
+}
+
+impl dyn ChangeStore + '_ {
+    /// Iterate over the changes in the order they were pushed
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            store: self,
+            range: 0..self.len(),
+        }
+    }
+}
+
+/// The change store of a document
+///
+/// The methods of this type panic if the store fails to read a change, use the methods of the
+/// underlying [`ChangeStore`] to handle those errors.
+#[derive(Debug)]
+pub(crate) struct History(Box<dyn ChangeStore>);
+
+impl History {
+    pub(crate) fn new<S: ChangeStore + 'static>(store: S) -> Self {
+        Self(Box::new(store))
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&Change> {
+        self.0
+            .get(index)
+            .unwrap_or_else(|e| panic!("failed to read change {} from change store: {}", index, e))
+    }
+
+    pub(crate) fn iter(&self) -> Changes<'_> {
+        Changes(self.0.iter())
+    }
+
+    pub(crate) fn try_get(&self, index: usize) -> Result<Option<&Change>, ChangeStoreError> {
+        self.0.get(index)
+    }
+
+    pub(crate) fn try_iter(&self) -> Iter<'_> {
+        self.0.iter()
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(MemoryChangeStore::new())
+    }
+}
+
+impl std::ops::Deref for History {
+    type Target = dyn ChangeStore;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl std::ops::DerefMut for History {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut()
+    }
+}
+
+impl Clone for History {
+    fn clone(&self) -> Self {
+        Self(self.0.box_clone())
+    }
+}
+
+impl PartialEq for History {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+/// An iterator over the changes in a [`ChangeStore`]
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    store: &'a dyn ChangeStore,
+    range: std::ops::Range<usize>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<&'a Change, ChangeStoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range
+            .next()
+            .and_then(|i| self.store.get(i).transpose())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range
+            .next_back()
+            .and_then(|i| self.store.get(i).transpose())
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+/// An iterator over the changes in a [`History`] which panics if a change cannot be read
+#[derive(Debug, Clone)]
+pub(crate) struct Changes<'a>(Iter<'a>);
+
+impl<'a> Iterator for Changes<'a> {
+    type Item = &'a Change;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .next()
+            .map(|c| c.unwrap_or_else(|e| panic!("failed to read change from change store: {}", e)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Changes<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0
+            .next_back()
+            .map(|c| c.unwrap_or_else(|e| panic!("failed to read change from change store: {}", e)))
+    }
+}
+
+impl<'a> ExactSizeIterator for Changes<'a> {}
+
+/// A [`ChangeStore`] which holds every change in memory, this is the default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryChangeStore(Vec<Change>);
+
+impl MemoryChangeStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl From<Vec<Change>> for MemoryChangeStore {
+    fn from(changes: Vec<Change>) -> Self {
+        Self(changes)
+    }
+}
+
+impl ChangeStore for MemoryChangeStore {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn get(&self, index: usize) -> Result<Option<&Change>, ChangeStoreError> {
+        Ok(self.0.get(index))
+    }
+
+    fn push(&mut self, change: Change) {
+        self.0.push(change)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.0.truncate(len)
+    }
+
+    fn box_clone(&self) -> Box<dyn ChangeStore> {
+        Box::new(self.clone())
+    }
+}
+
+/// The store of a short lived copy of a document which only holds the changes added to the copy
+///
+/// The changes the copy starts with are not copied and cannot be read, so the copy must not be
+/// used for anything which reads its history.
+#[derive(Debug, Clone)]
+pub(crate) struct AddedChangeStore {
+    skipped: usize,
+    added: Vec<Change>,
+}
+
+impl AddedChangeStore {
+    pub(crate) fn after(skipped: usize) -> Self {
+        Self {
+            skipped,
+            added: Vec::new(),
+        }
+    }
+}
+
+impl ChangeStore for AddedChangeStore {
+    fn len(&self) -> usize {
+        self.skipped + self.added.len()
+    }
+
+    fn get(&self, index: usize) -> Result<Option<&Change>, ChangeStoreError> {
+        Ok(index
+            .checked_sub(self.skipped)
+            .and_then(|i| self.added.get(i)))
+    }
+
+    fn push(&mut self, change: Change) {
+        self.added.push(change)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.added.truncate(len.saturating_sub(self.skipped))
+    }
+
+    fn box_clone(&self) -> Box<dyn ChangeStore> {
+        Box::new(self.clone())
+    }
+}
+
+/// A [`ChangeStore`] which appends changes to a file
+///
+/// The file is a sequence of change chunks, so it can be loaded with
+/// [`Automerge::load_incremental()`](crate::Automerge::load_incremental) if need be. Only the
+/// offset of each change is held in memory. A change is read and decoded from the file the first
+/// time it is accessed and then kept in memory until [`ChangeStore::release()`] is called.
+///
+/// Clones of the store share the file. As the file is only ever appended to and each clone
+/// keeps its own index into it, clones can diverge (e.g. after a
+/// [`fork`](crate::Automerge::fork)) without interfering with each other.
+///
+/// Errors reading a change back from the file are returned from [`ChangeStore::get()`]. If
+/// writing a change fails it is kept in memory instead.
+#[derive(Clone)]
+pub struct FileChangeStore {
+    file: Arc<Mutex<File>>,
+    slots: Vec<Slot>,
+    #[cfg(feature = "mmap")]
+    map: Option<crate::storage::Backing>,
+    use_mmap: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Slot {
+    Resident(Change),
+    OnDisk {
+        offset: u64,
+        len: usize,
+        cache: OnceLock<Change>,
+    },
+}
+
+impl FileChangeStore {
+    /// Create a store backed by the file at `path`, truncating it if it exists
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            slots: Vec::new(),
+            #[cfg(feature = "mmap")]
+            map: None,
+            use_mmap: false,
+        })
+    }
+
+    /// Like [`Self::create()`] but read changes back through a memory map of the file
+    ///
+    /// Changes read through the map share its memory rather than copying their bytes out of it.
+    /// The file must not be modified by anything else while the store is alive.
+    #[cfg(feature = "mmap")]
+    pub fn create_mmap<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        let mut store = Self::create(path)?;
+        store.use_mmap = true;
+        Ok(store)
+    }
+
+    /// The number of changes which are currently decoded and held in memory
+    pub fn num_resident(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|s| match s {
+                Slot::Resident(_) => true,
+                Slot::OnDisk { cache, .. } => cache.get().is_some(),
+            })
+            .count()
+    }
+
+    fn append(&self, bytes: &[u8]) -> Result<u64, io::Error> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let offset = file.seek(SeekFrom::End(0))?;
+        file.write_all(bytes)?;
+        Ok(offset)
+    }
+
+    fn read(&self, offset: u64, len: usize) -> Result<Change, ChangeStoreError> {
+        #[cfg(feature = "mmap")]
+        if let Some(map) = &self.map {
+            let start = offset as usize;
+            if start + len <= map.bytes().len() {
+                // The change shares its bytes with the map rather than copying them
+                return Ok(Change::from_backing(map, start..start + len)?);
+            }
+        }
+        let mut bytes = vec![0; len];
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut bytes)?;
+        Ok(Change::from_bytes(bytes)?)
+    }
+
+    /// Map the file again if it has grown significantly since it was last mapped
+    #[cfg(feature = "mmap")]
+    fn maybe_remap(&mut self, file_len: u64) {
+        let mapped = self
+            .map
+            .as_ref()
+            .map(|m| m.bytes().len() as u64)
+            .unwrap_or(0);
+        if !self.use_mmap || file_len < mapped * 2 {
+            return;
+        }
+        let file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        // SAFETY: the file is only ever appended to so the mapped region never changes
+        match unsafe { memmap2::Mmap::map(&*file) } {
+            Ok(map) => self.map = Some(crate::storage::Backing::new(map)),
+            Err(e) => tracing::warn!(err=?e, "failed to map change store file"),
+        }
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn maybe_remap(&mut self, _file_len: u64) {}
+}
+
+impl fmt::Debug for FileChangeStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileChangeStore")
+            .field("len", &self.slots.len())
+            .field("resident", &self.num_resident())
+            .field("use_mmap", &self.use_mmap)
+            .finish()
+    }
+}
+
+impl ChangeStore for FileChangeStore {
+    fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn get(&self, index: usize) -> Result<Option<&Change>, ChangeStoreError> {
+        match self.slots.get(index) {
+            None => Ok(None),
+            Some(Slot::Resident(change)) => Ok(Some(change)),
+            Some(Slot::OnDisk { offset, len, cache }) => {
+                if cache.get().is_none() {
+                    let change = self.read(*offset, *len)?;
+                    // another thread may have read the change first, either copy will do
+                    let _ = cache.set(change);
+                }
+                Ok(cache.get())
+            }
+        }
+    }
+
+    fn push(&mut self, change: Change) {
+        let bytes = change.raw_bytes();
+        match self.append(bytes) {
+            Ok(offset) => {
+                let len = bytes.len();
+                self.slots.push(Slot::OnDisk {
+                    offset,
+                    len,
+                    cache: OnceLock::new(),
+                });
+                self.maybe_remap(offset + len as u64);
+            }
+            Err(e) => {
+                tracing::warn!(err=?e, "failed to write change to change store file");
+                self.slots.push(Slot::Resident(change));
+            }
+        }
+    }
+
+    fn truncate(&mut self, len: usize) {
+        // The file is shared with clones of this store so the bytes of the removed changes stay
+        // in it
+        self.slots.truncate(len)
+    }
+
+    fn release(&mut self) {
+        for slot in &mut self.slots {
+            if let Slot::OnDisk { cache, .. } = slot {
+                cache.take();
+            }
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn ChangeStore> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{transaction::Transactable, Automerge, ChangeHash, ReadDoc, ROOT};
+
+    fn doc_with_changes(n: usize) -> Automerge {
+        let mut doc = Automerge::new();
+        for i in 0..n {
+            let mut tx = doc.transaction();
+            tx.put(ROOT, "key", i as i64).unwrap();
+            tx.commit();
+        }
+        doc
+    }
+
+    fn check_file_store(create: fn(&Path) -> Result<FileChangeStore, io::Error>) {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = doc_with_changes(10);
+        let mut stored = doc
+            .clone()
+            .with_change_store(create(&dir.path().join("changes")).unwrap())
+            .unwrap();
+        assert_eq!(stored.get_changes(&[]), doc.get_changes(&[]));
+        assert_eq!(stored.save(), doc.save());
+
+        // Changes made after switching stores go to the new store
+        let mut tx = stored.transaction();
+        tx.put(ROOT, "key", "last").unwrap();
+        tx.commit();
+        let last = stored.get_last_local_change().unwrap().clone();
+        stored.release_cached_changes();
+        assert_eq!(stored.get_change_by_hash(&last.hash()), Some(&last));
+        assert_eq!(
+            Automerge::load(&stored.save())
+                .unwrap()
+                .get(ROOT, "key")
+                .unwrap()
+                .unwrap()
+                .0
+                .to_str(),
+            Some("last")
+        );
+
+        // Forks share the file without stepping on each other
+        let mut fork = stored.fork();
+        let mut tx = fork.transaction();
+        tx.put(ROOT, "fork", true).unwrap();
+        tx.commit();
+        let mut tx = stored.transaction();
+        tx.put(ROOT, "original", true).unwrap();
+        tx.commit();
+        stored.release_cached_changes();
+        fork.release_cached_changes();
+        assert!(Automerge::load(&fork.save())
+            .unwrap()
+            .get(ROOT, "original")
+            .unwrap()
+            .is_none());
+        assert_eq!(stored.get_changes(&[]).len(), 12);
+        assert_eq!(fork.get_changes(&[]).len(), 12);
+    }
+
+    #[test]
+    fn file_change_store() {
+        check_file_store(|p| FileChangeStore::create(p));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_change_store() {
+        check_file_store(|p| FileChangeStore::create_mmap(p));
+    }
+
+    #[test]
+    fn changes_are_only_decoded_when_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FileChangeStore::create(dir.path().join("changes")).unwrap();
+        let doc = doc_with_changes(5);
+        for change in doc.get_changes(&[]) {
+            store.push(change.clone());
+        }
+        assert_eq!(store.num_resident(), 0);
+        assert_eq!(store.get(3).unwrap(), Some(doc.get_changes(&[])[3]));
+        assert_eq!(store.num_resident(), 1);
+        store.release();
+        assert_eq!(store.num_resident(), 0);
+        assert_eq!(store.get(5).unwrap(), None);
+    }
+
+    #[test]
+    fn read_errors_are_returned() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("changes");
+        let mut store = FileChangeStore::create(&path).unwrap();
+        for change in doc_with_changes(2).get_changes(&[]) {
+            store.push(change.clone());
+        }
+        std::fs::write(&path, b"").unwrap();
+        assert!(matches!(store.get(1), Err(ChangeStoreError::Io(_))));
+        assert!((&store as &dyn ChangeStore).iter().next().unwrap().is_err());
+
+        let mut doc = doc_with_changes(1);
+        doc.set_change_store(store).unwrap_err();
+    }
+
+    #[test]
+    fn load_into_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = doc_with_changes(5);
+        for (name, bytes) in [("doc", doc.save()), ("changes", doc.save_after(&[]))] {
+            let path = dir.path().join(name);
+            let store = FileChangeStore::create(&path).unwrap();
+            let loaded =
+                Automerge::load_with_options(&bytes, crate::LoadOptions::new().change_store(store))
+                    .unwrap();
+            assert_eq!(loaded.get_heads(), doc.get_heads());
+            assert_eq!(loaded.save(), doc.save());
+            assert_eq!(
+                std::fs::metadata(&path).unwrap().len() as usize,
+                doc.save_after(&[]).len()
+            );
+        }
+
+        let mut store = MemoryChangeStore::new();
+        store.push(doc.get_changes(&[])[0].clone());
+        assert!(matches!(
+            Automerge::load_with_options(
+                &doc.save(),
+                crate::LoadOptions::new().change_store(store)
+            ),
+            Err(crate::AutomergeError::ChangeStore(
+                ChangeStoreError::NotEmpty
+            ))
+        ));
+    }
+
+    #[test]
+    fn squashing_keeps_the_change_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("changes");
+        let mut doc = doc_with_changes(2)
+            .with_change_store(FileChangeStore::create(&path).unwrap())
+            .unwrap();
+        let heads = doc.get_heads();
+        for i in 0..3 {
+            let mut tx = doc.transaction();
+            tx.put(ROOT, "squashed", i).unwrap();
+            tx.commit();
+        }
+        let len = std::fs::metadata(&path).unwrap().len() as usize;
+
+        let hash = doc.squash_local(&heads, []).unwrap().unwrap();
+        let squashed = doc.get_change_by_hash(&hash).unwrap().raw_bytes().len();
+        // Only the squashed change is written
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len() as usize,
+            len + squashed
+        );
+        doc.release_cached_changes();
+        assert_eq!(doc.get_changes(&[]).len(), 3);
+        assert_eq!(doc.get_changes(&[])[2].hash(), hash);
+    }
+
+    #[test]
+    fn try_methods_return_read_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("changes");
+        let doc = doc_with_changes(2);
+        let hash = doc.get_heads()[0];
+        let mut stored = doc
+            .with_change_store(FileChangeStore::create(&path).unwrap())
+            .unwrap();
+        stored.release_cached_changes();
+        std::fs::write(&path, b"").unwrap();
+        assert!(stored.try_get_changes(&[]).is_err());
+        assert!(stored.try_get_change_by_hash(&hash).is_err());
+        assert!(stored.try_save().is_err());
+        assert!(stored.try_save_after(&[]).is_err());
+        // Changes which are not read are fine
+        assert_eq!(stored.try_save_after(&[hash]).unwrap(), Vec::<u8>::new());
+        assert_eq!(
+            stored.try_get_change_by_hash(&ChangeHash([0; 32])).unwrap(),
+            None
+        );
+    }
+}
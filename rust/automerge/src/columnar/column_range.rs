@@ -13,7 +13,7 @@ pub(crate) use opid_list::{OpIdListEncoder, OpIdListIter, OpIdListRange};
 mod deps;
 pub(crate) use deps::{DepsIter, DepsRange};
 mod value;
-pub(crate) use value::{ValueEncoder, ValueIter, ValueRange};
+pub(crate) use value::{ValueEncoder, ValueIter, ValueRange, REDACTED_TYPE_CODE};
 pub(crate) mod generic;
 mod key;
 pub(crate) use key::{Key, KeyEncoder, KeyIter, KeyRange};
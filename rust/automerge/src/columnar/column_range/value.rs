@@ -368,6 +368,13 @@ fn encode_val<S: Sink>(out: &mut RawEncoder<S>, val: &ScalarValue) -> usize {
     }
 }
 
+/// The type code of [`ScalarValue::redacted()`]
+///
+/// Codes 0 to 9 are the types below, 15 is reserved for redacted values and the rest are free.
+/// Redacted values decode as [`ValueType::Unknown`] so implementations which predate redaction
+/// can still load documents which contain them.
+pub(crate) const REDACTED_TYPE_CODE: u8 = 15;
+
 #[derive(Debug)]
 enum ValueType {
     Null,
@@ -1,13 +1,15 @@
-use crate::storage::load::Error as LoadError;
+pub use crate::storage::load::Error as LoadError;
 use crate::types::{ActorId, ScalarValue};
 use crate::value::DataType;
-use crate::{ChangeHash, Cursor, LoadChangeError, ObjType, PatchAction};
+use crate::{BranchesFromBytesError, ChangeHash, Cursor, LoadChangeError, ObjType, PatchAction};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum AutomergeError {
     #[error(transparent)]
     ChangeGraph(#[from] crate::change_graph::MissingDep),
+    #[error(transparent)]
+    ChangeStore(#[from] crate::change_store::ChangeStoreError),
     #[error("failed to load compressed data: {0}")]
     Deflate(#[source] std::io::Error),
     #[error("duplicate seq {0} found for actor {1}")]
@@ -138,3 +140,29 @@ pub enum UpdateObjectError {
     #[error(transparent)]
     Automerge(#[from] AutomergeError),
 }
+
+#[derive(Error, Debug)]
+pub enum BranchError {
+    #[error("no branch called {0:?}")]
+    NoSuchBranch(String),
+    #[error("there is already a branch called {0:?}")]
+    BranchExists(String),
+    #[error("branch {0:?} is checked out")]
+    CurrentBranch(String),
+    #[error("invalid branch data: {0}")]
+    Load(#[from] BranchesFromBytesError),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+}
+
+#[derive(Error, Debug)]
+pub enum SquashError {
+    #[error("change {0} was made by another actor")]
+    NotLocal(ChangeHash),
+    #[error("change {0} has been shared")]
+    Shared(ChangeHash),
+    #[error("change {0} depends on changes which are concurrent with the changes being squashed")]
+    Concurrent(ChangeHash),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+}
@@ -0,0 +1,292 @@
+//! # Filesystem storage
+//!
+//! [`FsStore`] persists a single document in a directory as a snapshot file plus a series of
+//! append-only incremental files:
+//!
+//! ```text
+//! my-doc/
+//!   snapshot.automerge
+//!   incremental-00000000000000000001.automerge
+//!   incremental-00000000000000000002.automerge
+//! ```
+//!
+//! Each call to [`FsStore::save()`] writes the changes made since the last save to a new
+//! incremental file. Once the incremental files add up to more than the
+//! [compaction threshold](FsStore::with_compaction_threshold) the whole document is written to
+//! a new snapshot and the incremental files are deleted. [`FsStore::load()`] combines the files
+//! using [`Automerge::load()`] and [`Automerge::load_incremental()`].
+//!
+//! Every file is written to a temporary file, flushed to disk and then atomically renamed into
+//! place, so a crash never leaves a partially written file behind. A crash during compaction
+//! can leave incremental files whose changes are also in the snapshot, loading these again is
+//! harmless.
+//!
+//! ```no_run
+//! use automerge::{fs_store::FsStore, transaction::Transactable, ROOT};
+//! # fn main() -> Result<(), automerge::fs_store::FsStoreError> {
+//! let mut store = FsStore::open("my-doc")?;
+//! let mut doc = store.load()?;
+//! let mut tx = doc.transaction();
+//! tx.put(ROOT, "key", "value")?;
+//! tx.commit();
+//! store.save(&doc)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{Automerge, AutomergeError, ChangeHash};
+
+const SNAPSHOT_FILE: &str = "snapshot.automerge";
+const INCREMENTAL_PREFIX: &str = "incremental-";
+const EXTENSION: &str = ".automerge";
+const TMP_EXTENSION: &str = ".tmp";
+
+/// The default size of the incremental files in bytes above which the store compacts
+pub const DEFAULT_COMPACTION_THRESHOLD: u64 = 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FsStoreError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+}
+
+/// A document stored as a snapshot and append-only incremental files in a directory
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug)]
+pub struct FsStore {
+    dir: PathBuf,
+    compaction_threshold: u64,
+    /// The indices of the incremental files on disk, in ascending order
+    incrementals: Vec<u64>,
+    incremental_bytes: u64,
+    /// The heads of the document as of the last load or save
+    heads: Vec<ChangeHash>,
+}
+
+impl FsStore {
+    /// Open the store in `dir`, creating the directory if it doesn't exist
+    ///
+    /// Temporary files left behind by an interrupted write are removed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, FsStoreError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut incrementals = Vec::new();
+        let mut incremental_bytes = 0;
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name.ends_with(TMP_EXTENSION) {
+                tracing::debug!(file = name, "removing incomplete temporary file");
+                fs::remove_file(entry.path())?;
+            } else if let Some(index) = parse_incremental_name(name) {
+                incrementals.push(index);
+                incremental_bytes += entry.metadata()?.len();
+            }
+        }
+        incrementals.sort_unstable();
+        Ok(Self {
+            dir,
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+            incrementals,
+            incremental_bytes,
+            heads: Vec::new(),
+        })
+    }
+
+    /// Compact once the incremental files add up to more than `bytes`
+    pub fn with_compaction_threshold(mut self, bytes: u64) -> Self {
+        self.compaction_threshold = bytes;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The number of incremental files which have not been compacted yet
+    pub fn num_incrementals(&self) -> usize {
+        self.incrementals.len()
+    }
+
+    /// Load the document from the snapshot and incremental files
+    ///
+    /// If the store is empty this returns an empty document.
+    pub fn load(&mut self) -> Result<Automerge, FsStoreError> {
+        let mut doc = match fs::read(self.dir.join(SNAPSHOT_FILE)) {
+            Ok(bytes) => Automerge::load(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Automerge::new(),
+            Err(e) => return Err(e.into()),
+        };
+        for index in &self.incrementals {
+            let bytes = fs::read(self.dir.join(incremental_name(*index)))?;
+            doc.load_incremental(&bytes)?;
+        }
+        self.heads = doc.get_heads();
+        Ok(doc)
+    }
+
+    /// Save the changes made to `doc` since the last load or save
+    ///
+    /// This appends a new incremental file, compacting if the incremental files have grown
+    /// beyond the compaction threshold. If there are no new changes this does nothing.
+    pub fn save(&mut self, doc: &Automerge) -> Result<(), FsStoreError> {
+        let heads = doc.get_heads();
+        if heads == self.heads {
+            return Ok(());
+        }
+        let bytes = doc.save_after(&self.heads);
+        let index = self.incrementals.last().map(|i| i + 1).unwrap_or(1);
+        self.write_atomic(&incremental_name(index), &bytes)?;
+        self.incrementals.push(index);
+        self.incremental_bytes += bytes.len() as u64;
+        self.heads = heads;
+        if self.incremental_bytes > self.compaction_threshold {
+            self.compact(doc)?;
+        }
+        Ok(())
+    }
+
+    /// Write the whole of `doc` to a new snapshot and delete the incremental files
+    pub fn compact(&mut self, doc: &Automerge) -> Result<(), FsStoreError> {
+        self.write_atomic(SNAPSHOT_FILE, &doc.save())?;
+        for index in self.incrementals.drain(..) {
+            fs::remove_file(self.dir.join(incremental_name(index)))?;
+        }
+        sync_dir(&self.dir)?;
+        self.incremental_bytes = 0;
+        self.heads = doc.get_heads();
+        Ok(())
+    }
+
+    fn write_atomic(&self, name: &str, bytes: &[u8]) -> Result<(), io::Error> {
+        let path = self.dir.join(name);
+        let tmp_path = self.dir.join(format!("{}{}", name, TMP_EXTENSION));
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp_path, &path)?;
+        sync_dir(&self.dir)
+    }
+}
+
+fn incremental_name(index: u64) -> String {
+    format!("{}{:020}{}", INCREMENTAL_PREFIX, index, EXTENSION)
+}
+
+fn parse_incremental_name(name: &str) -> Option<u64> {
+    name.strip_prefix(INCREMENTAL_PREFIX)?
+        .strip_suffix(EXTENSION)?
+        .parse()
+        .ok()
+}
+
+/// Flush a directory so that renames and deletions within it are durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<(), io::Error> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<(), io::Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{transaction::Transactable, ReadDoc, ROOT};
+
+    fn put(doc: &mut Automerge, key: &str, value: i64) {
+        let mut tx = doc.transaction();
+        tx.put(ROOT, key, value).unwrap();
+        tx.commit();
+    }
+
+    #[test]
+    fn save_and_load_incrementals() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let mut doc = store.load().unwrap();
+        assert_eq!(doc.get_heads(), vec![]);
+
+        put(&mut doc, "a", 1);
+        store.save(&doc).unwrap();
+        put(&mut doc, "b", 2);
+        store.save(&doc).unwrap();
+        // Nothing changed so nothing is written
+        store.save(&doc).unwrap();
+        assert_eq!(store.num_incrementals(), 2);
+
+        let mut reopened = FsStore::open(dir.path()).unwrap();
+        assert_eq!(reopened.num_incrementals(), 2);
+        let loaded = reopened.load().unwrap();
+        assert_eq!(loaded.get_heads(), doc.get_heads());
+        assert_eq!(loaded.save(), doc.save());
+
+        // Saving after loading only writes the new changes
+        let mut loaded = loaded;
+        put(&mut loaded, "c", 3);
+        reopened.save(&loaded).unwrap();
+        assert_eq!(reopened.num_incrementals(), 3);
+        let loaded = FsStore::open(dir.path()).unwrap().load().unwrap();
+        assert_eq!(loaded.get(ROOT, "c").unwrap().unwrap().0.to_i64(), Some(3));
+    }
+
+    #[test]
+    fn compacts_above_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FsStore::open(dir.path())
+            .unwrap()
+            .with_compaction_threshold(200);
+        let mut doc = Automerge::new();
+        let mut compacted = false;
+        for i in 0..20 {
+            put(&mut doc, &format!("key{}", i), i);
+            store.save(&doc).unwrap();
+            compacted |= store.num_incrementals() == 0;
+        }
+        assert!(compacted);
+        assert!(dir.path().join(SNAPSHOT_FILE).exists());
+
+        let loaded = FsStore::open(dir.path()).unwrap().load().unwrap();
+        assert_eq!(loaded.get_heads(), doc.get_heads());
+        assert_eq!(loaded.save(), doc.save());
+    }
+
+    #[test]
+    fn recovers_from_interrupted_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let mut doc = Automerge::new();
+        put(&mut doc, "a", 1);
+        store.save(&doc).unwrap();
+
+        // A crash during a write leaves a temporary file behind
+        fs::write(
+            dir.path()
+                .join(format!("{}{}", incremental_name(2), TMP_EXTENSION)),
+            b"garbage",
+        )
+        .unwrap();
+        // A crash during compaction leaves incrementals which are also in the snapshot
+        fs::write(dir.path().join(SNAPSHOT_FILE), doc.save()).unwrap();
+
+        let mut store = FsStore::open(dir.path()).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.get_heads(), doc.get_heads());
+    }
+}
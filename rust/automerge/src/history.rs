@@ -0,0 +1,256 @@
+//! # Iterating over the history of a document
+//!
+//! [`Automerge::iter_history()`](crate::Automerge::iter_history) returns a [`HistoryIter`] which
+//! iterates over the changes in a document in causal order, decoding each change only when it is
+//! reached. Changes can be filtered by the actor which made them, by their timestamp and by the
+//! objects they touch.
+//!
+//! ```
+//! use automerge::{transaction::Transactable, AutoCommit, ObjType, OpType, Prop, ROOT};
+//! let mut doc = AutoCommit::new();
+//! let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+//! doc.commit();
+//! doc.insert(&list, 0, "a").unwrap();
+//! doc.insert(&list, 1, "b").unwrap();
+//! doc.commit();
+//!
+//! let entries = doc.iter_history().touching(list.clone()).collect::<Vec<_>>();
+//! assert_eq!(entries.len(), 1);
+//! let ops = entries[0].ops();
+//! assert_eq!(ops[1].obj, list);
+//! assert_eq!(ops[1].prop, Prop::Seq(1));
+//! assert_eq!(ops[1].action, OpType::Put("b".into()));
+//! ```
+
+use std::ops::{Bound, RangeBounds};
+
+use crate::{
+    change_store,
+    clock::Clock,
+    columnar::Key as EncodedKey,
+    exid::ExId,
+    patches::TextRepresentation,
+    types::{self, ElemId, OpId},
+    ActorId, Automerge, Change, ObjId, OpType, Prop,
+};
+
+/// An iterator over the changes in a document, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct HistoryIter<'a> {
+    doc: &'a Automerge,
+    changes: change_store::Changes<'a>,
+    actors: Option<Vec<ActorId>>,
+    time: (Bound<i64>, Bound<i64>),
+    objects: Option<Vec<types::ObjId>>,
+}
+
+impl<'a> HistoryIter<'a> {
+    pub(crate) fn new(doc: &'a Automerge, changes: change_store::Changes<'a>) -> Self {
+        Self {
+            doc,
+            changes,
+            actors: None,
+            time: (Bound::Unbounded, Bound::Unbounded),
+            objects: None,
+        }
+    }
+
+    /// Only include changes made by `actor`
+    ///
+    /// Calling this more than once includes changes made by any of the actors.
+    pub fn by_actor(mut self, actor: ActorId) -> Self {
+        self.actors.get_or_insert_with(Vec::new).push(actor);
+        self
+    }
+
+    /// Only include changes whose [`Change::timestamp()`] is in `range`
+    pub fn in_time_range<R: RangeBounds<i64>>(mut self, range: R) -> Self {
+        self.time = (range.start_bound().cloned(), range.end_bound().cloned());
+        self
+    }
+
+    /// Only include changes which contain an operation on `obj`
+    ///
+    /// Calling this more than once includes changes which touch any of the objects. Objects which
+    /// aren't in the document are never touched.
+    pub fn touching(mut self, obj: ObjId) -> Self {
+        let objects = self.objects.get_or_insert_with(Vec::new);
+        if let Ok(obj) = self.doc.exid_to_obj(&obj) {
+            objects.push(obj.id);
+        }
+        self
+    }
+
+    fn includes(&self, entry: &HistoryEntry<'a>) -> bool {
+        if let Some(actors) = &self.actors {
+            if !actors.contains(entry.change.actor_id()) {
+                return false;
+            }
+        }
+        if !self.time.contains(&entry.change.timestamp()) {
+            return false;
+        }
+        if let Some(objects) = &self.objects {
+            return entry.decode().any(|(obj, _)| objects.contains(&obj));
+        }
+        true
+    }
+}
+
+impl<'a> Iterator for HistoryIter<'a> {
+    type Item = HistoryEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = HistoryEntry {
+                doc: self.doc,
+                change: self.changes.next()?,
+            };
+            if self.includes(&entry) {
+                return Some(entry);
+            }
+        }
+    }
+}
+
+/// A change in the history of a document
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryEntry<'a> {
+    doc: &'a Automerge,
+    change: &'a Change,
+}
+
+impl<'a> HistoryEntry<'a> {
+    /// The change itself
+    pub fn change(&self) -> &'a Change {
+        self.change
+    }
+
+    /// The operations in the change, with the ids in them resolved against the document
+    pub fn ops(&self) -> Vec<HistoryOp> {
+        // Sequence indices are resolved as of just after this change
+        let mut clock: Option<Clock> = None;
+        let actor = actor_index(self.doc, self.change.actor_id());
+        self.decode()
+            .enumerate()
+            .map(|(i, (obj, op))| {
+                let id = OpId::new(self.change.start_op().get() + i as u64, actor);
+                let prop = match op.key {
+                    DecodedKey::Map(prop) => Prop::Map(prop),
+                    DecodedKey::Seq(elem) => {
+                        let elem = if op.insert { id } else { elem.0 };
+                        let clock =
+                            clock.get_or_insert_with(|| self.doc.clock_at(&[self.change.hash()]));
+                        Prop::Seq(self.index_of(obj, elem, clock))
+                    }
+                };
+                HistoryOp {
+                    id: self.doc.id_to_exid(id),
+                    obj: self.doc.id_to_exid(obj.0),
+                    prop,
+                    insert: op.insert,
+                    action: op.action,
+                    pred: op
+                        .pred
+                        .into_iter()
+                        .map(|p| self.doc.id_to_exid(p))
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// The objects and ops in the change, with op ids translated to the actor indices of the
+    /// document
+    fn decode(&self) -> impl Iterator<Item = (types::ObjId, DecodedOp)> + 'a {
+        let doc = self.doc;
+        let actors = std::iter::once(self.change.actor_id())
+            .chain(self.change.other_actor_ids())
+            .map(|a| actor_index(doc, a))
+            .collect::<Vec<_>>();
+        self.change.iter_ops().map(move |c| {
+            let key = match &c.key {
+                EncodedKey::Prop(n) => DecodedKey::Map(n.to_string()),
+                EncodedKey::Elem(e) if e.is_head() => DecodedKey::Seq(ElemId::head()),
+                EncodedKey::Elem(ElemId(o)) => {
+                    DecodedKey::Seq(ElemId(OpId::new(o.counter(), actors[o.actor()])))
+                }
+            };
+            let obj = if c.obj.is_root() {
+                types::ObjId::root()
+            } else {
+                types::ObjId(OpId::new(
+                    c.obj.opid().counter(),
+                    actors[c.obj.opid().actor()],
+                ))
+            };
+            let pred = c
+                .pred
+                .iter()
+                .map(|p| OpId::new(p.counter(), actors[p.actor()]))
+                .collect();
+            let op = DecodedOp {
+                key,
+                insert: c.insert,
+                action: OpType::from_action_and_value(c.action, c.val, c.mark_name, c.expand),
+                pred,
+            };
+            (obj, op)
+        })
+    }
+
+    /// The index of `elem` in `obj` when the document is at `clock`
+    ///
+    /// Elements which aren't visible at `clock` are given the index they would have if they were.
+    fn index_of(&self, obj: types::ObjId, elem: OpId, clock: &Clock) -> usize {
+        let encoding = self
+            .doc
+            .ops()
+            .object_type(&obj)
+            .map(|typ| TextRepresentation::String.encoding(typ))
+            .unwrap_or_default();
+        self.doc
+            .ops()
+            .seek_list_opid(&obj, elem, encoding, Some(clock))
+            .map(|found| found.index)
+            .unwrap_or(0)
+    }
+}
+
+/// Every actor in a change which has been applied to a document is cached by the document
+fn actor_index(doc: &Automerge, actor: &ActorId) -> usize {
+    doc.osd()
+        .actors
+        .lookup(actor)
+        .expect("actors of applied changes should be cached")
+}
+
+struct DecodedOp {
+    key: DecodedKey,
+    insert: bool,
+    action: OpType,
+    pred: Vec<OpId>,
+}
+
+enum DecodedKey {
+    Map(String),
+    Seq(ElemId),
+}
+
+/// An operation in a [`HistoryEntry`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryOp {
+    /// The id of the operation
+    pub id: ExId,
+    /// The object the operation modifies
+    pub obj: ObjId,
+    /// The key of the operation in a map, or the index of the element it creates or modifies in a
+    /// sequence as of just after the change was applied
+    pub prop: Prop,
+    /// Whether the operation inserts a new element into a sequence
+    pub insert: bool,
+    /// What the operation does
+    pub action: OpType,
+    /// The operations this operation overwrites or deletes
+    pub pred: Vec<ExId>,
+}
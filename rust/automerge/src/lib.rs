@@ -201,6 +201,15 @@
 //! an API for allowing automerge to do the index translations for you. Cursors
 //! are created with [`ReadDoc::get_cursor()`] and dereferenced with
 //! [`ReadDoc::get_cursor_position()`].
+//!
+//! ## Persisting vector clocks, redaction records and branches
+//!
+//! [`VectorClock::to_bytes()`], [`RedactionRecord::to_bytes()`] and
+//! [`AutoCommit::save_branches()`] share one format: a version byte, currently always `0`,
+//! followed by the fields of the value. Integers and the lengths of variable length fields are
+//! uLEB encoded and change hashes are their 32 bytes. Bytes written by one version of this library
+//! can be read by any later version with the same major version, an incompatible change to the
+//! format is a breaking change and comes with a new version byte.
 
 #![doc(
     html_logo_url = "https://raw.githubusercontent.com/automerge/automerge/main/img/brandmark.svg",
@@ -261,52 +270,64 @@ mod automerge;
 mod autoserde;
 mod change;
 mod change_graph;
+pub mod change_store;
 mod clock;
 mod columnar;
 mod convert;
 mod cursor;
 pub mod error;
 mod exid;
+pub mod fs_store;
+pub mod history;
 pub mod hydrate;
 mod indexed_cache;
 pub mod iter;
 mod legacy;
 pub mod marks;
+mod merge_preview;
 mod op_set;
 pub mod op_tree;
 mod parents;
 pub mod patches;
 mod query;
 mod read;
+mod redaction;
 mod sequence_tree;
 mod storage;
+pub mod storage_stats;
 pub mod sync;
 mod text_diff;
 mod text_value;
 pub mod transaction;
 mod types;
 mod value;
+mod vector_clock;
 #[cfg(feature = "optree-visualisation")]
 mod visualisation;
 
+pub use crate::automerge::diff::ReadDocAt;
 pub use crate::automerge::{Automerge, LoadOptions, OnPartialLoad, SaveOptions, StringMigration};
-pub use autocommit::AutoCommit;
+pub use autocommit::{AutoCommit, BranchesFromBytesError};
 pub use autoserde::AutoSerde;
 pub use change::{Change, LoadError as LoadChangeError};
+pub use change_graph::CausalOrder;
 pub use cursor::Cursor;
 pub use error::AutomergeError;
 pub use error::InvalidActorId;
 pub use error::InvalidChangeHashSlice;
 pub use exid::{ExId as ObjId, ObjIdFromBytesError};
 pub use legacy::Change as ExpandedChange;
+pub use merge_preview::{MergeConflict, MergePreview};
 pub use parents::{Parent, Parents};
 pub use patches::{Patch, PatchAction, PatchLog};
 pub use read::ReadDoc;
+pub use redaction::{RedactCandidate, RedactionRecord, RedactionRecordFromBytesError};
 pub use sequence_tree::SequenceTree;
-pub use storage::VerificationMode;
+pub use storage::{Compression, RepairReport, VerificationMode};
 pub use transaction::BlockOrText;
 pub use types::{ActorId, ChangeHash, ObjType, OpType, ParseChangeHashError, Prop};
 pub use value::{ScalarValue, Value};
+pub use vector_clock::{ReadAt, VectorClock, VectorClockFromBytesError};
 
 /// The object ID for the root map of a document
 pub const ROOT: ObjId = ObjId::Root;
@@ -0,0 +1,23 @@
+use crate::{ObjId, Patch, Prop, Value};
+
+/// What merging some changes into a document would do, see
+/// [`Automerge::preview_merge()`](crate::Automerge::preview_merge)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergePreview {
+    /// The patches which merging would produce
+    pub patches: Vec<Patch>,
+    /// The conflicts which merging would introduce
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// A property which would be in conflict after a merge
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+    /// The object containing the property
+    pub obj: ObjId,
+    /// The conflicted property, for sequences this is the index after the merge
+    pub prop: Prop,
+    /// All of the conflicting values after the merge, as [`ReadDoc::get_all()`](crate::ReadDoc::get_all)
+    /// would return them
+    pub values: Vec<(Value<'static>, ObjId)>,
+}
@@ -6,8 +6,7 @@ use crate::iter::{Keys, ListRange, MapRange, TopOps};
 use crate::marks::MarkSet;
 use crate::op_tree::OpTreeIter;
 use crate::op_tree::{
-    self, FoundOpId, FoundOpWithPatchLog, FoundOpWithoutPatchLog, LastInsert, OpTree,
-    OpTreeInternal, OpsFound,
+    self, FoundOpId, FoundOpWithPatchLog, FoundOpWithoutPatchLog, LastInsert, OpTree, OpsFound,
 };
 use crate::parents::Parents;
 use crate::patches::TextRepresentation;
@@ -22,7 +21,7 @@ use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::ops::RangeBounds;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
 
 mod op;
 
@@ -45,7 +44,9 @@ impl OpIdxRange {
 #[derive(Debug, Clone)]
 pub(crate) struct OpSetInternal {
     /// The map of objects to their type and ops.
-    trees: HashMap<ObjId, OpTree, FxBuildHasher>,
+    ///
+    /// Trees are shared between clones of the opset until one of the clones modifies them.
+    trees: HashMap<ObjId, Arc<LazyOpTree>, FxBuildHasher>,
     /// The number of operations in the opset.
     length: usize,
     /// Metadata about the operations in this opset.
@@ -55,7 +56,7 @@ pub(crate) struct OpSetInternal {
 impl OpSetInternal {
     pub(crate) fn from_actors(actors: Vec<ActorId>) -> Self {
         let mut trees: HashMap<_, _, _> = Default::default();
-        trees.insert(ObjId::root(), OpTree::new(ObjType::Map));
+        trees.insert(ObjId::root(), Arc::new(LazyOpTree::new(ObjType::Map, None)));
         OpSetInternal {
             trees,
             length: 0,
@@ -65,7 +66,7 @@ impl OpSetInternal {
 
     pub(crate) fn new() -> Self {
         let mut trees: HashMap<_, _, _> = Default::default();
-        trees.insert(ObjId::root(), OpTree::new(ObjType::Map));
+        trees.insert(ObjId::root(), Arc::new(LazyOpTree::new(ObjType::Map, None)));
         OpSetInternal {
             trees,
             length: 0,
@@ -91,7 +92,11 @@ impl OpSetInternal {
     }
 
     pub(crate) fn iter(&self) -> Iter<'_> {
-        let mut objs: Vec<_> = self.trees.iter().map(|t| (t.0, t.1.objtype, t.1)).collect();
+        let mut objs: Vec<_> = self
+            .trees
+            .iter()
+            .map(|t| (t.0, t.1.objtype, t.1.get(&self.osd)))
+            .collect();
         objs.sort_by(|a, b| self.osd.lamport_cmp((a.0).0, (b.0).0));
         Iter {
             opset: self,
@@ -102,7 +107,7 @@ impl OpSetInternal {
     }
 
     pub(crate) fn iter_obj(&self, obj: &ObjId) -> Option<OpTreeIter<'_>> {
-        self.trees.get(obj).map(|t| t.iter())
+        self.tree(obj).map(|t| t.iter())
     }
 
     /// Iterate over objects in the opset in causal order
@@ -111,7 +116,7 @@ impl OpSetInternal {
         let mut objs: Vec<_> = self
             .trees
             .iter()
-            .map(|t| (ObjMeta::new(*t.0, t.1.objtype), t.1))
+            .map(|t| (ObjMeta::new(*t.0, t.1.objtype), t.1.get(&self.osd)))
             .collect();
         objs.sort_by(|a, b| self.osd.lamport_cmp((a.0).id, (b.0).id));
         IterObjs {
@@ -120,8 +125,7 @@ impl OpSetInternal {
     }
 
     pub(crate) fn iter_ops(&self, obj: &ObjId) -> impl Iterator<Item = Op<'_>> {
-        self.trees
-            .get(obj)
+        self.tree(obj)
             .map(|o| o.iter())
             .into_iter()
             .flatten()
@@ -150,7 +154,7 @@ impl OpSetInternal {
     ) -> Option<FoundOpId<'_>> {
         let obj = idx.as_op(&self.osd).obj();
         let typ = self.obj_type(obj)?;
-        self.trees.get(obj).and_then(|tree| {
+        self.tree(obj).and_then(|tree| {
             tree.internal
                 .seek_idx(idx, text_rep.encoding(typ), clock, &self.osd)
         })
@@ -163,8 +167,7 @@ impl OpSetInternal {
         encoding: ListEncoding,
         clock: Option<&Clock>,
     ) -> Option<FoundOpId<'_>> {
-        self.trees
-            .get(obj)
+        self.tree(obj)
             .and_then(|tree| tree.internal.seek_list_opid(id, encoding, clock, &self.osd))
     }
 
@@ -195,8 +198,7 @@ impl OpSetInternal {
         encoding: ListEncoding,
         clock: Option<&Clock>,
     ) -> OpsFound<'a> {
-        self.trees
-            .get(obj)
+        self.tree(obj)
             .and_then(|tree| {
                 tree.internal
                     .seek_ops_by_prop(&self.osd, prop, encoding, clock)
@@ -205,7 +207,7 @@ impl OpSetInternal {
     }
 
     pub(crate) fn op_iter<'a>(&'a self, obj: &ObjId) -> Option<OpIter<'a>> {
-        self.trees.get(obj).map(|tree| OpIter {
+        self.tree(obj).map(|tree| OpIter {
             iter: tree.iter(),
             osd: &self.osd,
         })
@@ -224,7 +226,7 @@ impl OpSetInternal {
         op: Op<'a>,
         pred: &OpIds,
     ) -> FoundOpWithPatchLog<'a> {
-        if let Some(tree) = self.trees.get(&obj.id) {
+        if let Some(tree) = self.tree(&obj.id) {
             tree.internal
                 .find_op_with_patch_log(op, pred, encoding, &self.osd)
         } else {
@@ -238,7 +240,7 @@ impl OpSetInternal {
         op: Op<'_>,
         pred: &OpIds,
     ) -> FoundOpWithoutPatchLog {
-        if let Some(tree) = self.trees.get(obj) {
+        if let Some(tree) = self.tree(obj) {
             tree.internal.find_op_without_patch_log(op, pred, &self.osd)
         } else {
             Default::default()
@@ -249,7 +251,7 @@ impl OpSetInternal {
     where
         Q: TreeQuery<'a>,
     {
-        if let Some(tree) = self.trees.get(obj) {
+        if let Some(tree) = self.tree(obj) {
             if query.can_shortcut_search(tree, &self.osd) {
                 query
             } else {
@@ -262,7 +264,11 @@ impl OpSetInternal {
 
     /// Add `op` as a successor to each op at `op_indices` in `obj`
     pub(crate) fn add_succ(&mut self, obj: &ObjId, op_indices: &[usize], op: OpIdx) {
-        if let Some(tree) = self.trees.get_mut(obj) {
+        if let Some(tree) = self
+            .trees
+            .get_mut(obj)
+            .map(|t| Arc::make_mut(t).get_mut(&self.osd))
+        {
             tree.last_insert = None;
             for i in op_indices {
                 if let Some(idx) = tree.internal.get(*i) {
@@ -285,7 +291,11 @@ impl OpSetInternal {
     }
 
     pub(crate) fn remove_succ(&mut self, obj: &ObjId, index: usize, op: OpIdx) {
-        if let Some(tree) = self.trees.get_mut(obj) {
+        if let Some(tree) = self
+            .trees
+            .get_mut(obj)
+            .map(|t| Arc::make_mut(t).get_mut(&self.osd))
+        {
             tree.last_insert = None;
             if let Some(idx) = tree.internal.get(index) {
                 let old_vis = idx.as_op(&self.osd).visible();
@@ -307,7 +317,7 @@ impl OpSetInternal {
 
     pub(crate) fn remove(&mut self, obj: &ObjId, index: usize) {
         // this happens on rollback - be sure to go back to the old state
-        let tree = self.trees.get_mut(obj).unwrap();
+        let tree = Arc::make_mut(self.trees.get_mut(obj).unwrap()).get_mut(&self.osd);
         self.length -= 1;
         tree.last_insert = None;
         let idx = tree.internal.remove(index, &self.osd);
@@ -330,7 +340,11 @@ impl OpSetInternal {
         key: Key,
         marks: Option<Arc<MarkSet>>,
     ) {
-        if let Some(tree) = self.trees.get_mut(obj) {
+        if let Some(tree) = self
+            .trees
+            .get_mut(obj)
+            .map(|t| Arc::make_mut(t).get_mut(&self.osd))
+        {
             tree.last_insert = Some(LastInsert {
                 index,
                 pos,
@@ -360,7 +374,7 @@ impl OpSetInternal {
     pub(crate) fn add_indexes(&mut self) {
         for (_, tree) in self.trees.iter_mut() {
             if tree.objtype.is_sequence() {
-                tree.add_index(&self.osd)
+                Arc::make_mut(tree).get_mut(&self.osd).add_index(&self.osd)
             }
         }
     }
@@ -369,18 +383,15 @@ impl OpSetInternal {
     pub(crate) fn insert(&mut self, index: usize, obj: &ObjId, idx: OpIdx) {
         let op = idx.as_op(&self.osd);
         if let OpType::Make(typ) = op.action() {
-            self.trees.insert(
-                op.id().into(),
-                OpTree {
-                    internal: OpTreeInternal::new(*typ),
-                    objtype: *typ,
-                    last_insert: None,
-                    parent: Some(idx),
-                },
-            );
+            self.trees
+                .insert(op.id().into(), Arc::new(LazyOpTree::new(*typ, Some(idx))));
         }
 
-        if let Some(tree) = self.trees.get_mut(obj) {
+        if let Some(tree) = self
+            .trees
+            .get_mut(obj)
+            .map(|t| Arc::make_mut(t).get_mut(&self.osd))
+        {
             tree.last_insert = None;
             tree.internal.insert(index, idx, &self.osd);
             self.length += 1;
@@ -392,18 +403,15 @@ impl OpSetInternal {
     pub(crate) fn load_idx(&mut self, obj: &ObjId, idx: OpIdx) -> Result<(), AutomergeError> {
         let op = idx.as_op(&self.osd);
         if let OpType::Make(typ) = op.action() {
-            self.trees.insert(
-                op.id().into(),
-                OpTree {
-                    internal: OpTreeInternal::new(*typ),
-                    objtype: *typ,
-                    last_insert: None,
-                    parent: Some(idx),
-                },
-            );
+            self.trees
+                .insert(op.id().into(), Arc::new(LazyOpTree::new(*typ, Some(idx))));
         }
 
-        if let Some(tree) = self.trees.get_mut(obj) {
+        if let Some(tree) = self
+            .trees
+            .get_mut(obj)
+            .map(|t| Arc::make_mut(t).get_mut(&self.osd))
+        {
             tree.last_insert = None;
             tree.internal.insert(tree.len(), idx, &self.osd);
             self.length += 1;
@@ -413,6 +421,81 @@ impl OpSetInternal {
         }
     }
 
+    /// Like [`Self::load_idx()`] but defer inserting the op into the tree of `obj` until the
+    /// tree is first accessed
+    pub(crate) fn load_idx_lazy(&mut self, obj: &ObjId, idx: OpIdx) -> Result<(), AutomergeError> {
+        let op = idx.as_op(&self.osd);
+        if let OpType::Make(typ) = op.action() {
+            self.trees
+                .insert(op.id().into(), Arc::new(LazyOpTree::new(*typ, Some(idx))));
+        }
+
+        if let Some(tree) = self.trees.get_mut(obj) {
+            Arc::make_mut(tree).defer(idx, &self.osd);
+            self.length += 1;
+            Ok(())
+        } else {
+            Err(AutomergeError::NotAnObject)
+        }
+    }
+
+    /// Build the tree of every object which was loaded lazily and not yet accessed
+    pub(crate) fn materialize_all(&mut self) {
+        #[cfg(feature = "parallel")]
+        self.par_materialize_all();
+        #[cfg(not(feature = "parallel"))]
+        for tree in self.trees.values_mut() {
+            Arc::make_mut(tree).get_mut(&self.osd);
+        }
+    }
+
+    /// Like [`Self::materialize_all()`] but build each tree on the rayon thread pool
+    #[cfg(feature = "parallel")]
+    pub(crate) fn par_materialize_all(&mut self) {
+        use rayon::prelude::*;
+        let osd = &self.osd;
+        self.trees.par_iter_mut().for_each(|(_, tree)| {
+            Arc::make_mut(tree).get_mut(osd);
+        });
+    }
+
+    /// A representation of the trees and ops in this opset which doesn't depend on the order of
+    /// the hash maps in the property and actor caches
+    #[cfg(all(test, feature = "parallel"))]
+    pub(crate) fn debug_structure(&self) -> String {
+        format!(
+            "{:?} {:?} {:?} {:?} {:?}",
+            self.trees, self.osd.actors.cache, self.osd.props.cache, self.osd.ops, self.osd.op_deps
+        )
+    }
+
+    #[cfg(test)]
+    pub(crate) fn num_materialized(&self) -> usize {
+        self.trees
+            .values()
+            .filter(|t| t.tree.get().is_some())
+            .count()
+    }
+
+    /// Whether `self` and `other` share the op tree of `obj` rather than each having a copy
+    #[cfg(test)]
+    pub(crate) fn shares_tree(&self, other: &Self, obj: &ObjId) -> bool {
+        match (self.trees.get(obj), other.trees.get(obj)) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// The number of ops which have been loaded but not yet inserted into a tree
+    #[cfg(test)]
+    pub(crate) fn num_pending(&self) -> usize {
+        self.trees.values().map(|t| t.lock_pending().len()).sum()
+    }
+
+    fn tree(&self, obj: &ObjId) -> Option<&OpTree> {
+        self.trees.get(obj).map(|t| t.get(&self.osd))
+    }
+
     pub(crate) fn object_type(&self, id: &ObjId) -> Option<ObjType> {
         self.trees.get(id).map(|tree| tree.objtype)
     }
@@ -430,16 +513,13 @@ impl OpSetInternal {
     ///            visualised
     #[cfg(feature = "optree-visualisation")]
     pub(crate) fn visualise(&self, objects: Option<Vec<ObjId>>) -> String {
-        use std::borrow::Cow;
         let mut out = Vec::new();
-        let trees = if let Some(objects) = objects {
-            let mut filtered = self.trees.clone();
-            filtered.retain(|k, _| objects.contains(k));
-            Cow::Owned(filtered)
-        } else {
-            Cow::Borrowed(&self.trees)
-        };
-        let graph = super::visualisation::GraphVisualisation::construct(&trees, &self.osd);
+        let trees = self
+            .trees
+            .iter()
+            .filter(|(k, _)| objects.as_ref().map_or(true, |o| o.contains(k)))
+            .map(|(k, t)| (k, t.get(&self.osd)));
+        let graph = super::visualisation::GraphVisualisation::construct(trees, &self.osd);
         dot::render(&graph, &mut out).unwrap();
         String::from_utf8_lossy(&out[..]).to_string()
     }
@@ -450,7 +530,7 @@ impl OpSetInternal {
         encoding: ListEncoding,
         clock: Option<Clock>,
     ) -> usize {
-        if let Some(tree) = self.trees.get(obj) {
+        if let Some(tree) = self.tree(obj) {
             match (&clock, tree.index(encoding)) {
                 // no clock and a clean index? - use it
                 (None, Some(index)) => index.visible_len(encoding),
@@ -850,6 +930,83 @@ impl OpSetData {
     }
 }
 
+/// The [`OpTree`] of an object, which may not have been built yet
+///
+/// When a document is loaded lazily the ops of each object are decoded up front but only
+/// inserted into the object's tree the first time the tree is accessed.
+#[derive(Debug)]
+struct LazyOpTree {
+    objtype: ObjType,
+    parent: Option<OpIdx>,
+    /// Ops which have been loaded but not yet inserted into the tree, in tree order. This is
+    /// emptied when the tree is built, which can happen through a shared reference.
+    pending: Mutex<Vec<OpIdx>>,
+    tree: OnceLock<OpTree>,
+}
+
+impl Clone for LazyOpTree {
+    fn clone(&self) -> Self {
+        Self {
+            objtype: self.objtype,
+            parent: self.parent,
+            pending: Mutex::new(self.lock_pending().clone()),
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+impl LazyOpTree {
+    fn new(objtype: ObjType, parent: Option<OpIdx>) -> Self {
+        Self {
+            objtype,
+            parent,
+            pending: Mutex::new(Vec::new()),
+            tree: OnceLock::new(),
+        }
+    }
+
+    fn defer(&mut self, idx: OpIdx, osd: &OpSetData) {
+        match self.tree.get_mut() {
+            Some(tree) => tree.internal.insert(tree.len(), idx, osd),
+            None => self
+                .pending
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(idx),
+        }
+    }
+
+    fn get(&self, osd: &OpSetData) -> &OpTree {
+        self.tree.get_or_init(|| self.build(osd))
+    }
+
+    fn get_mut(&mut self, osd: &OpSetData) -> &mut OpTree {
+        if self.tree.get().is_none() {
+            let tree = self.build(osd);
+            let _ = self.tree.set(tree);
+        }
+        self.tree.get_mut().unwrap()
+    }
+
+    fn lock_pending(&self) -> std::sync::MutexGuard<'_, Vec<OpIdx>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Build the tree from the pending ops, leaving `pending` empty
+    fn build(&self, osd: &OpSetData) -> OpTree {
+        let pending = std::mem::take(&mut *self.lock_pending());
+        let mut tree = OpTree::new(self.objtype);
+        tree.parent = self.parent;
+        for idx in pending {
+            tree.internal.insert(tree.len(), idx, osd);
+        }
+        if self.objtype.is_sequence() {
+            tree.add_index(osd);
+        }
+        tree
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Parent {
     pub(crate) obj: ObjId,
@@ -26,7 +26,6 @@ pub use node::B;
 #[derive(Debug, Clone)]
 pub(crate) struct OpTree {
     pub(crate) internal: OpTreeInternal,
-    pub(crate) objtype: ObjType,
     /// The id of the parent object, root has no parent.
     pub(crate) parent: Option<OpIdx>,
     /// record the last list index and tree position
@@ -49,7 +48,6 @@ impl OpTree {
     pub(crate) fn new(objtype: ObjType) -> Self {
         Self {
             internal: OpTreeInternal::new(objtype),
-            objtype,
             parent: None,
             last_insert: None,
         }
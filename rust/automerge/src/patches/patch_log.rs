@@ -6,6 +6,7 @@ use crate::marks::{MarkAccumulator, MarkSet};
 use crate::read::ReadDocInternal;
 use crate::types::{ObjId, ObjType, OpId, Prop};
 use crate::{Automerge, ChangeHash, Patch, ReadDoc};
+use std::borrow::Cow;
 use std::collections::BTreeSet;
 use std::collections::HashSet;
 use std::sync::Arc;
@@ -323,7 +324,7 @@ impl PatchLog {
         self.events.sort_by(|a, b| doc.ops().osd.lamport_cmp(a, b));
         let expose = ExposeQueue(self.expose.iter().map(|id| doc.id_to_exid(*id)).collect());
         if let Some(heads) = self.heads.as_ref() {
-            let read_doc = ReadDocAt { doc, heads };
+            let read_doc = ReadDocAt::new(doc, Cow::Borrowed(heads));
             Self::make_patches_inner(&self.events, expose, doc, &read_doc, self.text_rep)
         } else {
             Self::make_patches_inner(&self.events, expose, doc, doc, self.text_rep)
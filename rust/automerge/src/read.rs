@@ -6,7 +6,7 @@ use crate::{
     iter::{Keys, ListRange, MapRange, Values},
     marks::{Mark, MarkSet},
     parents::Parents,
-    Change, ChangeHash, Cursor, ObjType, Prop, Value,
+    Change, ChangeHash, Cursor, ObjType, Prop, ReadAt, Value,
 };
 
 use std::{collections::HashMap, ops::RangeBounds};
@@ -16,7 +16,8 @@ use std::{collections::HashMap, ops::RangeBounds};
 /// Many of the methods on this trait have an alternate `*_at` version which
 /// takes an additional argument of `&[ChangeHash]`. This allows you to retrieve
 /// the value at a particular point in the document history identified by the
-/// given change hashes.
+/// given change hashes. A [`VectorClock`](crate::VectorClock) for the point in
+/// history can be passed instead of the hashes, see [`ReadAt`].
 pub trait ReadDoc {
     /// Get the parents of an object in the document tree.
     ///
@@ -36,10 +37,10 @@ pub trait ReadDoc {
     /// Get the parents of the object `obj` as at `heads`
     ///
     /// See [`Self::parents()`]
-    fn parents_at<O: AsRef<ExId>>(
+    fn parents_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Parents<'_>, AutomergeError>;
 
     /// Get the keys of the object `obj`.
@@ -51,7 +52,7 @@ pub trait ReadDoc {
     /// Get the keys of the object `obj` as at `heads`
     ///
     /// See [`Self::keys()`]
-    fn keys_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Keys<'_>;
+    fn keys_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Keys<'_>;
 
     /// Iterate over the keys and values of the map `obj` in the given range.
     ///
@@ -74,11 +75,11 @@ pub trait ReadDoc {
     /// third element is the ID of the operation which created the value.
     ///
     /// See [`Self::map_range()`]
-    fn map_range_at<'a, O: AsRef<ExId>, R: RangeBounds<String> + 'a>(
+    fn map_range_at<'a, 'h, O: AsRef<ExId>, R: RangeBounds<String> + 'a, H: Into<ReadAt<'h>>>(
         &'a self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> MapRange<'a, R>;
 
     /// Iterate over the indexes and values of the list or text `obj` in the given range.
@@ -97,11 +98,11 @@ pub trait ReadDoc {
     /// element is the ID of the operation which created the value.
     ///
     /// See [`Self::list_range()`]
-    fn list_range_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+    fn list_range_at<'h, O: AsRef<ExId>, R: RangeBounds<usize>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> ListRange<'_, R>;
 
     /// Iterate over the values in a map, list, or text object
@@ -116,7 +117,7 @@ pub trait ReadDoc {
     /// is the ID of the operation which created the value.
     ///
     /// See [`Self::values()`]
-    fn values_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Values<'_>;
+    fn values_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Values<'_>;
 
     /// Get the length of the given object.
     ///
@@ -128,7 +129,7 @@ pub trait ReadDoc {
     /// If the given object is not in this document this method will return `0`
     ///
     /// See [`Self::length()`]
-    fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize;
+    fn length_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> usize;
 
     /// Get the type of this object, if it is an object.
     fn object_type<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjType, AutomergeError>;
@@ -137,10 +138,10 @@ pub trait ReadDoc {
     fn marks<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<Mark<'_>>, AutomergeError>;
 
     /// Get all marks on a sequence at a given heads
-    fn marks_at<O: AsRef<ExId>>(
+    fn marks_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<Mark<'_>>, AutomergeError>;
 
     fn get_marks<O: AsRef<ExId>>(
@@ -155,20 +156,20 @@ pub trait ReadDoc {
 
     /// Get the string represented by the given text object as at `heads`, see
     /// [`Self::text()`]
-    fn text_at<O: AsRef<ExId>>(
+    fn text_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<String, AutomergeError>;
 
     /// Return the sequence of text and block markers in the text object `obj`
     fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError>;
 
     /// Return the sequence of text and block markers in the text object `obj` as at `heads`
-    fn spans_at<O: AsRef<ExId>>(
+    fn spans_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Spans<'_>, AutomergeError>;
 
     /// Obtain the stable address (Cursor) for a [`usize`] position in a Sequence (either [`ObjType::List`] or [`ObjType::Text`]).
@@ -219,11 +220,11 @@ pub trait ReadDoc {
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError>;
 
     /// Get the value of the given key as at `heads`, see [`Self::get()`]
-    fn get_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError>;
 
     fn hydrate<O: AsRef<ExId>>(
@@ -246,11 +247,11 @@ pub trait ReadDoc {
     /// Get all possibly conflicting values for a key as at `heads`
     ///
     /// See [`Self::get_all()`]
-    fn get_all_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_all_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError>;
 
     /// Get the hashes of the changes in this document that aren't transitive dependencies of the
@@ -0,0 +1,143 @@
+use crate::exid::ObjIdFromBytesError;
+use crate::storage::versioned;
+use crate::{ChangeHash, ObjId, ScalarValue};
+
+/// An operation which [`Automerge::redact()`](crate::Automerge::redact) may redact
+///
+/// Operations which set a value other than a counter and operations which begin a mark with a
+/// value other than null can be redacted.
+#[derive(Debug)]
+pub struct RedactCandidate<'a> {
+    /// The ID of the operation, as [`ReadDoc::get_all()`](crate::ReadDoc::get_all) returns it
+    pub id: ObjId,
+    /// The object the operation is in
+    pub obj: ObjId,
+    /// The key the operation sets if `obj` is a map, `None` in sequences
+    pub key: Option<&'a str>,
+    /// The name of the mark if the operation begins a mark
+    pub mark: Option<&'a str>,
+    /// The value the operation sets, or the value of the mark
+    pub value: &'a ScalarValue,
+}
+
+/// What [`Automerge::redact()`](crate::Automerge::redact) did
+///
+/// Send this to peers so they can redact their copy of the document with
+/// [`Automerge::apply_redaction()`](crate::Automerge::apply_redaction) and converge on the
+/// redacted history.
+///
+/// This can be persisted using [`Self::to_bytes()`] and `TryFrom<&[u8]>`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RedactionRecord {
+    pub(crate) ops: Vec<ObjId>,
+    pub(crate) hashes: Vec<(ChangeHash, ChangeHash)>,
+}
+
+impl RedactionRecord {
+    /// The IDs of the operations whose values were redacted
+    pub fn ops(&self) -> &[ObjId] {
+        &self.ops
+    }
+
+    /// The old and new hash of every change which was rewritten, in the order of the history
+    ///
+    /// Every change which contains a redacted operation or depends on such a change gets a new
+    /// hash.
+    pub fn rewritten(&self) -> &[(ChangeHash, ChangeHash)] {
+        &self.hashes
+    }
+
+    /// The hash which replaces `old`, if the change was rewritten
+    pub fn new_hash(&self, old: &ChangeHash) -> Option<ChangeHash> {
+        self.hashes
+            .iter()
+            .find(|(o, _)| o == old)
+            .map(|(_, new)| *new)
+    }
+
+    /// Serialize this record to a byte array.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // The number of ops followed by the ops as the bytes of `ObjId::to_bytes`, then the number
+        // of rewritten changes followed by the old and new hash of each
+        let mut encoder = versioned::Encoder::new();
+        encoder.uleb(self.ops.len() as u64);
+        for op in &self.ops {
+            encoder.len_prefixed(&op.to_bytes());
+        }
+        encoder.uleb(self.hashes.len() as u64);
+        for (old, new) in &self.hashes {
+            encoder.hash(old);
+            encoder.hash(new);
+        }
+        encoder.finish()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RedactionRecordFromBytesError {
+    #[error("no version tag")]
+    NoVersion,
+    #[error("invalid version tag")]
+    InvalidVersion(u8),
+    #[error("invalid number of ops: {0}")]
+    ParseNumOps(String),
+    #[error("invalid op length: {0}")]
+    ParseOpLen(String),
+    #[error("not enough bytes in op")]
+    ParseOp,
+    #[error("invalid op: {0}")]
+    InvalidOp(#[from] ObjIdFromBytesError),
+    #[error("invalid number of hashes: {0}")]
+    ParseNumHashes(String),
+    #[error("not enough bytes in change hash")]
+    ParseHash,
+    #[error("trailing bytes")]
+    TrailingBytes,
+}
+
+impl From<versioned::VersionError> for RedactionRecordFromBytesError {
+    fn from(e: versioned::VersionError) -> Self {
+        match e {
+            versioned::VersionError::NoVersion => Self::NoVersion,
+            versioned::VersionError::InvalidVersion(v) => Self::InvalidVersion(v),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for RedactionRecord {
+    type Error = RedactionRecordFromBytesError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut decoder = versioned::Decoder::new(value)?;
+        let num_ops = decoder
+            .uleb()
+            .map_err(|e| RedactionRecordFromBytesError::ParseNumOps(e.to_string()))?;
+        let mut ops = Vec::new();
+        for _ in 0..num_ops {
+            let len = decoder
+                .uleb()
+                .map_err(|e| RedactionRecordFromBytesError::ParseOpLen(e.to_string()))?;
+            let op = decoder
+                .take(len)
+                .ok_or(RedactionRecordFromBytesError::ParseOp)?;
+            ops.push(ObjId::try_from(op)?);
+        }
+        let num_hashes = decoder
+            .uleb()
+            .map_err(|e| RedactionRecordFromBytesError::ParseNumHashes(e.to_string()))?;
+        let mut hashes = Vec::new();
+        for _ in 0..num_hashes {
+            let old = decoder
+                .hash()
+                .ok_or(RedactionRecordFromBytesError::ParseHash)?;
+            let new = decoder
+                .hash()
+                .ok_or(RedactionRecordFromBytesError::ParseHash)?;
+            hashes.push((old, new));
+        }
+        if !decoder.is_empty() {
+            return Err(RedactionRecordFromBytesError::TrailingBytes);
+        }
+        Ok(RedactionRecord { ops, hashes })
+    }
+}
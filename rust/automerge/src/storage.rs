@@ -1,20 +1,28 @@
 use std::ops::Range;
 
+mod bytes;
 pub(crate) mod change;
 mod chunk;
 mod columns;
+pub(crate) mod compress;
 pub(crate) mod convert;
 mod document;
 pub(crate) mod load;
 pub(crate) mod parse;
 pub(crate) mod save;
+pub(crate) mod versioned;
 
-pub use load::VerificationMode;
+pub use compress::Compression;
+pub use load::{RepairReport, VerificationMode};
 pub(crate) use {
+    bytes::Backing,
     change::{AsChangeOp, Change, ChangeOp, Compressed, ReadChangeOpError},
     chunk::{CheckSum, Chunk, ChunkType, Header},
     columns::{Columns, MismatchingColumn, RawColumn, RawColumns},
-    document::{AsChangeMeta, AsDocOp, ChangeMetadata, CompressConfig, DocOp, Document},
+    document::{
+        encoded_ops_len, AsChangeMeta, AsDocOp, ChangeMetadata, ColumnSize, CompressConfig, DocOp,
+        Document,
+    },
 };
 
 fn shift_range(range: Range<usize>, by: usize) -> Range<usize> {
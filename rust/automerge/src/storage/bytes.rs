@@ -0,0 +1,120 @@
+use std::{fmt, ops::Deref, ops::Range, sync::Arc};
+
+/// A buffer which loaded changes can borrow their bytes from rather than copying them
+///
+/// This allows a document to be loaded from e.g. a memory mapped file without copying the raw
+/// bytes of each change chunk out of the file.
+#[derive(Clone)]
+pub(crate) struct Backing(Arc<dyn AsRef<[u8]> + Send + Sync>);
+
+impl Backing {
+    pub(crate) fn new<B: AsRef<[u8]> + Send + Sync + 'static>(bytes: B) -> Self {
+        Self(Arc::new(bytes))
+    }
+
+    pub(crate) fn bytes(&self) -> &[u8] {
+        self.0.as_ref().as_ref()
+    }
+
+    /// Share `slice` if it is a subslice of this backing buffer
+    fn share(&self, slice: &[u8]) -> Option<ChunkBytes<'static>> {
+        let base = self.bytes().as_ptr() as usize;
+        let start = (slice.as_ptr() as usize).checked_sub(base)?;
+        let end = start + slice.len();
+        if end > self.bytes().len() {
+            return None;
+        }
+        Some(ChunkBytes::Shared(self.clone(), start..end))
+    }
+}
+
+impl fmt::Debug for Backing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Backing")
+            .field("len", &self.bytes().len())
+            .finish()
+    }
+}
+
+/// The raw bytes of a chunk, either borrowed, owned, or shared with a [`Backing`] buffer
+#[derive(Clone)]
+pub(crate) enum ChunkBytes<'a> {
+    Borrowed(&'a [u8]),
+    Owned(Vec<u8>),
+    Shared(Backing, Range<usize>),
+}
+
+impl<'a> ChunkBytes<'a> {
+    pub(crate) fn into_owned(self) -> ChunkBytes<'static> {
+        match self {
+            Self::Borrowed(b) => ChunkBytes::Owned(b.to_vec()),
+            Self::Owned(b) => ChunkBytes::Owned(b),
+            Self::Shared(backing, range) => ChunkBytes::Shared(backing, range),
+        }
+    }
+
+    /// Like [`Self::into_owned()`] but share rather than copy borrowed bytes which are part of
+    /// `backing`
+    pub(crate) fn into_backed(self, backing: Option<&Backing>) -> ChunkBytes<'static> {
+        if let (Self::Borrowed(b), Some(backing)) = (&self, backing) {
+            if let Some(shared) = backing.share(b) {
+                return shared;
+            }
+        }
+        self.into_owned()
+    }
+}
+
+impl<'a> Deref for ChunkBytes<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Borrowed(b) => b,
+            Self::Owned(b) => b,
+            Self::Shared(backing, range) => &backing.bytes()[range.clone()],
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for ChunkBytes<'a> {
+    fn from(b: &'a [u8]) -> Self {
+        Self::Borrowed(b)
+    }
+}
+
+impl<'a> From<Vec<u8>> for ChunkBytes<'a> {
+    fn from(b: Vec<u8>) -> Self {
+        Self::Owned(b)
+    }
+}
+
+impl<'a> PartialEq for ChunkBytes<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a> fmt::Debug for ChunkBytes<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_subslices_are_shared() {
+        let backing = Backing::new(vec![1, 2, 3, 4, 5]);
+        let inner = ChunkBytes::Borrowed(&backing.bytes()[1..3]).into_backed(Some(&backing));
+        assert!(matches!(inner, ChunkBytes::Shared(_, ref r) if r == &(1..3)));
+        assert_eq!(&*inner, &[2, 3]);
+
+        let other = [2, 3];
+        let outer = ChunkBytes::Borrowed(&other[..]).into_backed(Some(&backing));
+        assert!(matches!(outer, ChunkBytes::Owned(_)));
+        assert_eq!(inner, outer);
+    }
+}
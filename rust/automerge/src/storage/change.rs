@@ -2,7 +2,10 @@ use std::{borrow::Cow, io::Write, marker::PhantomData, num::NonZeroU64, ops::Ran
 
 use crate::{convert, ActorId, ChangeHash, ScalarValue};
 
-use super::{parse, shift_range, CheckSum, ChunkType, Columns, Header, RawColumns};
+use super::{
+    bytes::{Backing, ChunkBytes},
+    parse, shift_range, CheckSum, ChunkType, Columns, Compression, Header, RawColumns,
+};
 
 mod change_op_columns;
 use change_op_columns::ChangeOpsColumns;
@@ -43,7 +46,7 @@ impl OpReadState for Unverified {}
 #[derive(Clone, Debug)]
 pub(crate) struct Change<'a, O: OpReadState> {
     /// The raw bytes of the entire chunk containing this change, including the header.
-    bytes: Cow<'a, [u8]>,
+    bytes: ChunkBytes<'a>,
     header: Header,
     dependencies: Vec<ChangeHash>,
     actor: ActorId,
@@ -276,9 +279,15 @@ impl<'a, O: OpReadState> Change<'a, O> {
     }
 
     pub(crate) fn into_owned(self) -> Change<'static, O> {
+        self.into_backed(None)
+    }
+
+    /// Like [`Self::into_owned()`] but if the bytes of this change are part of `backing` then
+    /// share them rather than copying them
+    pub(crate) fn into_backed(self, backing: Option<&Backing>) -> Change<'static, O> {
         Change {
             dependencies: self.dependencies,
-            bytes: Cow::Owned(self.bytes.into_owned()),
+            bytes: self.bytes.into_backed(backing),
             header: self.header,
             actor: self.actor,
             other_actors: self.other_actors,
@@ -294,9 +303,9 @@ impl<'a, O: OpReadState> Change<'a, O> {
         }
     }
 
-    pub(crate) fn compress(&self) -> Option<Compressed<'static>> {
-        if self.bytes.len() > DEFLATE_MIN_SIZE {
-            Some(Compressed::compress(self))
+    pub(crate) fn compress(&self, compression: Compression) -> Option<Compressed<'static>> {
+        if self.bytes.len() > DEFLATE_MIN_SIZE && compression != Compression::None {
+            Some(Compressed::compress(self, compression))
         } else {
             None
         }
@@ -506,7 +515,7 @@ impl ChangeBuilder<Set<NonZeroU64>, Set<ActorId>, Set<u64>, Set<i64>> {
         let extra_bytes = shift_range(extra_bytes, header.len());
 
         Ok(Change {
-            bytes: Cow::Owned(bytes),
+            bytes: ChunkBytes::Owned(bytes),
             header,
             dependencies: self.dependencies,
             actor,
@@ -1,6 +1,6 @@
-use std::{borrow::Cow, io::Read};
+use std::borrow::Cow;
 
-use crate::storage::{Change, CheckSum, ChunkType, MAGIC_BYTES};
+use crate::storage::{compress, Change, CheckSum, ChunkType, Compression, MAGIC_BYTES};
 
 use super::OpReadState;
 
@@ -15,17 +15,22 @@ impl<'a> Compressed<'a> {
         Self { checksum, bytes }
     }
 
-    pub(crate) fn compress<'b, O: OpReadState>(change: &'b Change<'b, O>) -> Compressed<'static> {
+    /// # Panics
+    ///
+    /// If `compression` is [`Compression::None`]
+    pub(crate) fn compress<'b, O: OpReadState>(
+        change: &'b Change<'b, O>,
+        compression: Compression,
+    ) -> Compressed<'static> {
         let mut result = Vec::with_capacity(change.bytes().len());
         result.extend(MAGIC_BYTES);
         result.extend(change.checksum().bytes());
-        result.push(u8::from(ChunkType::Compressed));
-        let mut deflater = flate2::bufread::DeflateEncoder::new(
-            change.body_bytes(),
-            flate2::Compression::default(),
-        );
+        let algorithm = compression
+            .algorithm()
+            .expect("compress called with Compression::None");
+        result.push(u8::from(ChunkType::compressed(algorithm)));
         let mut deflated = Vec::new();
-        let deflated_len = deflater.read_to_end(&mut deflated).unwrap();
+        let deflated_len = compress::compress(compression, change.body_bytes(), &mut deflated);
         leb128::write::unsigned(&mut result, deflated_len as u64).unwrap();
         result.extend(&deflated[..]);
         Compressed {
@@ -1,13 +1,16 @@
 use std::{
     borrow::Cow,
     convert::{TryFrom, TryInto},
-    io::Read,
     ops::Range,
 };
 
 use sha2::{Digest, Sha256};
 
-use super::{change::Unverified, parse, Change, Compressed, Document, MAGIC_BYTES};
+use super::{
+    change::Unverified,
+    compress::{self, Algorithm},
+    parse, Change, Compressed, Document, MAGIC_BYTES,
+};
 use crate::{columnar::encoding::leb128::ulebsize, ChangeHash};
 
 pub(crate) enum Chunk<'a> {
@@ -34,6 +37,8 @@ pub(crate) mod error {
         Document(#[from] document::ParseError),
         #[error("unable to decompresse compressed chunk")]
         Deflate,
+        #[error("chunk is compressed with zstd but the zstd feature is not enabled")]
+        UnsupportedCompression,
     }
 
     #[derive(thiserror::Error, Debug)]
@@ -57,6 +62,11 @@ impl<'a> Chunk<'a> {
             remaining,
         } = i.split(header.data_bytes().len());
         tracing::trace!(?header, "parsed chunk header");
+        if !header.chunk_type.algorithm().is_supported() {
+            return Err(parse::ParseError::Error(
+                error::Chunk::UnsupportedCompression,
+            ));
+        }
         let chunk = match header.chunk_type {
             ChunkType::Change => {
                 let (remaining, change) =
@@ -66,7 +76,7 @@ impl<'a> Chunk<'a> {
                 }
                 Chunk::Change(change)
             }
-            ChunkType::Document => {
+            ChunkType::Document | ChunkType::DocumentZstd => {
                 let (remaining, doc) =
                     Document::parse(chunk_input, header).map_err(|e| e.lift())?;
                 if !remaining.is_empty() {
@@ -74,12 +84,10 @@ impl<'a> Chunk<'a> {
                 }
                 Chunk::Document(doc)
             }
-            ChunkType::Compressed => {
+            ChunkType::Compressed | ChunkType::CompressedZstd => {
                 let compressed = &input.unconsumed_bytes()[header.data_bytes()];
-                let mut decoder = flate2::bufread::DeflateDecoder::new(compressed);
                 let mut decompressed = Vec::new();
-                decoder
-                    .read_to_end(&mut decompressed)
+                compress::decompress(header.chunk_type.algorithm(), compressed, &mut decompressed)
                     .map_err(|_| parse::ParseError::Error(error::Chunk::Deflate))?;
                 let inner_header = header.with_data(ChunkType::Change, &decompressed);
                 let mut inner_chunk = Vec::with_capacity(inner_header.len() + decompressed.len());
@@ -110,11 +118,63 @@ impl<'a> Chunk<'a> {
     }
 }
 
+impl From<parse::ParseError<error::Chunk>> for crate::storage::load::Error {
+    fn from(e: parse::ParseError<error::Chunk>) -> Self {
+        match e {
+            parse::ParseError::Error(error::Chunk::UnsupportedCompression) => {
+                Self::UnsupportedCompression
+            }
+            e => Self::Parse(Box::new(e)),
+        }
+    }
+}
+
+impl From<parse::ParseError<error::Chunk>> for crate::LoadChangeError {
+    fn from(e: parse::ParseError<error::Chunk>) -> Self {
+        match e {
+            parse::ParseError::Error(error::Chunk::UnsupportedCompression) => {
+                Self::UnsupportedCompression
+            }
+            e => Self::Parse(Box::new(e)),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) enum ChunkType {
     Document,
     Change,
     Compressed,
+    /// A document whose compressed columns are compressed with zstd rather than DEFLATE
+    DocumentZstd,
+    /// A change which is compressed with zstd rather than DEFLATE
+    CompressedZstd,
+}
+
+impl ChunkType {
+    /// The type of a document chunk whose compressed columns use `algorithm`
+    pub(crate) fn document(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Deflate => Self::Document,
+            Algorithm::Zstd => Self::DocumentZstd,
+        }
+    }
+
+    /// The type of a change chunk compressed with `algorithm`
+    pub(crate) fn compressed(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Deflate => Self::Compressed,
+            Algorithm::Zstd => Self::CompressedZstd,
+        }
+    }
+
+    /// The algorithm used for any compressed data in a chunk of this type
+    pub(crate) fn algorithm(&self) -> Algorithm {
+        match self {
+            Self::DocumentZstd | Self::CompressedZstd => Algorithm::Zstd,
+            Self::Document | Self::Change | Self::Compressed => Algorithm::Deflate,
+        }
+    }
 }
 
 impl TryFrom<u8> for ChunkType {
@@ -125,6 +185,8 @@ impl TryFrom<u8> for ChunkType {
             0 => Ok(Self::Document),
             1 => Ok(Self::Change),
             2 => Ok(Self::Compressed),
+            3 => Ok(Self::DocumentZstd),
+            4 => Ok(Self::CompressedZstd),
             other => Err(other),
         }
     }
@@ -136,6 +198,8 @@ impl From<ChunkType> for u8 {
             ChunkType::Document => 0,
             ChunkType::Change => 1,
             ChunkType::Compressed => 2,
+            ChunkType::DocumentZstd => 3,
+            ChunkType::CompressedZstd => 4,
         }
     }
 }
@@ -213,6 +277,10 @@ impl Header {
         self.header_size
     }
 
+    pub(crate) fn chunk_type(&self) -> ChunkType {
+        self.chunk_type
+    }
+
     pub(crate) fn write(&self, out: &mut Vec<u8>) {
         out.extend(MAGIC_BYTES);
         out.extend(self.checksum.bytes());
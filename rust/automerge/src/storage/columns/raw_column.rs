@@ -1,6 +1,9 @@
-use std::{io::Read, marker::PhantomData, ops::Range};
+use std::{marker::PhantomData, ops::Range};
 
-use crate::storage::parse;
+use crate::storage::{
+    compress::{self, Algorithm},
+    parse, Compression,
+};
 
 use super::{compression, ColumnSpec};
 
@@ -46,17 +49,27 @@ impl<T: compression::ColumnCompression> RawColumn<T> {
         self.data.clone()
     }
 
-    fn compress(&self, input: &[u8], out: &mut Vec<u8>, threshold: usize) -> (ColumnSpec, usize) {
-        let (spec, len) = if self.data.len() < threshold || self.spec.deflate() {
+    /// Write this column to `out`, compressing it if it is larger than `threshold`
+    ///
+    /// The deflate bit of the column specification marks a column as compressed with whichever
+    /// algorithm `compression` specifies. The algorithm is recorded in the type of the chunk the
+    /// columns are written to.
+    fn compress(
+        &self,
+        input: &[u8],
+        out: &mut Vec<u8>,
+        threshold: usize,
+        compression: Compression,
+    ) -> (ColumnSpec, usize) {
+        let (spec, len) = if self.data.len() < threshold
+            || self.spec.deflate()
+            || compression == Compression::None
+        {
             out.extend(&input[self.data.clone()]);
             (self.spec, self.data.len())
         } else {
-            let mut deflater = flate2::bufread::DeflateEncoder::new(
-                &input[self.data.clone()],
-                flate2::Compression::default(),
-            );
-            //This unwrap should be okay as we're reading and writing to in memory buffers
-            (self.spec.deflated(), deflater.read_to_end(out).unwrap())
+            let len = compress::compress(compression, &input[self.data.clone()], out);
+            (self.spec.deflated(), len)
         };
         (spec, len)
     }
@@ -77,10 +90,11 @@ impl<T: compression::ColumnCompression> RawColumn<T> {
         &self,
         input: &[u8],
         out: &mut Vec<u8>,
+        algorithm: Algorithm,
     ) -> Result<(ColumnSpec, usize), ParseError> {
         let len = if self.spec.deflate() {
-            let mut inflater = flate2::bufread::DeflateDecoder::new(&input[self.data.clone()]);
-            inflater.read_to_end(out).map_err(ParseError::Deflate)?
+            compress::decompress(algorithm, &input[self.data.clone()], out)
+                .map_err(ParseError::Deflate)?
         } else {
             out.extend(&input[self.data.clone()]);
             self.data.len()
@@ -118,11 +132,12 @@ impl<T: compression::ColumnCompression> RawColumns<T> {
         input: &[u8],
         out: &mut Vec<u8>,
         threshold: usize,
+        compression: Compression,
     ) -> RawColumns<compression::Unknown> {
         let mut result = Vec::with_capacity(self.0.len());
         let mut start = 0;
         for col in &self.0 {
-            let (spec, len) = col.compress(input, out, threshold);
+            let (spec, len) = col.compress(input, out, threshold, compression);
             result.push(RawColumn {
                 spec,
                 data: start..(start + len),
@@ -134,6 +149,7 @@ impl<T: compression::ColumnCompression> RawColumns<T> {
     }
 
     /// Read each column from `input` and write to `out`, decompressing any compressed columns
+    /// using `algorithm`
     ///
     /// # Returns
     /// The `RawColumns` corresponding to the data written to `out`
@@ -144,6 +160,7 @@ impl<T: compression::ColumnCompression> RawColumns<T> {
         &self,
         input: &[u8],
         out: &mut Vec<u8>,
+        algorithm: Algorithm,
     ) -> Result<RawColumns<compression::Uncompressed>, ParseError> {
         let mut result = Vec::with_capacity(self.0.len());
         let mut start = 0;
@@ -152,7 +169,7 @@ impl<T: compression::ColumnCompression> RawColumns<T> {
                 out.extend(&input[decomp.data.clone()]);
                 (decomp.spec, decomp.data.len())
             } else {
-                col.decompress(input, out)?
+                col.decompress(input, out, algorithm)?
             };
             result.push(RawColumn {
                 spec,
@@ -0,0 +1,150 @@
+use std::io::{self, Read};
+
+/// The compression to apply to documents and changes
+///
+/// The chunk type of compressed documents and changes records which algorithm was used, so they
+/// can be loaded without knowing how they were saved. Loading a document compressed with zstd
+/// requires the `zstd` feature, without it loading fails with
+/// [`LoadError::UnsupportedCompression`](crate::error::LoadError::UnsupportedCompression).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// Don't compress anything
+    None,
+    /// DEFLATE at a level from 1 (fastest) to 9 (smallest), other levels are clamped to this
+    /// range
+    Deflate(u32),
+    /// Zstandard at a level from 1 (fastest) to 22 (smallest), other levels are clamped to this
+    /// range
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    /// The level of DEFLATE compression used by default
+    pub const DEFAULT_DEFLATE_LEVEL: u32 = 6;
+
+    /// The algorithm this compression uses, `None` if it doesn't compress anything
+    pub(crate) fn algorithm(&self) -> Option<Algorithm> {
+        match self {
+            Self::None => None,
+            Self::Deflate(_) => Some(Algorithm::Deflate),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => Some(Algorithm::Zstd),
+        }
+    }
+}
+
+/// A compression algorithm, as recorded in the type of a chunk
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Algorithm {
+    Deflate,
+    Zstd,
+}
+
+impl Algorithm {
+    /// Whether data compressed with this algorithm can be decompressed with the enabled features
+    pub(crate) fn is_supported(&self) -> bool {
+        match self {
+            Self::Deflate => true,
+            Self::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::Deflate(Self::DEFAULT_DEFLATE_LEVEL)
+    }
+}
+
+/// Compress `input` into `out`, returning the number of bytes written
+///
+/// # Panics
+///
+/// If `compression` is [`Compression::None`]
+pub(crate) fn compress(compression: Compression, input: &[u8], out: &mut Vec<u8>) -> usize {
+    let start = out.len();
+    match compression {
+        Compression::None => panic!("compress called with Compression::None"),
+        Compression::Deflate(level) => {
+            let mut deflater = flate2::bufread::DeflateEncoder::new(
+                input,
+                flate2::Compression::new(level.clamp(1, 9)),
+            );
+            //This unwrap should be okay as we're reading and writing to in memory buffers
+            deflater.read_to_end(out).unwrap();
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd(level) => {
+            //This unwrap should be okay as we're reading and writing to in memory buffers
+            zstd::stream::copy_encode(input, &mut *out, level.clamp(1, 22)).unwrap();
+        }
+    }
+    out.len() - start
+}
+
+/// Decompress `input`, which was compressed with `algorithm`, into `out`, returning the number of
+/// bytes written
+pub(crate) fn decompress(
+    algorithm: Algorithm,
+    input: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<usize, io::Error> {
+    match algorithm {
+        Algorithm::Deflate => flate2::bufread::DeflateDecoder::new(input).read_to_end(out),
+        Algorithm::Zstd => decompress_zstd(input, out),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn decompress_zstd(input: &[u8], out: &mut Vec<u8>) -> Result<usize, io::Error> {
+    let start = out.len();
+    zstd::stream::copy_decode(input, &mut *out)?;
+    Ok(out.len() - start)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress_zstd(_input: &[u8], _out: &mut Vec<u8>) -> Result<usize, io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "data is compressed with zstd but the zstd feature is not enabled",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deflate_levels_round_trip() {
+        let input = b"hello hello hello hello hello hello".repeat(10);
+        for level in [0, 1, 6, 9, 100] {
+            let mut compressed = Vec::new();
+            let len = compress(Compression::Deflate(level), &input, &mut compressed);
+            assert_eq!(len, compressed.len());
+            let mut out = Vec::new();
+            decompress(Algorithm::Deflate, &compressed, &mut out).unwrap();
+            assert_eq!(out, input);
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trip() {
+        let input = b"hello hello hello hello hello hello".repeat(10);
+        let mut compressed = vec![1, 2, 3];
+        let len = compress(Compression::Zstd(3), &input, &mut compressed);
+        assert_eq!(len, compressed.len() - 3);
+        let mut out = Vec::new();
+        decompress(Algorithm::Zstd, &compressed[3..], &mut out).unwrap();
+        assert_eq!(out, input);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn zstd_requires_the_feature() {
+        let err = decompress(Algorithm::Zstd, b"data", &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+}
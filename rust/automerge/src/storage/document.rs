@@ -1,6 +1,9 @@
 use std::{borrow::Cow, ops::Range};
 
-use super::{parse, shift_range, ChunkType, Columns, Header, RawColumns};
+use super::{
+    columns::{compression::Uncompressed, ColumnSpec},
+    parse, shift_range, ChunkType, Columns, Compression, Header, RawColumns,
+};
 
 use crate::{convert, ActorId, ChangeHash};
 
@@ -12,10 +15,33 @@ use doc_change_columns::DocChangeColumns;
 pub(crate) use doc_change_columns::{AsChangeMeta, ChangeMetadata, ReadChangeError};
 mod compression;
 
-#[allow(dead_code)]
 pub(crate) enum CompressConfig {
     None,
-    Threshold(usize),
+    /// Compress columns larger than `threshold` bytes using `compression`
+    Threshold {
+        threshold: usize,
+        compression: Compression,
+    },
+}
+
+/// The size of a column in a document chunk before and after compression
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ColumnSize {
+    pub(crate) name: &'static str,
+    pub(crate) raw: usize,
+    pub(crate) compressed: usize,
+}
+
+/// The size of the op columns which encode `ops`
+pub(crate) fn encoded_ops_len<'b, I, D, O>(ops: I) -> usize
+where
+    I: Iterator<Item = D> + Clone + ExactSizeIterator,
+    O: convert::OpId<usize>,
+    D: AsDocOp<'b, OpId = O>,
+{
+    let mut out = Vec::new();
+    DocOpColumns::encode(ops, &mut out);
+    out.len()
 }
 
 #[derive(Debug, Clone)]
@@ -160,20 +186,23 @@ impl<'a> Document<'a> {
             compressed,
             changes,
             ops,
-        } = compression::decompress(compression::Args {
-            prefix: prefix.start,
-            suffix: suffix.start,
-            original: Cow::Borrowed(input.bytes()),
-            changes: compression::Cols {
-                data: changes,
-                raw_columns: change_meta,
-            },
-            ops: compression::Cols {
-                data: ops,
-                raw_columns: ops_meta,
+        } = compression::decompress(
+            compression::Args {
+                prefix: prefix.start,
+                suffix: suffix.start,
+                original: Cow::Borrowed(input.bytes()),
+                changes: compression::Cols {
+                    data: changes,
+                    raw_columns: change_meta,
+                },
+                ops: compression::Cols {
+                    data: ops,
+                    raw_columns: ops_meta,
+                },
+                extra_args: (),
             },
-            extra_args: (),
-        })
+            header.chunk_type().algorithm(),
+        )
         .map_err(|e| parse::ParseError::Error(ParseError::RawColumns(e)))?;
 
         let ops_layout = Columns::parse(op_bytes.len(), ops.iter()).map_err(|e| {
@@ -211,6 +240,42 @@ impl<'a> Document<'a> {
         ))
     }
 
+    /// The size of each change and op column in the chunk which [`Self::new()`] would create
+    /// from `ops` and `changes`
+    pub(crate) fn column_sizes<'b, I, C, IC, D, O>(
+        ops: I,
+        changes: IC,
+        compress: &CompressConfig,
+    ) -> (Vec<ColumnSize>, Vec<ColumnSize>)
+    where
+        I: Iterator<Item = D> + Clone + ExactSizeIterator,
+        O: convert::OpId<usize>,
+        D: AsDocOp<'b, OpId = O>,
+        C: AsChangeMeta<'b>,
+        IC: Iterator<Item = C> + Clone,
+    {
+        let mut ops_out = Vec::new();
+        let ops_meta = DocOpColumns::encode(ops, &mut ops_out);
+
+        let mut change_out = Vec::new();
+        let change_meta = DocChangeColumns::encode(changes, &mut change_out);
+
+        (
+            column_sizes(
+                change_meta.raw_columns(),
+                &change_out,
+                compress,
+                doc_change_columns::column_name,
+            ),
+            column_sizes(
+                ops_meta.raw_columns(),
+                &ops_out,
+                compress,
+                doc_op_columns::column_name,
+            ),
+        )
+    }
+
     pub(crate) fn new<'b, I, C, IC, D, O>(
         mut actors: Vec<ActorId>,
         heads_with_indices: Vec<(ChangeHash, usize)>,
@@ -271,7 +336,11 @@ impl<'a> Document<'a> {
         let op_bytes = shift_range(ops_start..ops_end, header.len());
         let change_bytes = shift_range(change_start..change_end, header.len());
 
-        let compressed_bytes = if let CompressConfig::Threshold(threshold) = compress {
+        let compressed_bytes = if let CompressConfig::Threshold {
+            threshold,
+            compression,
+        } = compress
+        {
             let compressed = Cow::Owned(compression::compress(compression::Args {
                 prefix: prefix_len + header.len(),
                 suffix: suffix_start + header.len(),
@@ -286,6 +355,7 @@ impl<'a> Document<'a> {
                 original: Cow::Borrowed(&bytes),
                 extra_args: compression::CompressArgs {
                     threshold,
+                    compression,
                     original_header_len: header_len,
                 },
             }));
@@ -341,3 +411,30 @@ impl<'a> Document<'a> {
         &self.heads
     }
 }
+
+fn column_sizes(
+    cols: RawColumns<Uncompressed>,
+    data: &[u8],
+    compress: &CompressConfig,
+    name: fn(ColumnSpec) -> &'static str,
+) -> Vec<ColumnSize> {
+    let compressed_lens: Vec<usize> = match compress {
+        CompressConfig::None => cols.iter().map(|c| c.data().len()).collect(),
+        CompressConfig::Threshold {
+            threshold,
+            compression,
+        } => cols
+            .compress(data, &mut Vec::new(), *threshold, *compression)
+            .iter()
+            .map(|c| c.data().len())
+            .collect(),
+    };
+    cols.iter()
+        .zip(compressed_lens)
+        .map(|(col, compressed)| ColumnSize {
+            name: name(col.spec()),
+            raw: col.data().len(),
+            compressed,
+        })
+        .collect()
+}
@@ -2,7 +2,8 @@ use std::{borrow::Cow, convert::Infallible, ops::Range};
 
 use crate::storage::{
     columns::{compression, raw_column},
-    shift_range, ChunkType, Header, RawColumns,
+    compress::Algorithm,
+    shift_range, ChunkType, Compression as Compress, Header, RawColumns,
 };
 
 pub(super) struct Args<'a, T: compression::ColumnCompression, DirArgs> {
@@ -22,6 +23,7 @@ pub(super) struct Args<'a, T: compression::ColumnCompression, DirArgs> {
 
 pub(super) struct CompressArgs {
     pub(super) threshold: usize,
+    pub(super) compression: Compress,
     pub(super) original_header_len: usize,
 }
 
@@ -29,6 +31,7 @@ pub(super) struct CompressArgs {
 pub(super) fn compress(args: Args<'_, compression::Uncompressed, CompressArgs>) -> Vec<u8> {
     let header_len = args.extra_args.original_header_len;
     let threshold = args.extra_args.threshold;
+    let compression = args.extra_args.compression;
     // Wrap in a closure so we can use `?` in the construction but still force the compiler
     // to check that the error type is `Infallible`
     let result: Result<_, Infallible> = (|| {
@@ -36,6 +39,7 @@ pub(super) fn compress(args: Args<'_, compression::Uncompressed, CompressArgs>)
             args,
             Compressing {
                 threshold,
+                compression,
                 header_len,
             },
         )
@@ -48,8 +52,10 @@ pub(super) fn compress(args: Args<'_, compression::Uncompressed, CompressArgs>)
     result.unwrap()
 }
 
+/// Decompress a document chunk whose compressed columns were compressed with `algorithm`
 pub(super) fn decompress<'a>(
     args: Args<'a, compression::Unknown, ()>,
+    algorithm: Algorithm,
 ) -> Result<Decompressed<'a>, raw_column::ParseError> {
     match (
         args.changes.raw_columns.uncompressed(),
@@ -64,7 +70,7 @@ pub(super) fn decompress<'a>(
             op_bytes: args.ops.data,
         }),
         _ => Ok(
-            Compression::<'a, Decompressing, _>::new(args, Decompressing)
+            Compression::<'a, Decompressing, _>::new(args, Decompressing { algorithm })
                 .changes()?
                 .ops()?
                 .write_data()
@@ -127,6 +133,7 @@ trait Direction: std::fmt::Debug {
 #[derive(Debug)]
 struct Compressing {
     threshold: usize,
+    compression: Compress,
     header_len: usize,
 }
 
@@ -144,9 +151,12 @@ impl Direction for Compressing {
         meta_out: &mut Vec<u8>,
     ) -> Result<Cols<Self::Out>, Self::Error> {
         let start = out.len();
-        let raw_columns = cols
-            .raw_columns
-            .compress(&input[cols.data.clone()], out, self.threshold);
+        let raw_columns = cols.raw_columns.compress(
+            &input[cols.data.clone()],
+            out,
+            self.threshold,
+            self.compression,
+        );
         raw_columns.write(meta_out);
         Ok(Cols {
             data: start..out.len(),
@@ -156,7 +166,9 @@ impl Direction for Compressing {
 }
 
 #[derive(Debug)]
-struct Decompressing;
+struct Decompressing {
+    algorithm: Algorithm,
+}
 
 impl Direction for Decompressing {
     type Error = raw_column::ParseError;
@@ -172,9 +184,9 @@ impl Direction for Decompressing {
         meta_out: &mut Vec<u8>,
     ) -> Result<Cols<Self::Out>, raw_column::ParseError> {
         let start = out.len();
-        let raw_columns = cols
-            .raw_columns
-            .uncompress(&input[cols.data.clone()], out)?;
+        let raw_columns =
+            cols.raw_columns
+                .uncompress(&input[cols.data.clone()], out, self.algorithm)?;
         raw_columns.write(meta_out);
         Ok(Cols {
             data: start..out.len(),
@@ -345,9 +357,22 @@ impl<'a> Compression<'a, Decompressing, Finished<Decompressing>> {
 
 impl<'a> Compression<'a, Compressing, Finished<Compressing>> {
     fn finish(self) -> Vec<u8> {
-        let Finished { out, .. } = self.state;
+        let Finished {
+            out,
+            change_cols,
+            ops_cols,
+            ..
+        } = self.state;
+        // Only record the algorithm if a column was compressed, so documents whose columns are
+        // all below the threshold can still be read by anything which reads documents
+        let compressed = change_cols.raw_columns.uncompressed().is_none()
+            || ops_cols.raw_columns.uncompressed().is_none();
+        let chunk_type = match self.direction.compression.algorithm() {
+            Some(algorithm) if compressed => ChunkType::document(algorithm),
+            _ => ChunkType::Document,
+        };
         let headerless = &out[self.direction.header_len..];
-        let header = Header::new(ChunkType::Document, headerless);
+        let header = Header::new(chunk_type, headerless);
         let mut result = Vec::with_capacity(header.len() + out.len());
         header.write(&mut result);
         result.extend(headerless);
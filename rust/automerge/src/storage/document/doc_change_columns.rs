@@ -23,6 +23,22 @@ const MESSAGE_COL_ID: ColumnId = ColumnId::new(3);
 const DEPS_COL_ID: ColumnId = ColumnId::new(4);
 const EXTRA_COL_ID: ColumnId = ColumnId::new(5);
 
+/// The name of the change column `spec` as used in the storage format documentation
+pub(crate) fn column_name(spec: ColumnSpec) -> &'static str {
+    match (spec.id(), spec.col_type()) {
+        (ACTOR_COL_ID, ColumnType::Actor) => "actor",
+        (SEQ_COL_ID, _) => "seq",
+        (MAX_OP_COL_ID, _) => "maxOp",
+        (TIME_COL_ID, _) => "time",
+        (MESSAGE_COL_ID, _) => "message",
+        (DEPS_COL_ID, ColumnType::Group) => "depsNum",
+        (DEPS_COL_ID, _) => "depsIndex",
+        (EXTRA_COL_ID, ColumnType::ValueMetadata) => "extraLen",
+        (EXTRA_COL_ID, _) => "extraRaw",
+        _ => "unknown",
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct ChangeMetadata<'a> {
     pub(crate) actor: usize,
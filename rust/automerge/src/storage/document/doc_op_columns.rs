@@ -14,11 +14,12 @@ use crate::{
         },
     },
     convert,
+    error::InvalidOpType,
     storage::{
         columns::{compression, ColumnId, ColumnSpec, ColumnType},
         Columns, MismatchingColumn, RawColumn, RawColumns,
     },
-    types::{ObjId, OpId, ScalarValue},
+    types::{ObjId, OpId, OpType, ScalarValue},
 };
 
 const OBJ_COL_ID: ColumnId = ColumnId::new(0);
@@ -31,6 +32,29 @@ const SUCC_COL_ID: ColumnId = ColumnId::new(8);
 const EXPAND_COL_ID: ColumnId = ColumnId::new(9);
 const MARK_NAME_COL_ID: ColumnId = ColumnId::new(10);
 
+/// The name of the op column `spec` as used in the storage format documentation
+pub(crate) fn column_name(spec: ColumnSpec) -> &'static str {
+    match (spec.id(), spec.col_type()) {
+        (OBJ_COL_ID, ColumnType::Actor) => "objActor",
+        (OBJ_COL_ID, _) => "objCtr",
+        (KEY_COL_ID, ColumnType::Actor) => "keyActor",
+        (KEY_COL_ID, ColumnType::String) => "keyStr",
+        (KEY_COL_ID, _) => "keyCtr",
+        (ID_COL_ID, ColumnType::Actor) => "idActor",
+        (ID_COL_ID, _) => "idCtr",
+        (INSERT_COL_ID, _) => "insert",
+        (ACTION_COL_ID, _) => "action",
+        (VAL_COL_ID, ColumnType::ValueMetadata) => "valLen",
+        (VAL_COL_ID, _) => "valRaw",
+        (SUCC_COL_ID, ColumnType::Group) => "succNum",
+        (SUCC_COL_ID, ColumnType::Actor) => "succActor",
+        (SUCC_COL_ID, _) => "succCtr",
+        (EXPAND_COL_ID, _) => "expand",
+        (MARK_NAME_COL_ID, _) => "markName",
+        _ => "unknown",
+    }
+}
+
 /// The form operations take in the compressed document format.
 #[derive(Debug)]
 pub(crate) struct DocOp {
@@ -331,8 +355,12 @@ impl<'a> DocOpColumnIter<'a> {
 }
 
 #[derive(Debug, thiserror::Error)]
-#[error(transparent)]
-pub(crate) struct ReadDocOpError(#[from] DecodeColumnError);
+pub(crate) enum ReadDocOpError {
+    #[error(transparent)]
+    DecodeError(#[from] DecodeColumnError),
+    #[error(transparent)]
+    InvalidOpType(#[from] InvalidOpType),
+}
 
 impl<'a> Iterator for DocOpColumnIter<'a> {
     type Item = Result<DocOp, ReadDocOpError>;
@@ -344,14 +372,14 @@ impl<'a> Iterator for DocOpColumnIter<'a> {
             match self.try_next() {
                 Ok(Some(op)) => Some(Ok(op)),
                 Ok(None) => None,
-                Err(e) => Some(Err(e.into())),
+                Err(e) => Some(Err(e)),
             }
         }
     }
 }
 
 impl<'a> DocOpColumnIter<'a> {
-    fn try_next(&mut self) -> Result<Option<DocOp>, DecodeColumnError> {
+    fn try_next(&mut self) -> Result<Option<DocOp>, ReadDocOpError> {
         if self.done() {
             Ok(None)
         } else {
@@ -368,6 +396,11 @@ impl<'a> DocOpColumnIter<'a> {
             let insert = self.insert.next_in_col("insert")?;
             let expand = self.expand.maybe_next_in_col("expand")?.unwrap_or(false);
             let mark_name = self.mark_name.maybe_next_in_col("mark_name")?;
+
+            // This check is necessary to ensure that OpType::from_action_and_value
+            // cannot panic later in the process.
+            OpType::validate_action_and_value(action, &value)?;
+
             Ok(Some(DocOp {
                 id,
                 value,
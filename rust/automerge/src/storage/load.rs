@@ -7,8 +7,10 @@ use crate::{
 
 pub(crate) mod change_collector;
 mod reconstruct_document;
+pub(crate) mod repair;
 pub use reconstruct_document::VerificationMode;
-pub(crate) use reconstruct_document::{reconstruct_opset, ReconOpSet};
+pub(crate) use reconstruct_document::{reconstruct_changes, reconstruct_opset, ReconOpSet};
+pub use repair::RepairReport;
 
 #[derive(Debug, thiserror::Error)]
 #[allow(unreachable_pub)]
@@ -25,6 +27,10 @@ pub enum Error {
     InflateDocument(Box<dyn std::error::Error + Send + Sync + 'static>),
     #[error("bad checksum")]
     BadChecksum,
+    #[error("document chunks cannot be loaded with load_changes_shared, use load")]
+    SharedDocumentChunk,
+    #[error("the data is compressed with zstd but the zstd feature is not enabled")]
+    UnsupportedCompression,
 }
 
 pub(crate) enum LoadedChanges<'a> {
@@ -50,11 +56,14 @@ pub(crate) enum LoadedChanges<'a> {
 /// or more changes. This means it is possible to partially load corrupted data if the first `n`
 /// chunks are valid. This function returns a `LoadedChanges` which you can examine to determine if
 /// this is the case.
-#[instrument(skip(data))]
-pub(crate) fn load_changes<'a>(mut data: parse::Input<'a>) -> LoadedChanges<'a> {
+#[instrument(skip(data, backing))]
+pub(crate) fn load_changes<'a>(
+    mut data: parse::Input<'a>,
+    backing: Option<&storage::Backing>,
+) -> LoadedChanges<'a> {
     let mut changes = Vec::new();
     while !data.is_empty() {
-        let remaining = match load_next_change(data, &mut changes) {
+        let remaining = match load_next_change(data, &mut changes, backing) {
             Ok(d) => d,
             Err(e) => {
                 return LoadedChanges::Partial {
@@ -72,22 +81,25 @@ pub(crate) fn load_changes<'a>(mut data: parse::Input<'a>) -> LoadedChanges<'a>
 fn load_next_change<'a>(
     data: parse::Input<'a>,
     changes: &mut Vec<Change>,
+    backing: Option<&storage::Backing>,
 ) -> Result<parse::Input<'a>, Error> {
-    let (remaining, chunk) = storage::Chunk::parse(data).map_err(|e| Error::Parse(Box::new(e)))?;
+    let (remaining, chunk) = storage::Chunk::parse(data)?;
     if !chunk.checksum_valid() {
         return Err(Error::BadChecksum);
     }
     match chunk {
+        storage::Chunk::Document(_) if backing.is_some() => {
+            return Err(Error::SharedDocumentChunk);
+        }
         storage::Chunk::Document(d) => {
             tracing::trace!("loading document chunk");
-            let new_changes = reconstruct_opset(&d, VerificationMode::DontCheck)
-                .map_err(|e| Error::InflateDocument(Box::new(e)))?
-                .changes;
+            let new_changes = reconstruct_changes(&d, VerificationMode::DontCheck)
+                .map_err(|e| Error::InflateDocument(Box::new(e)))?;
             changes.extend(new_changes);
         }
         storage::Chunk::Change(change) => {
             tracing::trace!("loading change chunk");
-            let change = Change::new_from_unverified(change.into_owned(), None)
+            let change = Change::new_from_unverified(change.into_backed(backing), None)
                 .map_err(|e| Error::InvalidChangeColumns(Box::new(e)))?;
             #[cfg(debug_assertions)]
             {
@@ -100,9 +112,11 @@ fn load_next_change<'a>(
         }
         storage::Chunk::CompressedChange(change, compressed) => {
             tracing::trace!("loading compressed change chunk");
-            let change =
-                Change::new_from_unverified(change.into_owned(), Some(compressed.into_owned()))
-                    .map_err(|e| Error::InvalidChangeColumns(Box::new(e)))?;
+            let change = Change::new_from_unverified(
+                change.into_backed(backing),
+                Some(compressed.into_owned()),
+            )
+            .map_err(|e| Error::InvalidChangeColumns(Box::new(e)))?;
             changes.push(change);
         }
     };
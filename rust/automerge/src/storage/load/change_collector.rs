@@ -39,11 +39,6 @@ pub(crate) struct ChangeCollector<'a> {
     changes_by_actor: HashMap<usize, Vec<PartialChange<'a>>, FxBuildHasher>,
 }
 
-pub(crate) struct CollectedChanges<'a> {
-    pub(crate) history: Vec<StoredChange<'a, Verified>>,
-    pub(crate) heads: BTreeSet<ChangeHash>,
-}
-
 impl<'a> ChangeCollector<'a> {
     pub(crate) fn new<E: std::error::Error + Send + Sync + 'static, I>(
         changes: I,
@@ -99,8 +94,17 @@ impl<'a> ChangeCollector<'a> {
         Ok(())
     }
 
-    #[instrument(skip(self, osd))]
-    pub(crate) fn finish(self, osd: &OpSetData) -> Result<CollectedChanges<'static>, Error> {
+    /// Build the changes, passing each one to `on_change` in the order of the history, and return
+    /// the heads
+    #[instrument(skip(self, osd, on_change))]
+    pub(crate) fn finish<F>(
+        self,
+        osd: &OpSetData,
+        mut on_change: F,
+    ) -> Result<BTreeSet<ChangeHash>, Error>
+    where
+        F: FnMut(StoredChange<'static, Verified>),
+    {
         let mut changes_in_order =
             Vec::with_capacity(self.changes_by_actor.values().map(|c| c.len()).sum());
         for (_, changes) in self.changes_by_actor {
@@ -120,7 +124,6 @@ impl<'a> ChangeCollector<'a> {
         changes_in_order.sort_by_key(|c| c.index);
 
         let mut hashes_by_index = HashMap::default();
-        let mut history = Vec::new();
         let mut heads = BTreeSet::new();
         for (index, change) in changes_in_order.into_iter().enumerate() {
             let finished = change.finish(&hashes_by_index, osd)?;
@@ -130,10 +133,10 @@ impl<'a> ChangeCollector<'a> {
                 heads.remove(dep);
             }
             heads.insert(hash);
-            history.push(finished.into_owned());
+            on_change(finished.into_owned());
         }
 
-        Ok(CollectedChanges { history, heads })
+        Ok(heads)
     }
 }
 
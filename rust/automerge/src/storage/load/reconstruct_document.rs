@@ -6,7 +6,7 @@ use crate::{
     change::Change,
     columnar::Key as DocOpKey,
     op_set::{OpIdx, OpSet, OpSetData},
-    storage::{change::Verified, Change as StoredChange, DocOp, Document},
+    storage::{DocOp, Document},
     types::{ChangeHash, ElemId, Key, ObjId, OpBuilder, OpId, OpIds, OpType},
 };
 
@@ -29,7 +29,6 @@ pub(crate) enum Error {
 }
 
 pub(crate) struct MismatchedHeads {
-    changes: Vec<StoredChange<'static, Verified>>,
     expected_heads: BTreeSet<ChangeHash>,
     derived_heads: BTreeSet<ChangeHash>,
 }
@@ -37,7 +36,6 @@ pub(crate) struct MismatchedHeads {
 impl std::fmt::Debug for MismatchedHeads {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("MismatchedHeads")
-            .field("changes", &self.changes.len())
             .field("expected_heads", &self.expected_heads)
             .field("derived_heads", &self.derived_heads)
             .finish()
@@ -59,9 +57,9 @@ struct NextDocOp {
     obj: ObjId,
 }
 
-fn next_op<'a, I>(iter: &mut I, op_set: &mut OpSet) -> Result<Option<NextDocOp>, Error>
+fn next_op<I>(iter: &mut I, op_set: &mut OpSet) -> Result<Option<NextDocOp>, Error>
 where
-    I: Iterator<Item = Result<DocOp, ReadDocOpError>> + Clone + 'a,
+    I: Iterator<Item = Result<DocOp, ReadDocOpError>>,
 {
     let op_res = iter.next();
     if let Some(op_res) = op_res {
@@ -91,28 +89,98 @@ struct ReconstructionState<'a> {
     pred: HashMap<OpId, Vec<OpIdx>>,
     ops_collecter: Vec<OpIdx>,
     change_collector: ChangeCollector<'a>,
+    /// Whether to defer building the op tree of each object until it is first accessed
+    lazy: bool,
 }
 
 impl<'a> ReconstructionState<'a> {
-    fn new(doc: &'a Document<'a>) -> Result<Self, Error> {
-        Ok(Self {
+    fn new(doc: &'a Document<'a>, change_collector: ChangeCollector<'a>, lazy: bool) -> Self {
+        Self {
             op_set: OpSet::from_actors(doc.actors().to_vec()),
             max_op: 0,
             last_obj: None,
             last_key: None,
             pred: HashMap::default(),
             ops_collecter: Vec::default(),
-            change_collector: ChangeCollector::new(doc.iter_changes())?,
-        })
+            change_collector,
+            lazy,
+        }
     }
 }
 
-pub(crate) fn reconstruct_opset<'a>(
+/// Reconstruct the op set of `doc`, passing each change in the history to `on_change` as soon as
+/// it has been rebuilt
+///
+/// `on_change` is also given the op set data, whose actor cache contains the actor of the
+/// change. If `mode` is [`VerificationMode::Check`] the heads are checked once every change has
+/// been passed to `on_change`.
+pub(crate) fn reconstruct_opset<'a, F>(
+    doc: &'a Document<'a>,
+    mode: VerificationMode,
+    lazy: bool,
+    on_change: F,
+) -> Result<ReconOpSet, Error>
+where
+    F: FnMut(Change, &OpSetData),
+{
+    reconstruct_opset_with(doc, mode, lazy, cfg!(feature = "parallel"), on_change)
+}
+
+/// Reconstruct just the changes in `doc`
+pub(crate) fn reconstruct_changes<'a>(
+    doc: &'a Document<'a>,
+    mode: VerificationMode,
+) -> Result<Vec<Change>, Error> {
+    let mut changes = Vec::new();
+    // The op set is thrown away so there is no point indexing it
+    reconstruct_opset(doc, mode, true, |change, _| changes.push(change))?;
+    Ok(changes)
+}
+
+/// Reconstruct the op set of `doc`, using multiple threads if `parallel` is true
+///
+/// The parallel path decodes the op columns while the change columns are decoded, then builds
+/// the op tree of each object on a separate thread once all the ops have been loaded. The
+/// result is the same as the serial path.
+fn reconstruct_opset_with<'a, F>(
     doc: &'a Document<'a>,
     mode: VerificationMode,
-) -> Result<ReconOpSet, Error> {
-    let mut state = ReconstructionState::new(doc)?;
-    let mut iter_ops = doc.iter_ops();
+    lazy: bool,
+    parallel: bool,
+    on_change: F,
+) -> Result<ReconOpSet, Error>
+where
+    F: FnMut(Change, &OpSetData),
+{
+    #[cfg(feature = "parallel")]
+    if parallel {
+        let (change_collector, ops) = rayon::join(
+            || ChangeCollector::new(doc.iter_changes()),
+            || doc.iter_ops().collect::<Vec<_>>(),
+        );
+        // Defer building the op trees so they can be built concurrently below
+        let mut state = ReconstructionState::new(doc, change_collector?, true);
+        load_ops(&mut state, ops.into_iter())?;
+        if !lazy {
+            state.op_set.par_materialize_all();
+        }
+        return finish(state, doc, mode, on_change);
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = parallel;
+
+    let mut state = ReconstructionState::new(doc, ChangeCollector::new(doc.iter_changes())?, lazy);
+    load_ops(&mut state, doc.iter_ops())?;
+    if !lazy {
+        state.op_set.add_indexes();
+    }
+    finish(state, doc, mode, on_change)
+}
+
+fn load_ops<I>(state: &mut ReconstructionState<'_>, mut iter_ops: I) -> Result<(), Error>
+where
+    I: Iterator<Item = Result<DocOp, ReadDocOpError>>,
+{
     let mut next = next_op(&mut iter_ops, &mut state.op_set)?;
     while let Some(NextDocOp {
         op,
@@ -149,19 +217,27 @@ pub(crate) fn reconstruct_opset<'a>(
 
         next = next_op(&mut iter_ops, &mut state.op_set)?;
 
-        flush_ops(&obj, next.as_ref(), &mut state)?;
+        flush_ops(&obj, next.as_ref(), state)?;
     }
+    Ok(())
+}
 
-    state.op_set.add_indexes();
-
+fn finish<F>(
+    state: ReconstructionState<'_>,
+    doc: &Document<'_>,
+    mode: VerificationMode,
+    on_change: F,
+) -> Result<ReconOpSet, Error>
+where
+    F: FnMut(Change, &OpSetData),
+{
     let op_set = state.op_set;
     let change_collector = state.change_collector;
     let max_op = state.max_op;
 
-    let (changes, heads) = flush_changes(change_collector, doc, mode, &op_set.osd)?;
+    let heads = flush_changes(change_collector, doc, mode, &op_set.osd, on_change)?;
 
     Ok(ReconOpSet {
-        changes,
         max_op,
         op_set,
         heads,
@@ -171,27 +247,28 @@ pub(crate) fn reconstruct_opset<'a>(
 // create all binary changes
 // look for mismatched heads
 
-fn flush_changes(
+fn flush_changes<F>(
     change_collector: ChangeCollector<'_>,
     doc: &Document<'_>,
     mode: VerificationMode,
     osd: &OpSetData,
-) -> Result<(Vec<Change>, BTreeSet<ChangeHash>), Error> {
-    let super::change_collector::CollectedChanges { history, heads } =
-        change_collector.finish(osd)?;
+    mut on_change: F,
+) -> Result<BTreeSet<ChangeHash>, Error>
+where
+    F: FnMut(Change, &OpSetData),
+{
+    let heads = change_collector.finish(osd, |change| on_change(Change::new(change), osd))?;
     if matches!(mode, VerificationMode::Check) {
         let expected_heads: BTreeSet<_> = doc.heads().iter().cloned().collect();
         if expected_heads != heads {
             tracing::error!(?expected_heads, ?heads, "mismatching heads");
             return Err(Error::MismatchingHeads(MismatchedHeads {
-                changes: history,
                 expected_heads,
                 derived_heads: heads,
             }));
         }
     }
-    let changes = history.into_iter().map(Change::new).collect();
-    Ok((changes, heads))
+    Ok(heads)
 }
 
 // after we see all ops for a given obj/key we can detect delets (this is more complex with MOVE)
@@ -227,10 +304,12 @@ fn flush_ops(
         state.pred.clear();
 
         for idx in &state.ops_collecter {
-            state
-                .op_set
-                .load_idx(obj, *idx)
-                .map_err(|e| Error::ReadOp(Box::new(e)))?;
+            let loaded = if state.lazy {
+                state.op_set.load_idx_lazy(obj, *idx)
+            } else {
+                state.op_set.load_idx(obj, *idx)
+            };
+            loaded.map_err(|e| Error::ReadOp(Box::new(e)))?;
         }
 
         state.ops_collecter.truncate(0)
@@ -239,7 +318,6 @@ fn flush_ops(
 }
 
 pub(crate) struct ReconOpSet {
-    pub(crate) changes: Vec<Change>,
     pub(crate) max_op: u64,
     pub(crate) op_set: OpSet,
     pub(crate) heads: BTreeSet<ChangeHash>,
@@ -281,3 +359,65 @@ fn check_opid(osd: &OpSetData, opid: OpId) -> Result<OpId, Error> {
         }
     }
 }
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use super::*;
+    use crate::{storage, transaction::Transactable, ActorId, AutoCommit, ObjType, ROOT};
+
+    fn fixture() -> Vec<u8> {
+        let mut doc = AutoCommit::new().with_actor(ActorId::from([1]));
+        let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+        let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+        for i in 0..100 {
+            doc.insert(&list, i, i as i64).unwrap();
+            let map = doc
+                .put_object(ROOT, format!("map{}", i % 10), ObjType::Map)
+                .unwrap();
+            doc.put(&map, "value", i as i64).unwrap();
+        }
+        doc.splice_text(&text, 0, 0, "the quick brown fox").unwrap();
+        doc.put(ROOT, "counter", crate::ScalarValue::counter(1))
+            .unwrap();
+        doc.commit();
+
+        let mut other = doc.fork().with_actor(ActorId::from([2]));
+        other.delete(&list, 10).unwrap();
+        other.splice_text(&text, 4, 6, "slow").unwrap();
+        other.increment(ROOT, "counter", 5).unwrap();
+        doc.put(&list, 10, "concurrent").unwrap();
+        doc.splice_text(&text, 10, 5, "red").unwrap();
+        doc.merge(&mut other).unwrap();
+        doc.save()
+    }
+
+    #[test]
+    fn parallel_reconstruction_is_identical_to_serial() {
+        let bytes = fixture();
+        let (_, chunk) = storage::Chunk::parse(storage::parse::Input::new(&bytes)).unwrap();
+        let storage::Chunk::Document(doc) = chunk else {
+            panic!("expected a document chunk");
+        };
+        for lazy in [false, true] {
+            let mut serial_changes = Vec::new();
+            let serial =
+                reconstruct_opset_with(&doc, VerificationMode::Check, lazy, false, |c, _| {
+                    serial_changes.push(c.raw_bytes().to_vec())
+                })
+                .unwrap();
+            let mut parallel_changes = Vec::new();
+            let parallel =
+                reconstruct_opset_with(&doc, VerificationMode::Check, lazy, true, |c, _| {
+                    parallel_changes.push(c.raw_bytes().to_vec())
+                })
+                .unwrap();
+            assert_eq!(serial.max_op, parallel.max_op);
+            assert_eq!(serial.heads, parallel.heads);
+            assert_eq!(serial_changes, parallel_changes);
+            assert_eq!(
+                serial.op_set.debug_structure(),
+                parallel.op_set.debug_structure()
+            );
+        }
+    }
+}
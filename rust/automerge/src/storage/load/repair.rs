@@ -0,0 +1,124 @@
+use std::ops::Range;
+
+use crate::{
+    change::Change,
+    storage::{self, parse, MAGIC_BYTES},
+    ChangeHash,
+};
+
+use super::{reconstruct_changes, VerificationMode};
+
+/// What [`Automerge::repair()`](crate::Automerge::repair) was able to recover from damaged data
+///
+/// Only changes which are referenced by something that survived can be reported as lost. A change
+/// which nothing else depends on and which was in a corrupt region of the input disappears
+/// without a trace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// The changes which were recovered and applied to the document
+    pub recovered: Vec<ChangeHash>,
+    /// The changes which were recovered but depend on lost changes
+    ///
+    /// These are kept in the document and saved with it, they will be applied if the changes
+    /// they depend on are received later, e.g. by syncing with another peer.
+    pub orphaned: Vec<ChangeHash>,
+    /// The changes which are known to have existed but which could not be recovered
+    pub lost: Vec<ChangeHash>,
+    /// The byte ranges of the input which were not part of any valid chunk
+    pub corrupt: Vec<Range<usize>>,
+}
+
+/// The result of scanning some damaged data for chunks
+pub(crate) struct Salvaged {
+    /// Changes from every valid change chunk and every document chunk which could be decoded
+    pub(crate) changes: Vec<Change>,
+    /// The heads of every document chunk, whether or not it could be decoded
+    pub(crate) heads: Vec<ChangeHash>,
+    pub(crate) corrupt: Vec<Range<usize>>,
+}
+
+/// Scan `data` for chunks, skipping over any region which can't be parsed
+///
+/// When a chunk fails to parse or fails its checksum we search for the next occurrence of the
+/// magic bytes after the start of the failed chunk, so chunks which follow a corrupt region (or
+/// which a chunk with a corrupt length would have swallowed) are still found.
+///
+/// Change chunks are only accepted if their checksum is valid. The checksum of a document chunk
+/// covers the whole chunk, so a document chunk with a bad checksum is still decoded and accepted
+/// if the hashes of the changes it reconstructs match the heads it records.
+pub(crate) fn salvage(data: &[u8]) -> Salvaged {
+    let mut salvaged = Salvaged {
+        changes: Vec::new(),
+        heads: Vec::new(),
+        corrupt: Vec::new(),
+    };
+    let mut offset = 0;
+    let mut corrupt_start = None;
+    while offset < data.len() {
+        match salvage_chunk(&data[offset..], &mut salvaged) {
+            Some(len) => {
+                if let Some(start) = corrupt_start.take() {
+                    salvaged.corrupt.push(start..offset);
+                }
+                offset += len;
+            }
+            None => {
+                tracing::debug!(offset, "skipping corrupt chunk");
+                corrupt_start.get_or_insert(offset);
+                offset = next_magic_bytes(data, offset + 1);
+            }
+        }
+    }
+    if let Some(start) = corrupt_start {
+        salvaged.corrupt.push(start..data.len());
+    }
+    salvaged
+}
+
+/// Try to load the chunk at the start of `data`, returning the length of the chunk if it was
+/// valid
+fn salvage_chunk(data: &[u8], salvaged: &mut Salvaged) -> Option<usize> {
+    let (remaining, chunk) = storage::Chunk::parse(parse::Input::new(data)).ok()?;
+    let len = data.len() - remaining.unconsumed_bytes().len();
+    match chunk {
+        storage::Chunk::Document(d) => {
+            salvaged.heads.extend_from_slice(d.heads());
+            let mode = if d.checksum_valid() {
+                VerificationMode::DontCheck
+            } else {
+                VerificationMode::Check
+            };
+            let changes = reconstruct_changes(&d, mode)
+                .map_err(|e| tracing::debug!(err=?e, "unable to reconstruct document chunk"))
+                .ok()?;
+            salvaged.changes.extend(changes);
+        }
+        storage::Chunk::Change(change) => {
+            if !change.checksum_valid() {
+                return None;
+            }
+            salvaged
+                .changes
+                .push(Change::new_from_unverified(change.into_owned(), None).ok()?);
+        }
+        storage::Chunk::CompressedChange(change, compressed) => {
+            if compressed.checksum() != change.checksum() || !change.checksum_valid() {
+                return None;
+            }
+            salvaged
+                .changes
+                .push(Change::new_from_unverified(change, Some(compressed.into_owned())).ok()?);
+        }
+    }
+    Some(len)
+}
+
+fn next_magic_bytes(data: &[u8], from: usize) -> usize {
+    data.get(from..)
+        .and_then(|rest| {
+            rest.windows(MAGIC_BYTES.len())
+                .position(|w| w == MAGIC_BYTES)
+        })
+        .map(|pos| from + pos)
+        .unwrap_or(data.len())
+}
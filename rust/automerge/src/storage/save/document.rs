@@ -6,7 +6,9 @@ use itertools::Itertools;
 use crate::{
     indexed_cache::IndexedCache,
     storage::{
-        change::DEFLATE_MIN_SIZE, convert::op_as_docop, AsChangeMeta, CompressConfig, Document,
+        change::DEFLATE_MIN_SIZE,
+        convert::{op_as_docop, OpAsDocOp},
+        encoded_ops_len, AsChangeMeta, ColumnSize, CompressConfig, Compression, Document,
     },
     types::{ActorId, ObjId, Op},
     Change, ChangeHash,
@@ -18,52 +20,150 @@ use crate::{
 /// * If any of ops in `ops` reference an actor which is not in `actors`
 /// * If any of ops in `ops` reference a property which is not in `props`
 /// * If any of the changes reference a dependency index which is not in `changes`
-#[tracing::instrument(skip(changes, ops, actors, props, config))]
+#[tracing::instrument(skip(changes, ops, actors, props))]
 pub(crate) fn save_document<'a, I, O>(
     changes: I,
     ops: O,
     actors: &'a IndexedCache<ActorId>,
-    props: &IndexedCache<String>,
+    props: &'a IndexedCache<String>,
     heads: &[ChangeHash],
-    config: Option<CompressConfig>,
+    compression: Compression,
 ) -> Vec<u8>
 where
     I: Iterator<Item = &'a Change> + Clone + 'a,
     O: Iterator<Item = (&'a ObjId, Op<'a>)> + Clone + ExactSizeIterator,
 {
-    let mut actor_lookup = HashMap::with_capacity(actors.len());
-    let mut actor_ids = changes
-        .clone()
-        .map(|c| c.actor_id().clone())
-        .unique()
-        .collect::<Vec<_>>();
-    actor_ids.sort();
-    for (index, actor_id) in actor_ids.iter().enumerate() {
-        actor_lookup.insert(actors.lookup(actor_id).unwrap(), index);
-    }
-
-    let doc_ops = ops
-        .clone()
-        .map(|(_obj, op)| op_as_docop(&actor_lookup, props, op));
-
-    let hash_graph = HashGraph::new(changes.clone());
-    let changes = changes.map(|c| ChangeWithGraph {
-        actors,
-        actor_lookup: &actor_lookup,
-        change: c,
-        graph: &hash_graph,
-    });
-
+    let encoder = Encoder::new(changes.clone(), actors, props);
     let doc = Document::new(
-        actor_ids,
-        hash_graph.heads_with_indices(heads.to_vec()),
-        doc_ops,
-        changes,
-        config.unwrap_or(CompressConfig::Threshold(DEFLATE_MIN_SIZE)),
+        encoder.actor_ids.clone(),
+        encoder.hash_graph.heads_with_indices(heads.to_vec()),
+        ops.map(|(_obj, op)| encoder.doc_op(op)),
+        changes.map(|c| encoder.change(c)),
+        compress_config(compression),
     );
     doc.into_bytes()
 }
 
+/// The sizes of the parts of the document chunk which [`save_document()`] would produce
+pub(crate) struct DocumentStats {
+    pub(crate) total_size: usize,
+    pub(crate) change_columns: Vec<ColumnSize>,
+    pub(crate) op_columns: Vec<ColumnSize>,
+    /// The size of the op columns for the ops of each object, encoded separately
+    pub(crate) objects: Vec<(ObjId, usize)>,
+}
+
+/// Encode the document as [`save_document()`] would and measure the result
+///
+/// # Panics
+///
+/// See [`save_document()`]
+pub(crate) fn document_stats<'a, I, O>(
+    changes: I,
+    ops: O,
+    actors: &'a IndexedCache<ActorId>,
+    props: &'a IndexedCache<String>,
+    heads: &[ChangeHash],
+    compression: Compression,
+) -> DocumentStats
+where
+    I: Iterator<Item = &'a Change> + Clone + 'a,
+    O: Iterator<Item = (&'a ObjId, Op<'a>)> + Clone + ExactSizeIterator,
+{
+    let encoder = Encoder::new(changes.clone(), actors, props);
+    let config = compress_config(compression);
+    let doc_ops = ops.clone().map(|(_obj, op)| encoder.doc_op(op));
+    let doc_changes = changes.map(|c| encoder.change(c));
+    let (change_columns, op_columns) =
+        Document::column_sizes(doc_ops.clone(), doc_changes.clone(), &config);
+    let total_size = Document::new(
+        encoder.actor_ids.clone(),
+        encoder.hash_graph.heads_with_indices(heads.to_vec()),
+        doc_ops,
+        doc_changes,
+        config,
+    )
+    .into_bytes()
+    .len();
+    let mut objects = Vec::new();
+    for (obj, obj_ops) in &ops.group_by(|(obj, _)| *obj) {
+        let obj_ops = obj_ops.collect::<Vec<_>>();
+        objects.push((
+            *obj,
+            encoded_ops_len(obj_ops.iter().map(|(_obj, op)| encoder.doc_op(*op))),
+        ));
+    }
+    DocumentStats {
+        total_size,
+        change_columns,
+        op_columns,
+        objects,
+    }
+}
+
+fn compress_config(compression: Compression) -> CompressConfig {
+    match compression {
+        Compression::None => CompressConfig::None,
+        compression => CompressConfig::Threshold {
+            threshold: DEFLATE_MIN_SIZE,
+            compression,
+        },
+    }
+}
+
+/// The actor indices and change indices used to encode a set of changes and their ops
+struct Encoder<'a> {
+    actors: &'a IndexedCache<ActorId>,
+    props: &'a IndexedCache<String>,
+    /// The actors of the changes, sorted
+    actor_ids: Vec<ActorId>,
+    /// A map from indices in `actors` to indices in `actor_ids`
+    actor_lookup: HashMap<usize, usize>,
+    hash_graph: HashGraph,
+}
+
+impl<'a> Encoder<'a> {
+    fn new<I>(
+        changes: I,
+        actors: &'a IndexedCache<ActorId>,
+        props: &'a IndexedCache<String>,
+    ) -> Self
+    where
+        I: Iterator<Item = &'a Change> + Clone,
+    {
+        let mut actor_lookup = HashMap::with_capacity(actors.len());
+        let mut actor_ids = changes
+            .clone()
+            .map(|c| c.actor_id().clone())
+            .unique()
+            .collect::<Vec<_>>();
+        actor_ids.sort();
+        for (index, actor_id) in actor_ids.iter().enumerate() {
+            actor_lookup.insert(actors.lookup(actor_id).unwrap(), index);
+        }
+        Self {
+            actors,
+            props,
+            actor_ids,
+            actor_lookup,
+            hash_graph: HashGraph::new(changes),
+        }
+    }
+
+    fn doc_op<'s>(&'s self, op: Op<'s>) -> OpAsDocOp<'s> {
+        op_as_docop(&self.actor_lookup, self.props, op)
+    }
+
+    fn change<'s>(&'s self, change: &'s Change) -> ChangeWithGraph<'s> {
+        ChangeWithGraph {
+            actors: self.actors,
+            actor_lookup: &self.actor_lookup,
+            change,
+            graph: &self.hash_graph,
+        }
+    }
+}
+
 struct HashGraph {
     index_by_hash: HashMap<ChangeHash, usize, FxBuildHasher>,
 }
@@ -0,0 +1,80 @@
+//! Encoding and decoding of the versioned format described in the crate docs under "Persisting
+//! vector clocks, redaction records and branches"
+//!
+//! Each type documents the order of its fields where it is encoded.
+use super::parse;
+use crate::ChangeHash;
+
+const VERSION: u8 = 0;
+
+/// The version byte was missing or not one we know how to read
+#[derive(Debug)]
+pub(crate) enum VersionError {
+    NoVersion,
+    InvalidVersion(u8),
+}
+
+pub(crate) struct Encoder(Vec<u8>);
+
+impl Encoder {
+    pub(crate) fn new() -> Self {
+        Encoder(vec![VERSION])
+    }
+
+    pub(crate) fn uleb(&mut self, value: u64) {
+        leb128::write::unsigned(&mut self.0, value).unwrap();
+    }
+
+    /// The length of `bytes` followed by `bytes`
+    pub(crate) fn len_prefixed(&mut self, bytes: &[u8]) {
+        self.uleb(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn hash(&mut self, hash: &ChangeHash) {
+        self.0.extend_from_slice(hash.as_bytes());
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+pub(crate) struct Decoder<'a>(parse::Input<'a>);
+
+impl<'a> Decoder<'a> {
+    /// Start decoding `data`, checking the version byte
+    pub(crate) fn new(data: &'a [u8]) -> Result<Self, VersionError> {
+        let (i, version) =
+            parse::take1::<()>(parse::Input::new(data)).map_err(|_| VersionError::NoVersion)?;
+        if version != VERSION {
+            return Err(VersionError::InvalidVersion(version));
+        }
+        Ok(Decoder(i))
+    }
+
+    pub(crate) fn uleb(&mut self) -> Result<u64, parse::ParseError<parse::leb128::Error>> {
+        let (i, value) = parse::leb128_u64(self.0)?;
+        self.0 = i;
+        Ok(value)
+    }
+
+    /// The next `len` bytes, `None` if there are not enough bytes left
+    pub(crate) fn take(&mut self, len: u64) -> Option<&'a [u8]> {
+        let (i, bytes) = parse::take_n::<()>(len as usize, self.0).ok()?;
+        self.0 = i;
+        Some(bytes)
+    }
+
+    /// The next change hash, `None` if there are not enough bytes left
+    pub(crate) fn hash(&mut self) -> Option<ChangeHash> {
+        let (i, hash) = parse::change_hash::<()>(self.0).ok()?;
+        self.0 = i;
+        Some(hash)
+    }
+
+    /// Whether all the data has been decoded
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
@@ -0,0 +1,101 @@
+//! # Storage statistics
+//!
+//! [`Automerge::storage_stats()`](crate::Automerge::storage_stats) encodes a document the same
+//! way [`Automerge::save()`](crate::Automerge::save) does and reports where the bytes went. The
+//! names of the columns are the names used in the [storage format
+//! documentation](https://alexjg.github.io/automerge-storage-docs/).
+//!
+//! ```
+//! use automerge::{transaction::Transactable, AutoCommit, ObjType, ROOT};
+//! let mut doc = AutoCommit::new();
+//! let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+//! doc.splice_text(&text, 0, 0, "hello").unwrap();
+//! let stats = doc.storage_stats();
+//! assert_eq!(stats.total_size, doc.save().len());
+//! let largest = stats.objects.iter().max_by_key(|o| o.encoded_size).unwrap();
+//! assert_eq!(largest.obj, text);
+//! ```
+
+use crate::{op_set::Op, types::OpType, ObjId, ObjType};
+
+/// The size of a saved document broken down by column and by object
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageStats {
+    /// The size of the document chunk, this is the length of the output of
+    /// [`Automerge::save()`](crate::Automerge::save) unless the document has orphaned changes
+    pub total_size: usize,
+    /// The columns which encode the metadata of each change
+    pub change_columns: Vec<ColumnStats>,
+    /// The columns which encode the operations in the document
+    pub op_columns: Vec<ColumnStats>,
+    /// Counts of all the operations in the document
+    pub ops: OpStats,
+    /// The operations of each object in the document, in the order they are saved
+    pub objects: Vec<ObjectStats>,
+}
+
+/// The size of a column in a saved document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnStats {
+    /// The name of the column, e.g. `keyStr` or `succNum`
+    pub name: &'static str,
+    /// The size of the column after run length encoding
+    pub raw_size: usize,
+    /// The size of the column as it is saved, after any compression
+    ///
+    /// Columns which are too small to benefit from compression are saved uncompressed
+    pub compressed_size: usize,
+}
+
+/// Counts of operations by type
+///
+/// Deletions are not stored as separate operations, instead they are recorded in the successors
+/// of the operations they delete and so make those operations tombstones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpStats {
+    /// The total number of operations
+    pub total: usize,
+    /// Operations which create an object
+    pub make: usize,
+    /// Operations which set a scalar value
+    pub put: usize,
+    /// Operations which increment a counter
+    pub increment: usize,
+    /// Operations which begin or end a mark
+    pub mark: usize,
+    /// Operations which have been deleted or overwritten and are no longer visible
+    pub tombstones: usize,
+}
+
+impl OpStats {
+    pub(crate) fn add(&mut self, op: Op<'_>) {
+        self.total += 1;
+        match op.action() {
+            OpType::Make(_) => self.make += 1,
+            OpType::Put(_) => self.put += 1,
+            OpType::Increment(_) => self.increment += 1,
+            OpType::MarkBegin(..) | OpType::MarkEnd(_) => self.mark += 1,
+            OpType::Delete => {}
+        }
+        if !op.visible() {
+            self.tombstones += 1;
+        }
+    }
+}
+
+/// The operations in a single object
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectStats {
+    /// The object
+    pub obj: ObjId,
+    /// The type of the object
+    pub obj_type: ObjType,
+    /// Counts of the operations in the object
+    pub ops: OpStats,
+    /// The size of the op columns when only this object's operations are encoded, before
+    /// compression
+    ///
+    /// Run length encoding works across object boundaries so the sum of these sizes is slightly
+    /// larger than the size of the op columns of the whole document.
+    pub encoded_size: usize,
+}
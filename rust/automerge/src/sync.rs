@@ -66,6 +66,21 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! Applications which synchronize one document with many peers can use a [`SyncManager`] rather
+//! than managing a [`State`] for each peer themselves.
+//!
+//! To synchronize over a byte stream such as a TCP socket, use [`write_frame()`] and
+//! [`read_frame()`] to delimit messages, or [`sync_over_stream()`] to run the whole protocol.
+//! Async versions of these functions are available with the `tokio` feature. To talk to peers
+//! running the JavaScript `automerge-repo` library see the [`repo`] module.
+//!
+//! ## Ephemeral messages
+//!
+//! Data such as cursor positions should be shared with peers but never stored in the document.
+//! [`EphemeralMessage`]s can be sent over the same channel as sync messages, use
+//! [`ChannelMessage::decode()`] to tell the two apart on the receiving end and a [`Presence`] to
+//! track the most recent ephemeral data from each peer.
 
 use itertools::Itertools;
 use serde::ser::SerializeMap;
@@ -78,14 +93,32 @@ use crate::{
 };
 
 mod bloom;
+mod document_id;
+mod ephemeral;
+mod framing;
+mod iblt;
+mod manager;
 mod message_builder;
+pub mod repo;
 mod state;
 use message_builder::MessageBuilder;
 
 #[cfg(test)]
 mod v1_compat_test;
 
-pub use bloom::{BloomFilter, DecodeError as DecodeBloomError};
+pub use bloom::{BloomFilter, BloomParams, DecodeError as DecodeBloomError};
+pub use document_id::{DocumentId, ParseDocumentIdError};
+pub use ephemeral::{ChannelMessage, EphemeralMessage, PeerPresence, Presence};
+pub use framing::{
+    read_frame, sync_over_stream, sync_over_stream_with_state, write_frame, FrameError,
+    SyncStreamError, FRAME_MAGIC, FRAME_VERSION,
+};
+#[cfg(feature = "tokio")]
+pub use framing::{
+    read_frame_async, sync_over_stream_async, sync_over_stream_with_state_async, write_frame_async,
+};
+pub use iblt::{Iblt, SetDifference};
+pub use manager::SyncManager;
 pub use state::DecodeError as DecodeStateError;
 pub use state::{Have, State};
 
@@ -175,7 +208,7 @@ impl SyncDoc for Automerge {
             HashSet::new()
         };
         let our_have = if our_need.iter().all(|hash| their_heads_set.contains(hash)) {
-            vec![self.make_bloom_filter(sync_state.shared_heads.clone())]
+            vec![self.make_have(sync_state)]
         } else {
             Vec::new()
         };
@@ -192,10 +225,7 @@ impl SyncDoc for Automerge {
                         need: Vec::new(),
                         have: vec![Have::default()],
                         changes: ChunkList::empty(),
-                        supported_capabilities: Some(vec![
-                            Capability::MessageV1,
-                            Capability::MessageV2,
-                        ]),
+                        supported_capabilities: Some(sync_state.our_capabilities()),
                         version: MessageVersion::V1,
                     };
                     return Some(reset_msg);
@@ -207,12 +237,17 @@ impl SyncDoc for Automerge {
             sync_state.their_have.as_ref(),
             sync_state.their_need.as_ref(),
         ) {
+            // If the other end has nothing then sending them a compressed document is much more
+            // efficient than sending every change individually. We check `sent_hashes` rather
+            // than `have_responded` so that this also happens when we were the one to initiate
+            // the sync, and `our_heads` so that we don't send empty documents back and forth.
             let send_doc = sync_state
                 .their_heads
                 .as_ref()
                 .map(|h| h.is_empty())
                 .unwrap_or(false)
-                && !sync_state.have_responded
+                && !our_heads.is_empty()
+                && sync_state.sent_hashes.is_empty()
                 && sync_state.supports_v2_messages();
 
             if send_doc {
@@ -269,7 +304,7 @@ impl SyncDoc for Automerge {
         let supported_capabilities = if sync_state.have_responded {
             None
         } else {
-            Some(vec![Capability::MessageV1, Capability::MessageV2])
+            Some(sync_state.our_capabilities())
         };
 
         sync_state.have_responded = true;
@@ -307,12 +342,42 @@ impl SyncDoc for Automerge {
 }
 
 impl Automerge {
-    fn make_bloom_filter(&self, last_sync: Vec<ChangeHash>) -> Have {
+    fn make_have(&self, sync_state: &State) -> Have {
+        let last_sync = sync_state.shared_heads.clone();
         let new_changes = self.get_changes(&last_sync);
         let hashes = new_changes.iter().map(|change| change.hash());
+        let iblt = match sync_state.iblt_cells {
+            Some(num_cells) if sync_state.supports_iblt() => {
+                Some(Iblt::from_hashes(num_cells, hashes.clone()))
+            }
+            _ => None,
+        };
         Have {
             last_sync,
-            bloom: BloomFilter::from_hashes(hashes),
+            bloom: BloomFilter::from_hashes_with_params(hashes, sync_state.bloom_params),
+            iblt,
+        }
+    }
+
+    /// Use the IBLT in `have` (if any) to compute exactly which of our changes since
+    /// `have.last_sync` the sender of `have` is missing
+    ///
+    /// Returns `None` if there is no IBLT or it could not be decoded, in which case the bloom
+    /// filter must be used instead.
+    fn missing_from_iblt(&self, have: &Have) -> Option<HashSet<ChangeHash>> {
+        let theirs = have.iblt.as_ref()?;
+        let our_hashes = self
+            .get_changes(&have.last_sync)
+            .into_iter()
+            .map(|c| c.hash())
+            .collect::<HashSet<_>>();
+        let ours = Iblt::from_hashes(theirs.num_cells(), our_hashes.iter());
+        let diff = ours.difference(theirs)?;
+        // A corrupt table could decode to hashes we don't have, don't trust it if so
+        if diff.only_in_self.iter().all(|h| our_hashes.contains(h)) {
+            Some(diff.only_in_self.into_iter().collect())
+        } else {
+            None
         }
     }
 
@@ -331,9 +396,8 @@ impl Automerge {
             let mut bloom_filters = Vec::with_capacity(have.len());
 
             for h in have {
-                let Have { last_sync, bloom } = h;
-                last_sync_hashes.extend(last_sync);
-                bloom_filters.push(bloom);
+                last_sync_hashes.extend(&h.last_sync);
+                bloom_filters.push((&h.bloom, self.missing_from_iblt(h)));
             }
             let last_sync_hashes = last_sync_hashes.into_iter().copied().collect::<Vec<_>>();
 
@@ -350,10 +414,10 @@ impl Automerge {
                     dependents.entry(*dep).or_default().push(change.hash());
                 }
 
-                if bloom_filters
-                    .iter()
-                    .all(|bloom| !bloom.contains_hash(&change.hash()))
-                {
+                if bloom_filters.iter().all(|(bloom, missing)| match missing {
+                    Some(missing) => missing.contains(&change.hash()),
+                    None => !bloom.contains_hash(&change.hash()),
+                }) {
                     hashes_to_send.insert(change.hash());
                 }
             }
@@ -482,6 +546,12 @@ impl From<bloom::ParseError> for ReadMessageError {
     }
 }
 
+impl From<iblt::ParseError> for ReadMessageError {
+    fn from(e: iblt::ParseError) -> Self {
+        ReadMessageError::Parse(e.to_string())
+    }
+}
+
 impl From<crate::storage::change::ParseError> for ReadMessageError {
     fn from(e: crate::storage::change::ParseError) -> Self {
         ReadMessageError::Parse(format!("error parsing changes: {}", e))
@@ -600,8 +670,21 @@ impl serde::Serialize for Message {
 fn parse_have(input: parse::Input<'_>) -> parse::ParseResult<'_, Have, ReadMessageError> {
     let (i, last_sync) = parse::length_prefixed(parse::change_hash)(input)?;
     let (i, bloom_bytes) = parse::length_prefixed_bytes(i)?;
-    let (_, bloom) = BloomFilter::parse(parse::Input::new(bloom_bytes)).map_err(|e| e.lift())?;
-    Ok((i, Have { last_sync, bloom }))
+    let (rest, bloom) = BloomFilter::parse(parse::Input::new(bloom_bytes)).map_err(|e| e.lift())?;
+    let iblt = if rest.is_empty() {
+        None
+    } else {
+        let (_, iblt) = Iblt::parse(rest).map_err(|e| e.lift())?;
+        Some(iblt)
+    };
+    Ok((
+        i,
+        Have {
+            last_sync,
+            bloom,
+            iblt,
+        },
+    ))
 }
 
 impl Message {
@@ -648,8 +731,9 @@ impl Message {
         encode_hashes(&mut buf, &self.need);
         encode_many(&mut buf, self.have.iter(), |buf, h| {
             encode_hashes(buf, &h.last_sync);
-            leb128::write::unsigned(buf, h.bloom.to_bytes().len() as u64).unwrap();
-            buf.extend(h.bloom.to_bytes());
+            let filter = h.filter_bytes();
+            leb128::write::unsigned(buf, filter.len() as u64).unwrap();
+            buf.extend(filter);
         });
 
         encode_many(&mut buf, self.changes.iter(), |buf, change| {
@@ -672,6 +756,8 @@ pub enum Capability {
     #[default]
     MessageV1,
     MessageV2,
+    /// The peer understands [`Iblt`]s appended to the bloom filters in [`Have`]s
+    Iblt,
     Unknown(u8),
 }
 
@@ -680,6 +766,7 @@ impl Capability {
         match self {
             Capability::MessageV1 => out.push(0x01),
             Capability::MessageV2 => out.push(0x02),
+            Capability::Iblt => out.push(0x03),
             Capability::Unknown(v) => out.push(*v),
         }
     }
@@ -689,6 +776,7 @@ impl Capability {
         match v {
             0x01 => Ok((i, Self::MessageV1)),
             0x02 => Ok((i, Self::MessageV2)),
+            0x03 => Ok((i, Self::Iblt)),
             _ => Ok((i, Self::Unknown(v))),
         }
     }
@@ -757,10 +845,15 @@ mod tests {
     }
 
     prop_compose! {
-        fn gen_have()(bloom in gen_bloom(), last_sync in gen_sorted_hashes(0..10))  -> Have {
+        fn gen_have()(
+            bloom in gen_bloom(),
+            last_sync in gen_sorted_hashes(0..10),
+            iblt in proptest::option::of(gen_sorted_hashes(0..10)),
+        )  -> Have {
             Have {
                 bloom,
                 last_sync,
+                iblt: iblt.map(|hashes| Iblt::from_hashes(6, hashes.into_iter())),
             }
         }
     }
@@ -783,6 +876,7 @@ mod tests {
                 Just(Some(vec![Capability::MessageV1])),
                 Just(Some(vec![Capability::MessageV2])),
                 Just(Some(vec![Capability::MessageV1, Capability::MessageV2])),
+                Just(Some(vec![Capability::MessageV1, Capability::MessageV2, Capability::Iblt])),
             ],
         ) -> Message {
             Message {
@@ -807,6 +901,7 @@ mod tests {
                 Just(Some(vec![Capability::MessageV1])),
                 Just(Some(vec![Capability::MessageV2])),
                 Just(Some(vec![Capability::MessageV1, Capability::MessageV2])),
+                Just(Some(vec![Capability::MessageV1, Capability::MessageV2, Capability::Iblt])),
             ],
         ) -> Message {
             Message {
@@ -1024,6 +1119,151 @@ mod tests {
         assert_eq!(actual_heads, expected_heads);
     }
 
+    #[test]
+    fn bloom_params_from_false_positive_rate() {
+        assert_eq!(
+            BloomParams::with_false_positive_rate(0.01),
+            BloomParams::default()
+        );
+        let params = BloomParams::with_false_positive_rate(0.0001);
+        assert_eq!(params.bits_per_entry(), 20);
+        assert_eq!(params.num_probes(), 14);
+        assert!(params.false_positive_rate() < 0.0001);
+        assert!(BloomParams::default().false_positive_rate() < 0.01);
+    }
+
+    #[test]
+    fn sync_with_custom_bloom_params() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        s1.set_bloom_params(BloomParams::with_false_positive_rate(0.001));
+        let mut s2 = State::new();
+        s2.set_bloom_params(BloomParams::new(4, 2));
+        for i in 0..10 {
+            doc1.put(crate::ROOT, "x", i).unwrap();
+            doc1.commit();
+            doc2.put(crate::ROOT, "y", i).unwrap();
+            doc2.commit();
+        }
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
+    #[test]
+    fn iblt_is_only_sent_when_both_peers_opt_in() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        doc1.put(crate::ROOT, "x", 1).unwrap();
+        doc2.put(crate::ROOT, "y", 1).unwrap();
+
+        let mut s1 = State::new();
+        s1.set_iblt_cells(Some(30));
+        let mut s2 = State::new();
+        let msg = doc1.sync().generate_sync_message(&mut s1).unwrap();
+        assert!(msg
+            .supported_capabilities
+            .as_ref()
+            .unwrap()
+            .contains(&Capability::Iblt));
+        doc2.sync().receive_sync_message(&mut s2, msg).unwrap();
+        let msg = doc2.sync().generate_sync_message(&mut s2).unwrap();
+        assert!(msg.have.iter().all(|h| h.iblt().is_none()));
+        assert!(!msg
+            .supported_capabilities
+            .as_ref()
+            .unwrap()
+            .contains(&Capability::Iblt));
+
+        s2.set_iblt_cells(Some(30));
+        doc1.sync().receive_sync_message(&mut s1, msg).unwrap();
+        let msg = doc1.sync().generate_sync_message(&mut s1).unwrap();
+        assert!(msg.have.iter().all(|h| h.iblt().is_none()));
+        doc2.sync().receive_sync_message(&mut s2, msg).unwrap();
+
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
+    #[test]
+    fn sync_with_iblt() {
+        let mut doc1 = crate::AutoCommit::new().with_actor(ActorId::try_from("abc123").unwrap());
+        let mut doc2 = crate::AutoCommit::new().with_actor(ActorId::try_from("def456").unwrap());
+        let mut s1 = State::new();
+        s1.set_iblt_cells(Some(30));
+        let mut s2 = State::new();
+        s2.set_iblt_cells(Some(30));
+        for i in 0..10 {
+            doc1.put(crate::ROOT, "x", i).unwrap();
+            doc1.commit();
+        }
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        for i in 0..5 {
+            doc1.put(crate::ROOT, "x", i).unwrap();
+            doc1.commit();
+            doc2.put(crate::ROOT, "y", i).unwrap();
+            doc2.commit();
+        }
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
+    #[test]
+    fn iblt_avoids_bloom_false_positives() {
+        // Find two concurrent changes n1 and n2 where n2 is a false positive in the bloom
+        // filter containing {n1}
+        let mut doc = crate::AutoCommit::new().with_actor(ActorId::try_from("abc123").unwrap());
+        for i in 0..10 {
+            doc.put(crate::ROOT, "x", i).unwrap();
+            doc.commit();
+        }
+        let last_sync = doc.get_heads();
+        let mut i = 0;
+        let (n1, mut doc2) = loop {
+            let mut doc1copy = doc
+                .clone()
+                .with_actor(ActorId::try_from("01234567").unwrap());
+            doc1copy
+                .put(crate::ROOT, "x", format!("{} @ n1", i))
+                .unwrap();
+            doc1copy.commit();
+
+            let mut doc2copy = doc
+                .clone()
+                .with_actor(ActorId::try_from("89abcdef").unwrap());
+            doc2copy
+                .put(crate::ROOT, "x", format!("{} @ n2", i))
+                .unwrap();
+            doc2copy.commit();
+
+            let n1 = doc1copy.get_heads();
+            let n1_bloom = BloomFilter::from_hashes(n1.iter());
+            if n1_bloom.contains_hash(&doc2copy.get_heads()[0]) {
+                break (n1, doc2copy);
+            }
+            i += 1;
+        };
+        let n2 = doc2.get_heads()[0];
+
+        let bloom_only = Have::new(last_sync.clone(), BloomFilter::from_hashes(n1.iter()));
+        let to_send = doc2
+            .document()
+            .get_changes_to_send(&[bloom_only], &[])
+            .unwrap();
+        assert!(to_send.is_empty());
+
+        let with_iblt = Have::new(last_sync, BloomFilter::from_hashes(n1.iter()))
+            .with_iblt(Iblt::from_hashes(30, n1.iter()));
+        let to_send = doc2
+            .document()
+            .get_changes_to_send(&[with_iblt], &[])
+            .unwrap()
+            .into_iter()
+            .map(|c| c.hash())
+            .collect::<Vec<_>>();
+        assert_eq!(to_send, vec![n2]);
+    }
+
     #[test]
     fn should_handle_false_positive_head() {
         // Scenario:                                                            ,-- n1
@@ -1287,4 +1527,53 @@ mod tests {
         let (_, chunk) = Chunk::parse(Input::new(&changes.0[0])).unwrap();
         assert!(matches!(chunk, Chunk::Document(_)));
     }
+
+    #[test]
+    fn if_initiator_has_data_and_peer_has_no_heads_send_whole_doc() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        doc1.put(crate::ROOT, "foo", "bar").unwrap();
+        doc1.put(crate::ROOT, "baz", "qux").unwrap();
+
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+
+        let outgoing = doc1
+            .sync()
+            .generate_sync_message(&mut s1)
+            .expect("message was none");
+        doc2.sync().receive_sync_message(&mut s2, outgoing).unwrap();
+
+        let response = doc2
+            .sync()
+            .generate_sync_message(&mut s2)
+            .expect("response was none");
+        assert!(response.heads.is_empty());
+        doc1.sync().receive_sync_message(&mut s1, response).unwrap();
+
+        let outgoing = doc1
+            .sync()
+            .generate_sync_message(&mut s1)
+            .expect("message was none");
+        assert_eq!(outgoing.version, MessageVersion::V2);
+        assert_eq!(outgoing.changes.len(), 1);
+        let (_, chunk) = Chunk::parse(Input::new(&outgoing.changes.0[0])).unwrap();
+        assert!(matches!(chunk, Chunk::Document(_)));
+
+        doc2.sync().receive_sync_message(&mut s2, outgoing).unwrap();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
+    #[test]
+    fn empty_peers_do_not_exchange_documents() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert!(doc1.get_heads().is_empty());
+        assert!(doc2.get_heads().is_empty());
+    }
 }
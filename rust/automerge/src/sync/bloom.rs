@@ -9,6 +9,69 @@ use crate::ChangeHash;
 const BITS_PER_ENTRY: u32 = 10;
 const NUM_PROBES: u32 = 7;
 
+/// The parameters used when constructing a [`BloomFilter`]
+///
+/// The defaults correspond to a 1% false positive rate. Documents with very large concurrent
+/// histories may want a lower false positive rate in exchange for larger sync messages, as every
+/// false positive costs an extra round trip. The parameters are encoded in each bloom filter so
+/// peers using different parameters remain compatible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BloomParams {
+    bits_per_entry: u32,
+    num_probes: u32,
+}
+
+impl Default for BloomParams {
+    fn default() -> Self {
+        BloomParams {
+            bits_per_entry: BITS_PER_ENTRY,
+            num_probes: NUM_PROBES,
+        }
+    }
+}
+
+impl BloomParams {
+    /// Create parameters with the given number of bits per entry and number of probes
+    ///
+    /// Both values are clamped to be at least 1.
+    pub fn new(bits_per_entry: u32, num_probes: u32) -> Self {
+        BloomParams {
+            bits_per_entry: bits_per_entry.max(1),
+            num_probes: num_probes.max(1),
+        }
+    }
+
+    /// Create the smallest parameters which achieve (approximately) the given false positive rate
+    ///
+    /// `rate` is clamped to the range `[1e-9, 0.5]`.
+    pub fn with_false_positive_rate(rate: f64) -> Self {
+        let rate = if rate.is_nan() {
+            0.01
+        } else {
+            rate.clamp(1e-9, 0.5)
+        };
+        let ln2 = std::f64::consts::LN_2;
+        let bits_per_entry = (-rate.ln() / (ln2 * ln2)).ceil();
+        let num_probes = (bits_per_entry * ln2).round();
+        Self::new(bits_per_entry as u32, num_probes as u32)
+    }
+
+    pub fn bits_per_entry(&self) -> u32 {
+        self.bits_per_entry
+    }
+
+    pub fn num_probes(&self) -> u32 {
+        self.num_probes
+    }
+
+    /// The expected false positive rate of a bloom filter constructed with these parameters
+    pub fn false_positive_rate(&self) -> f64 {
+        let k = f64::from(self.num_probes);
+        let b = f64::from(self.bits_per_entry);
+        (1.0 - (-k / b).exp()).powf(k)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub struct BloomFilter {
     num_entries: u32,
@@ -46,6 +109,17 @@ impl BloomFilter {
         buf
     }
 
+    /// Like [`Self::to_bytes()`] but always encodes the header, even for an empty filter. This
+    /// is used when the filter is followed by other data in the same length delimited field.
+    pub(crate) fn to_bytes_with_header(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        leb128::write::unsigned(&mut buf, self.num_entries as u64).unwrap();
+        leb128::write::unsigned(&mut buf, self.num_bits_per_entry as u64).unwrap();
+        leb128::write::unsigned(&mut buf, self.num_probes as u64).unwrap();
+        buf.extend(&self.bits);
+        buf
+    }
+
     pub(crate) fn parse(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, ParseError> {
         if input.is_empty() {
             Ok((input, Self::default()))
@@ -123,9 +197,16 @@ impl BloomFilter {
     }
 
     pub fn from_hashes<H: Borrow<ChangeHash>>(hashes: impl ExactSizeIterator<Item = H>) -> Self {
+        Self::from_hashes_with_params(hashes, BloomParams::default())
+    }
+
+    pub fn from_hashes_with_params<H: Borrow<ChangeHash>>(
+        hashes: impl ExactSizeIterator<Item = H>,
+        params: BloomParams,
+    ) -> Self {
         let num_entries = hashes.len() as u32;
-        let num_bits_per_entry = BITS_PER_ENTRY;
-        let num_probes = NUM_PROBES;
+        let num_bits_per_entry = params.bits_per_entry;
+        let num_probes = params.num_probes;
         let bits = vec![0; bits_capacity(num_entries, num_bits_per_entry)];
         let mut filter = Self {
             num_entries,
@@ -0,0 +1,202 @@
+use std::{fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+const URL_PREFIX: &str = "automerge:";
+
+/// The ID of a document in an automerge-repo
+///
+/// A document ID is 16 random bytes. It is written as a
+/// [bs58check](https://en.bitcoin.it/wiki/Base58Check_encoding) string (base58 with a four byte
+/// checksum), and shared as an `automerge:` URL. This is the same format the JavaScript
+/// `automerge-repo` library uses, so IDs and URLs can be passed between the two.
+///
+/// ```
+/// use automerge::sync::DocumentId;
+///
+/// let id: DocumentId = "4NMNnkMhL8jXrdJ9jamS58PAVdXu".parse().unwrap();
+/// assert_eq!(id.to_url(), "automerge:4NMNnkMhL8jXrdJ9jamS58PAVdXu");
+/// assert_eq!(DocumentId::from_url(&id.to_url()).unwrap(), id);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DocumentId([u8; 16]);
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum ParseDocumentIdError {
+    #[error("not an automerge URL")]
+    NotAnAutomergeUrl,
+    #[error("invalid base58 character")]
+    InvalidBase58,
+    #[error("invalid checksum")]
+    InvalidChecksum,
+    #[error("expected 16 bytes but got {0}")]
+    InvalidLength(usize),
+}
+
+impl DocumentId {
+    /// Generate a new random document ID
+    pub fn random() -> Self {
+        DocumentId(*uuid::Uuid::new_v4().as_bytes())
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    /// The `automerge:` URL for this document
+    pub fn to_url(&self) -> String {
+        format!("{}{}", URL_PREFIX, self)
+    }
+
+    /// Parse an `automerge:` URL
+    pub fn from_url(url: &str) -> Result<Self, ParseDocumentIdError> {
+        url.strip_prefix(URL_PREFIX)
+            .ok_or(ParseDocumentIdError::NotAnAutomergeUrl)?
+            .parse()
+    }
+}
+
+impl From<[u8; 16]> for DocumentId {
+    fn from(bytes: [u8; 16]) -> Self {
+        DocumentId(bytes)
+    }
+}
+
+impl From<uuid::Uuid> for DocumentId {
+    fn from(u: uuid::Uuid) -> Self {
+        DocumentId(*u.as_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for DocumentId {
+    type Error = ParseDocumentIdError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        bytes
+            .try_into()
+            .map(DocumentId)
+            .map_err(|_| ParseDocumentIdError::InvalidLength(bytes.len()))
+    }
+}
+
+impl AsRef<[u8]> for DocumentId {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromStr for DocumentId {
+    type Err = ParseDocumentIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bs58::decode(s)
+            .with_check(None)
+            .into_vec()
+            .map_err(|e| match e {
+                bs58::decode::Error::InvalidChecksum { .. } => {
+                    ParseDocumentIdError::InvalidChecksum
+                }
+                bs58::decode::Error::NoChecksum => ParseDocumentIdError::InvalidLength(0),
+                _ => ParseDocumentIdError::InvalidBase58,
+            })?;
+        DocumentId::try_from(bytes.as_slice())
+    }
+}
+
+impl fmt::Display for DocumentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", bs58::encode(&self.0).with_check().into_string())
+    }
+}
+
+impl fmt::Debug for DocumentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DocumentId")
+            .field(&self.to_string())
+            .finish()
+    }
+}
+
+impl Serialize for DocumentId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DocumentId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(|_| {
+            de::Error::invalid_value(de::Unexpected::Str(&s), &"a bs58check document ID")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The ID used in the automerge-repo protocol fixtures
+    const ID: &str = "4NMNnkMhL8jXrdJ9jamS58PAVdXu";
+    const BYTES: [u8; 16] = [
+        0xf1, 0xc2, 0x9c, 0x84, 0x74, 0xc8, 0x4a, 0xde, 0xb8, 0x00, 0xbe, 0x47, 0x62, 0x7d, 0x33,
+        0xbf,
+    ];
+
+    #[test]
+    fn parse_and_format() {
+        let id: DocumentId = ID.parse().unwrap();
+        assert_eq!(id.as_bytes(), &BYTES);
+        assert_eq!(id.to_string(), ID);
+        assert_eq!(DocumentId::from(BYTES), id);
+        assert_eq!(
+            DocumentId::from_url("automerge:4NMNnkMhL8jXrdJ9jamS58PAVdXu"),
+            Ok(id)
+        );
+        assert_eq!(id.to_url(), "automerge:4NMNnkMhL8jXrdJ9jamS58PAVdXu");
+    }
+
+    #[test]
+    fn random_ids_roundtrip() {
+        let id = DocumentId::random();
+        assert_eq!(id.to_string().parse::<DocumentId>(), Ok(id));
+        assert_ne!(DocumentId::random(), id);
+    }
+
+    #[test]
+    fn invalid_ids() {
+        assert_eq!(
+            "4NMNnkMhL8jXrdJ9jamS58PAVdXv".parse::<DocumentId>(),
+            Err(ParseDocumentIdError::InvalidChecksum)
+        );
+        assert_eq!(
+            "4NMNnkMhL8jXrdJ9jamS58PAVdX0".parse::<DocumentId>(),
+            Err(ParseDocumentIdError::InvalidBase58)
+        );
+        // A valid bs58check string with an eight byte payload
+        let short = bs58::encode([1u8; 8]).with_check().into_string();
+        assert_eq!(
+            short.parse::<DocumentId>(),
+            Err(ParseDocumentIdError::InvalidLength(8))
+        );
+        assert_eq!(
+            DocumentId::from_url(ID),
+            Err(ParseDocumentIdError::NotAnAutomergeUrl)
+        );
+    }
+
+    #[test]
+    fn serde() {
+        let id: DocumentId = ID.parse().unwrap();
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{}\"", ID));
+        assert_eq!(serde_json::from_str::<DocumentId>(&json).unwrap(), id);
+        assert!(serde_json::from_str::<DocumentId>("\"not an id\"").is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use crate::storage::parse;
+use crate::ActorId;
+
+use super::{Message, ReadMessageError, MESSAGE_TYPE_SYNC, MESSAGE_TYPE_SYNC_V2};
+
+pub(super) const MESSAGE_TYPE_EPHEMERAL: u8 = 0x44; // first byte of an ephemeral message
+
+/// A message which is sent alongside sync messages but is never stored in the document
+///
+/// Ephemeral messages are intended for things like cursor positions and "user is typing"
+/// indicators. The payload is opaque to automerge. Each sender should increment `count` for every
+/// message it sends so that receivers can discard messages which arrive out of order.
+///
+/// The first byte of an encoded ephemeral message is distinct from the first byte of an encoded
+/// [`Message`], so both can be sent over the same channel and told apart using
+/// [`ChannelMessage::decode()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EphemeralMessage {
+    /// The actor which sent this message
+    pub sender: ActorId,
+    /// A counter which increases with each message `sender` sends
+    pub count: u64,
+    /// The payload of the message
+    pub data: Vec<u8>,
+}
+
+impl EphemeralMessage {
+    pub fn new(sender: ActorId, count: u64, data: Vec<u8>) -> Self {
+        EphemeralMessage {
+            sender,
+            count,
+            data,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![MESSAGE_TYPE_EPHEMERAL];
+        let actor = self.sender.to_bytes();
+        leb128::write::unsigned(&mut buf, actor.len() as u64).unwrap();
+        buf.extend(actor);
+        leb128::write::unsigned(&mut buf, self.count).unwrap();
+        leb128::write::unsigned(&mut buf, self.data.len() as u64).unwrap();
+        buf.extend(&self.data);
+        buf
+    }
+
+    pub fn decode(input: &[u8]) -> Result<Self, ReadMessageError> {
+        let (_, msg) = Self::parse(parse::Input::new(input))?;
+        Ok(msg)
+    }
+
+    pub(crate) fn parse(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, ReadMessageError> {
+        let (i, first_byte) = parse::take1(input)?;
+        if first_byte != MESSAGE_TYPE_EPHEMERAL {
+            return Err(parse::ParseError::Error(ReadMessageError::WrongType {
+                expected_one_of: vec![MESSAGE_TYPE_EPHEMERAL],
+                found: first_byte,
+            }));
+        }
+        let (i, sender) = parse::actor_id(i)?;
+        let (i, count) = parse::leb128_u64(i)?;
+        let (i, data) = parse::length_prefixed_bytes(i)?;
+        Ok((
+            i,
+            EphemeralMessage {
+                sender,
+                count,
+                data: data.to_vec(),
+            },
+        ))
+    }
+}
+
+/// Either a sync [`Message`] or an [`EphemeralMessage`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChannelMessage {
+    Sync(Message),
+    Ephemeral(EphemeralMessage),
+}
+
+impl ChannelMessage {
+    /// Decode a message based on its first byte
+    pub fn decode(input: &[u8]) -> Result<Self, ReadMessageError> {
+        match input.first() {
+            Some(&MESSAGE_TYPE_EPHEMERAL) => EphemeralMessage::decode(input).map(Self::Ephemeral),
+            Some(&MESSAGE_TYPE_SYNC) | Some(&MESSAGE_TYPE_SYNC_V2) => {
+                Message::decode(input).map(Self::Sync)
+            }
+            Some(other) => Err(ReadMessageError::WrongType {
+                expected_one_of: vec![
+                    MESSAGE_TYPE_SYNC,
+                    MESSAGE_TYPE_SYNC_V2,
+                    MESSAGE_TYPE_EPHEMERAL,
+                ],
+                found: *other,
+            }),
+            None => Err(ReadMessageError::NotEnoughInput),
+        }
+    }
+
+    pub fn encode(self) -> Vec<u8> {
+        match self {
+            Self::Sync(msg) => msg.encode(),
+            Self::Ephemeral(msg) => msg.encode(),
+        }
+    }
+}
+
+impl From<Message> for ChannelMessage {
+    fn from(msg: Message) -> Self {
+        Self::Sync(msg)
+    }
+}
+
+impl From<EphemeralMessage> for ChannelMessage {
+    fn from(msg: EphemeralMessage) -> Self {
+        Self::Ephemeral(msg)
+    }
+}
+
+/// The most recent ephemeral data received from a peer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerPresence {
+    /// The `count` of the message the data came from
+    pub count: u64,
+    /// The payload of the most recent message
+    pub data: Vec<u8>,
+    /// The time (in milliseconds, using whatever clock was passed to [`Presence::receive()`]) at
+    /// which the most recent message was received
+    pub last_seen: i64,
+}
+
+/// Tracks the most recent [`EphemeralMessage`] from each peer
+///
+/// Peers which have not sent a message for longer than the timeout are removed by
+/// [`Self::expire()`]. Times are passed in explicitly as milliseconds so that this works
+/// regardless of the clock available on the platform.
+///
+/// ## Example
+///
+/// ```
+/// use automerge::{sync::{EphemeralMessage, Presence}, ActorId};
+/// let mut presence = Presence::new(30_000);
+/// let alice = ActorId::random();
+/// presence.receive(EphemeralMessage::new(alice.clone(), 1, b"cursor: 5".to_vec()), 1_000);
+/// assert_eq!(presence.get(&alice).unwrap().data, b"cursor: 5");
+///
+/// // 40 seconds later nothing has been heard from alice
+/// let expired = presence.expire(41_000);
+/// assert_eq!(expired, vec![alice.clone()]);
+/// assert!(presence.get(&alice).is_none());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Presence {
+    timeout_millis: i64,
+    peers: HashMap<ActorId, PeerPresence>,
+}
+
+impl Presence {
+    /// Create a new tracker which forgets peers after `timeout_millis` milliseconds of silence
+    pub fn new(timeout_millis: i64) -> Self {
+        Presence {
+            timeout_millis,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Record a message received at `now`
+    ///
+    /// Returns `false` if the message was ignored because we have already seen a message from
+    /// the same sender with an equal or higher `count`.
+    pub fn receive(&mut self, msg: EphemeralMessage, now: i64) -> bool {
+        let EphemeralMessage {
+            sender,
+            count,
+            data,
+        } = msg;
+        match self.peers.get_mut(&sender) {
+            Some(existing) if existing.count >= count => false,
+            Some(existing) => {
+                existing.count = count;
+                existing.data = data;
+                existing.last_seen = now;
+                true
+            }
+            None => {
+                self.peers.insert(
+                    sender,
+                    PeerPresence {
+                        count,
+                        data,
+                        last_seen: now,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Remove any peers we have not heard from within the timeout, returning their actor IDs
+    pub fn expire(&mut self, now: i64) -> Vec<ActorId> {
+        let timeout = self.timeout_millis;
+        let mut expired = Vec::new();
+        self.peers.retain(|actor, presence| {
+            if now.saturating_sub(presence.last_seen) > timeout {
+                expired.push(actor.clone());
+                false
+            } else {
+                true
+            }
+        });
+        expired.sort();
+        expired
+    }
+
+    /// Forget a peer, for example because it has disconnected
+    pub fn remove(&mut self, actor: &ActorId) -> Option<PeerPresence> {
+        self.peers.remove(actor)
+    }
+
+    pub fn get(&self, actor: &ActorId) -> Option<&PeerPresence> {
+        self.peers.get(actor)
+    }
+
+    /// All the peers we currently know about
+    pub fn peers(&self) -> impl Iterator<Item = (&ActorId, &PeerPresence)> {
+        self.peers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{State, SyncDoc};
+
+    #[test]
+    fn encode_decode_ephemeral_message() {
+        let msg = EphemeralMessage::new(ActorId::random(), 12, b"hello".to_vec());
+        let encoded = msg.encode();
+        assert_eq!(EphemeralMessage::decode(&encoded).unwrap(), msg);
+        assert_eq!(
+            ChannelMessage::decode(&encoded).unwrap(),
+            ChannelMessage::Ephemeral(msg)
+        );
+    }
+
+    #[test]
+    fn channel_message_decodes_sync_messages() {
+        let mut doc = crate::AutoCommit::new();
+        let msg = doc.sync().generate_sync_message(&mut State::new()).unwrap();
+        let encoded = msg.clone().encode();
+        assert_eq!(
+            ChannelMessage::decode(&encoded).unwrap(),
+            ChannelMessage::Sync(msg)
+        );
+        assert!(matches!(
+            ChannelMessage::decode(&[0x01]),
+            Err(ReadMessageError::WrongType { found: 0x01, .. })
+        ));
+        assert!(matches!(
+            Message::decode(&EphemeralMessage::new(ActorId::random(), 0, vec![]).encode()),
+            Err(ReadMessageError::WrongType { .. })
+        ));
+    }
+
+    #[test]
+    fn presence_ignores_stale_messages() {
+        let actor = ActorId::random();
+        let mut presence = Presence::new(1000);
+        assert!(presence.receive(EphemeralMessage::new(actor.clone(), 2, vec![2]), 0));
+        assert!(!presence.receive(EphemeralMessage::new(actor.clone(), 1, vec![1]), 10));
+        assert_eq!(presence.get(&actor).unwrap().data, vec![2]);
+        assert!(presence.receive(EphemeralMessage::new(actor.clone(), 3, vec![3]), 20));
+        assert_eq!(
+            presence.get(&actor),
+            Some(&PeerPresence {
+                count: 3,
+                data: vec![3],
+                last_seen: 20,
+            })
+        );
+    }
+
+    #[test]
+    fn presence_expires_silent_peers() {
+        let alice = ActorId::from(b"alice");
+        let bob = ActorId::from(b"bob");
+        let mut presence = Presence::new(1000);
+        presence.receive(EphemeralMessage::new(alice.clone(), 1, vec![]), 0);
+        presence.receive(EphemeralMessage::new(bob.clone(), 1, vec![]), 500);
+        assert!(presence.expire(1000).is_empty());
+        assert_eq!(presence.expire(1200), vec![alice]);
+        assert_eq!(presence.len(), 1);
+        assert_eq!(presence.expire(1500), Vec::<ActorId>::new());
+        assert_eq!(presence.expire(1501), vec![bob]);
+        assert!(presence.is_empty());
+    }
+}
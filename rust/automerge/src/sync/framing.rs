@@ -0,0 +1,442 @@
+use std::io::{self, Read, Write};
+
+use crate::AutomergeError;
+
+use super::{Message, ReadMessageError, State, SyncDoc};
+
+/// The first byte of every frame
+pub const FRAME_MAGIC: u8 = 0x85;
+/// The version of the framing format
+pub const FRAME_VERSION: u8 = 0x01;
+
+// magic byte, version byte, big endian u32 length
+const HEADER_LEN: usize = 6;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid magic byte: expected {FRAME_MAGIC:#04x} but found {0:#04x}")]
+    BadMagic(u8),
+    #[error("unsupported frame version {0}")]
+    UnsupportedVersion(u8),
+    #[error("frame of {0} bytes is too large")]
+    TooLarge(usize),
+    #[error("stream ended in the middle of a frame")]
+    UnexpectedEof,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SyncStreamError {
+    #[error(transparent)]
+    Frame(#[from] FrameError),
+    #[error(transparent)]
+    ReadMessage(#[from] ReadMessageError),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+    #[error("stream closed before sync completed")]
+    Closed,
+}
+
+impl From<io::Error> for SyncStreamError {
+    fn from(e: io::Error) -> Self {
+        Self::Frame(FrameError::Io(e))
+    }
+}
+
+fn encode_header(len: usize) -> Result<[u8; HEADER_LEN], FrameError> {
+    let len = u32::try_from(len).map_err(|_| FrameError::TooLarge(len))?;
+    let mut header = [0; HEADER_LEN];
+    header[0] = FRAME_MAGIC;
+    header[1] = FRAME_VERSION;
+    header[2..].copy_from_slice(&len.to_be_bytes());
+    Ok(header)
+}
+
+fn decode_header(header: [u8; HEADER_LEN]) -> Result<usize, FrameError> {
+    if header[0] != FRAME_MAGIC {
+        return Err(FrameError::BadMagic(header[0]));
+    }
+    if header[1] != FRAME_VERSION {
+        return Err(FrameError::UnsupportedVersion(header[1]));
+    }
+    Ok(u32::from_be_bytes([header[2], header[3], header[4], header[5]]) as usize)
+}
+
+/// Write `payload` to `writer` as a single frame
+///
+/// A frame is the byte [`FRAME_MAGIC`], followed by the byte [`FRAME_VERSION`], followed by the
+/// length of `payload` as a big endian `u32`, followed by `payload`.
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> Result<(), FrameError> {
+    writer.write_all(&encode_header(payload.len())?)?;
+    writer.write_all(payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a single frame written by [`write_frame()`] from `reader`
+///
+/// Returns `None` if the stream ended cleanly before the start of a frame.
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, FrameError> {
+    let mut header = [0; HEADER_LEN];
+    let mut read = 0;
+    while read < HEADER_LEN {
+        match reader.read(&mut header[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(FrameError::UnexpectedEof),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let len = decode_header(header)?;
+    // Don't trust the length to preallocate, a corrupt frame could claim to be 4GB long
+    let mut payload = Vec::new();
+    reader.take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() != len {
+        return Err(FrameError::UnexpectedEof);
+    }
+    Ok(Some(payload))
+}
+
+/// Which side of the connection takes the first turn, decided by exchanging random nonces
+fn we_go_first(ours: &[u8], theirs: &[u8]) -> Option<bool> {
+    match ours.cmp(theirs) {
+        std::cmp::Ordering::Greater => Some(true),
+        std::cmp::Ordering::Less => Some(false),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// Tracks the turns taken in [`sync_over_stream()`] to decide when both sides are done
+#[derive(Default)]
+struct Turns {
+    last_was_empty: bool,
+    done: bool,
+}
+
+impl Turns {
+    fn record(&mut self, empty: bool) {
+        self.done = self.last_was_empty && empty;
+        self.last_was_empty = empty;
+    }
+}
+
+/// Synchronize `doc` with the peer at the other end of `stream` until neither side has anything
+/// more to send
+///
+/// Both peers must call this function (or [`sync_over_stream_with_state()`]). The peers first
+/// exchange random nonces to decide who goes first, then take turns to send a single frame. Each
+/// frame contains either an encoded sync [`Message`] or nothing, if the peer had nothing to send.
+/// Once two consecutive frames are empty both sides are up to date and the function returns.
+/// Because the peers take turns only one side is ever writing, so this cannot deadlock on a
+/// stream with limited buffering.
+///
+/// ## Example
+///
+/// ```
+/// # use automerge::{sync::{sync_over_stream, SyncDoc}, transaction::Transactable, AutoCommit};
+/// # fn sync_with_server(stream: std::net::TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+/// let mut doc = AutoCommit::new();
+/// doc.put(automerge::ROOT, "key", "value")?;
+/// sync_over_stream(&mut doc.sync(), stream)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn sync_over_stream<D, S>(doc: &mut D, stream: S) -> Result<(), SyncStreamError>
+where
+    D: SyncDoc,
+    S: Read + Write,
+{
+    sync_over_stream_with_state(doc, &mut State::new(), stream)
+}
+
+/// Like [`sync_over_stream()`] but using an existing [`State`] for the peer
+pub fn sync_over_stream_with_state<D, S>(
+    doc: &mut D,
+    state: &mut State,
+    mut stream: S,
+) -> Result<(), SyncStreamError>
+where
+    D: SyncDoc,
+    S: Read + Write,
+{
+    let mut our_turn = loop {
+        let nonce = uuid::Uuid::new_v4();
+        write_frame(&mut stream, nonce.as_bytes())?;
+        let theirs = read_frame(&mut stream)?.ok_or(SyncStreamError::Closed)?;
+        if let Some(first) = we_go_first(nonce.as_bytes(), &theirs) {
+            break first;
+        }
+    };
+    let mut turns = Turns::default();
+    while !turns.done {
+        if our_turn {
+            let payload = doc
+                .generate_sync_message(state)
+                .map(Message::encode)
+                .unwrap_or_default();
+            write_frame(&mut stream, &payload)?;
+            turns.record(payload.is_empty());
+        } else {
+            let payload = read_frame(&mut stream)?.ok_or(SyncStreamError::Closed)?;
+            turns.record(payload.is_empty());
+            if !payload.is_empty() {
+                doc.receive_sync_message(state, Message::decode(&payload)?)?;
+            }
+        }
+        our_turn = !our_turn;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+mod tokio_impls {
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    use super::*;
+
+    /// An async version of [`write_frame()`]
+    pub async fn write_frame_async<W: AsyncWrite + Unpin>(
+        writer: &mut W,
+        payload: &[u8],
+    ) -> Result<(), FrameError> {
+        writer.write_all(&encode_header(payload.len())?).await?;
+        writer.write_all(payload).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// An async version of [`read_frame()`]
+    pub async fn read_frame_async<R: AsyncRead + Unpin>(
+        reader: &mut R,
+    ) -> Result<Option<Vec<u8>>, FrameError> {
+        let mut header = [0; HEADER_LEN];
+        let mut read = 0;
+        while read < HEADER_LEN {
+            match reader.read(&mut header[read..]).await? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(FrameError::UnexpectedEof),
+                n => read += n,
+            }
+        }
+        let len = decode_header(header)?;
+        let mut payload = Vec::new();
+        reader.take(len as u64).read_to_end(&mut payload).await?;
+        if payload.len() != len {
+            return Err(FrameError::UnexpectedEof);
+        }
+        Ok(Some(payload))
+    }
+
+    /// An async version of [`sync_over_stream()`]
+    pub async fn sync_over_stream_async<D, S>(doc: &mut D, stream: S) -> Result<(), SyncStreamError>
+    where
+        D: SyncDoc,
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        sync_over_stream_with_state_async(doc, &mut State::new(), stream).await
+    }
+
+    /// An async version of [`sync_over_stream_with_state()`]
+    pub async fn sync_over_stream_with_state_async<D, S>(
+        doc: &mut D,
+        state: &mut State,
+        mut stream: S,
+    ) -> Result<(), SyncStreamError>
+    where
+        D: SyncDoc,
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut our_turn = loop {
+            let nonce = uuid::Uuid::new_v4();
+            write_frame_async(&mut stream, nonce.as_bytes()).await?;
+            let theirs = read_frame_async(&mut stream)
+                .await?
+                .ok_or(SyncStreamError::Closed)?;
+            if let Some(first) = we_go_first(nonce.as_bytes(), &theirs) {
+                break first;
+            }
+        };
+        let mut turns = Turns::default();
+        while !turns.done {
+            if our_turn {
+                let payload = doc
+                    .generate_sync_message(state)
+                    .map(Message::encode)
+                    .unwrap_or_default();
+                write_frame_async(&mut stream, &payload).await?;
+                turns.record(payload.is_empty());
+            } else {
+                let payload = read_frame_async(&mut stream)
+                    .await?
+                    .ok_or(SyncStreamError::Closed)?;
+                turns.record(payload.is_empty());
+                if !payload.is_empty() {
+                    doc.receive_sync_message(state, Message::decode(&payload)?)?;
+                }
+            }
+            our_turn = !our_turn;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use tokio_impls::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{transaction::Transactable, AutoCommit, ReadDoc, ROOT};
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    /// One end of an in memory, bidirectional byte stream
+    struct Pipe {
+        tx: Sender<Vec<u8>>,
+        rx: Receiver<Vec<u8>>,
+        buf: Vec<u8>,
+    }
+
+    fn pipe() -> (Pipe, Pipe) {
+        let (tx1, rx1) = channel();
+        let (tx2, rx2) = channel();
+        (
+            Pipe {
+                tx: tx1,
+                rx: rx2,
+                buf: Vec::new(),
+            },
+            Pipe {
+                tx: tx2,
+                rx: rx1,
+                buf: Vec::new(),
+            },
+        )
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            if self.buf.is_empty() {
+                match self.rx.recv() {
+                    Ok(bytes) => self.buf = bytes,
+                    Err(_) => return Ok(0),
+                }
+            }
+            let n = out.len().min(self.buf.len());
+            out[..n].copy_from_slice(&self.buf[..n]);
+            self.buf.drain(..n);
+            Ok(n)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.tx
+                .send(data.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn frames_roundtrip() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, b"hello").unwrap();
+        write_frame(&mut buf, b"").unwrap();
+        assert_eq!(&buf[..6], &[FRAME_MAGIC, FRAME_VERSION, 0, 0, 0, 5]);
+        let mut reader = buf.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(Vec::new()));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn bad_frames() {
+        let mut reader: &[u8] = &[0x00, FRAME_VERSION, 0, 0, 0, 0];
+        assert!(matches!(
+            read_frame(&mut reader),
+            Err(FrameError::BadMagic(0))
+        ));
+        let mut reader: &[u8] = &[FRAME_MAGIC, 0x02, 0, 0, 0, 0];
+        assert!(matches!(
+            read_frame(&mut reader),
+            Err(FrameError::UnsupportedVersion(2))
+        ));
+        let mut reader: &[u8] = &[FRAME_MAGIC, FRAME_VERSION, 0, 0, 0, 5, 1, 2];
+        assert!(matches!(
+            read_frame(&mut reader),
+            Err(FrameError::UnexpectedEof)
+        ));
+        let mut reader: &[u8] = &[FRAME_MAGIC, FRAME_VERSION, 0];
+        assert!(matches!(
+            read_frame(&mut reader),
+            Err(FrameError::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn sync_over_in_memory_pipe() {
+        let mut doc1 = AutoCommit::new();
+        doc1.put(ROOT, "one", 1).unwrap();
+        let mut doc2 = AutoCommit::new();
+        doc2.put(ROOT, "two", 2).unwrap();
+
+        let (a, b) = pipe();
+        let handle = std::thread::spawn(move || {
+            sync_over_stream(&mut doc2.sync(), b).unwrap();
+            doc2
+        });
+        sync_over_stream(&mut doc1.sync(), a).unwrap();
+        let mut doc2 = handle.join().unwrap();
+
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+        assert!(doc2.get(ROOT, "one").unwrap().is_some());
+    }
+
+    #[test]
+    fn sync_when_already_up_to_date() {
+        let mut doc1 = AutoCommit::new();
+        doc1.put(ROOT, "one", 1).unwrap();
+        let mut doc2 = doc1.fork();
+
+        let (a, b) = pipe();
+        let handle = std::thread::spawn(move || {
+            sync_over_stream(&mut doc2.sync(), b).unwrap();
+            doc2
+        });
+        sync_over_stream(&mut doc1.sync(), a).unwrap();
+        let mut doc2 = handle.join().unwrap();
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
+    #[test]
+    fn closed_stream_is_an_error() {
+        let mut doc = AutoCommit::new();
+        let (a, b) = pipe();
+        drop(b);
+        assert!(sync_over_stream(&mut doc.sync(), a).is_err());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn sync_over_tokio_duplex() {
+        let mut doc1 = AutoCommit::new();
+        doc1.put(ROOT, "one", 1).unwrap();
+        let mut doc2 = AutoCommit::new();
+        doc2.put(ROOT, "two", 2).unwrap();
+
+        let (a, b) = tokio::io::duplex(64);
+        let handle = tokio::spawn(async move {
+            sync_over_stream_async(&mut doc2.sync(), b).await.unwrap();
+            doc2
+        });
+        sync_over_stream_async(&mut doc1.sync(), a).await.unwrap();
+        let mut doc2 = handle.await.unwrap();
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+}
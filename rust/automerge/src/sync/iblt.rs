@@ -0,0 +1,290 @@
+use std::borrow::Borrow;
+use std::collections::HashSet;
+
+use crate::storage::parse;
+use crate::ChangeHash;
+
+// Each hash is added to one cell in each of `NUM_HASHES` equally sized partitions of the table.
+// Using partitions rather than indexing into the whole table ensures that a hash never maps to
+// the same cell twice.
+const NUM_HASHES: usize = 3;
+
+// The encoded size of a cell is at least this many bytes. Used to reject obviously bogus cell
+// counts before allocating.
+const MIN_CELL_SIZE: usize = 1 + 32 + 8;
+
+/// An invertible bloom lookup table summarising a set of change hashes
+///
+/// Unlike a [`super::BloomFilter`] an IBLT can be subtracted from another IBLT of the same size and
+/// the result "peeled" to recover the exact set difference, provided the difference is not too
+/// large relative to the number of cells. Decoding needs roughly 1.5 cells per differing hash.
+/// When decoding fails the sync protocol falls back to the bloom filter it is sent alongside.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct Iblt {
+    cells: Vec<Cell>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize)]
+struct Cell {
+    count: i64,
+    key_sum: [u8; 32],
+    check_sum: u64,
+}
+
+/// The result of decoding the difference of two [`Iblt`]s
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetDifference {
+    /// Hashes which were in the table `difference` was called on but not the other one
+    pub only_in_self: Vec<ChangeHash>,
+    /// Hashes which were in the other table but not the one `difference` was called on
+    pub only_in_other: Vec<ChangeHash>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ParseError {
+    #[error(transparent)]
+    Leb128(#[from] parse::leb128::Error),
+    #[error("too many cells: {0}")]
+    TooManyCells(u64),
+}
+
+impl Cell {
+    fn toggle(&mut self, hash: &ChangeHash, delta: i64) {
+        // Counts come from the other end so they can be anything, the arithmetic is modular
+        self.count = self.count.wrapping_add(delta);
+        for (sum, byte) in self.key_sum.iter_mut().zip(hash.0.iter()) {
+            *sum ^= byte;
+        }
+        self.check_sum ^= check_hash(&hash.0);
+    }
+
+    fn subtract(&self, other: &Cell) -> Cell {
+        let mut key_sum = self.key_sum;
+        for (sum, byte) in key_sum.iter_mut().zip(other.key_sum.iter()) {
+            *sum ^= byte;
+        }
+        Cell {
+            count: self.count.wrapping_sub(other.count),
+            key_sum,
+            check_sum: self.check_sum ^ other.check_sum,
+        }
+    }
+
+    fn is_pure(&self) -> bool {
+        (self.count == 1 || self.count == -1) && self.check_sum == check_hash(&self.key_sum)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count == 0 && self.check_sum == 0 && self.key_sum == [0; 32]
+    }
+}
+
+impl Iblt {
+    /// Create an empty table with (at least) `num_cells` cells
+    ///
+    /// The number of cells is rounded up to a multiple of the number of hash functions.
+    pub fn new(num_cells: usize) -> Self {
+        let partition = ((num_cells + NUM_HASHES - 1) / NUM_HASHES).max(1);
+        Iblt {
+            cells: vec![Cell::default(); partition * NUM_HASHES],
+        }
+    }
+
+    pub fn from_hashes<H: Borrow<ChangeHash>>(
+        num_cells: usize,
+        hashes: impl Iterator<Item = H>,
+    ) -> Self {
+        let mut table = Self::new(num_cells);
+        for hash in hashes {
+            table.insert(hash.borrow());
+        }
+        table
+    }
+
+    pub fn num_cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn insert(&mut self, hash: &ChangeHash) {
+        self.toggle(hash, 1)
+    }
+
+    fn toggle(&mut self, hash: &ChangeHash, delta: i64) {
+        for index in self.indices(hash) {
+            self.cells[index].toggle(hash, delta);
+        }
+    }
+
+    fn indices(&self, hash: &ChangeHash) -> [usize; NUM_HASHES] {
+        let partition = self.cells.len() / NUM_HASHES;
+        let mut result = [0; NUM_HASHES];
+        for (i, index) in result.iter_mut().enumerate() {
+            let bytes = [
+                hash.0[4 * i],
+                hash.0[4 * i + 1],
+                hash.0[4 * i + 2],
+                hash.0[4 * i + 3],
+            ];
+            *index = i * partition + (u32::from_le_bytes(bytes) as usize % partition);
+        }
+        result
+    }
+
+    /// Decode the difference between this table and `other`
+    ///
+    /// Returns `None` if the tables have different sizes or if the difference is too large to be
+    /// decoded.
+    pub fn difference(&self, other: &Iblt) -> Option<SetDifference> {
+        if self.cells.len() != other.cells.len() || self.cells.is_empty() {
+            return None;
+        }
+        let mut diff = Iblt {
+            cells: self
+                .cells
+                .iter()
+                .zip(other.cells.iter())
+                .map(|(a, b)| a.subtract(b))
+                .collect(),
+        };
+        let mut result = SetDifference::default();
+        let mut seen = HashSet::new();
+        let mut pure = (0..diff.cells.len())
+            .filter(|i| diff.cells[*i].is_pure())
+            .collect::<Vec<_>>();
+        while let Some(index) = pure.pop() {
+            let cell = diff.cells[index];
+            if !cell.is_pure() {
+                continue;
+            }
+            let hash = ChangeHash(cell.key_sum);
+            if !seen.insert(hash) {
+                // A hash can only be peeled once, seeing it again means the table is corrupt
+                return None;
+            }
+            if cell.count == 1 {
+                result.only_in_self.push(hash);
+            } else {
+                result.only_in_other.push(hash);
+            }
+            diff.toggle(&hash, -cell.count);
+            pure.extend(
+                diff.indices(&hash)
+                    .into_iter()
+                    .filter(|i| diff.cells[*i].is_pure()),
+            );
+        }
+        if diff.cells.iter().all(Cell::is_empty) {
+            result.only_in_self.sort();
+            result.only_in_other.sort();
+            Some(result)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        leb128::write::unsigned(&mut buf, self.cells.len() as u64).unwrap();
+        for cell in &self.cells {
+            leb128::write::signed(&mut buf, cell.count).unwrap();
+            buf.extend(cell.key_sum);
+            buf.extend(cell.check_sum.to_le_bytes());
+        }
+        buf
+    }
+
+    pub(crate) fn parse(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, ParseError> {
+        let (mut i, num_cells) = parse::leb128_u64(input)?;
+        if num_cells % NUM_HASHES as u64 != 0
+            || num_cells.saturating_mul(MIN_CELL_SIZE as u64) > i.unconsumed_bytes().len() as u64
+        {
+            return Err(parse::ParseError::Error(ParseError::TooManyCells(
+                num_cells,
+            )));
+        }
+        let mut cells = Vec::with_capacity(num_cells as usize);
+        for _ in 0..num_cells {
+            let (rest, count) = parse::leb128_i64(i)?;
+            let (rest, key_sum) = parse::take_n(32, rest)?;
+            let (rest, check_sum) = parse::take_n(8, rest)?;
+            cells.push(Cell {
+                count,
+                key_sum: key_sum.try_into().unwrap(),
+                check_sum: u64::from_le_bytes(check_sum.try_into().unwrap()),
+            });
+            i = rest;
+        }
+        Ok((i, Self { cells }))
+    }
+}
+
+// Change hashes are SHA-256 hashes so any bytes not used for indexing are as good a checksum as
+// any.
+fn check_hash(key: &[u8; 32]) -> u64 {
+    u64::from_le_bytes(key[16..24].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gen::gen_hash;
+    use proptest::prelude::*;
+
+    fn hashes(range: std::ops::Range<u32>) -> Vec<ChangeHash> {
+        use sha2::{Digest, Sha256};
+        range
+            .map(|i| ChangeHash(Sha256::digest(i.to_le_bytes()).into()))
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn encode_decode(hashes in proptest::collection::vec(gen_hash(), 0..20)) {
+            let table = Iblt::from_hashes(12, hashes.iter());
+            let bytes = table.to_bytes();
+            let (rest, decoded) = Iblt::parse(parse::Input::new(&bytes)).unwrap();
+            prop_assert!(rest.is_empty());
+            prop_assert_eq!(decoded, table);
+        }
+    }
+
+    #[test]
+    fn decodes_small_differences() {
+        let shared = hashes(0..1000);
+        let mut ours = hashes(1000..1005);
+        let mut theirs = hashes(2000..2003);
+        let a = Iblt::from_hashes(30, shared.iter().chain(ours.iter()));
+        let b = Iblt::from_hashes(30, shared.iter().chain(theirs.iter()));
+        ours.sort();
+        theirs.sort();
+        let diff = a.difference(&b).expect("failed to decode");
+        assert_eq!(diff.only_in_self, ours);
+        assert_eq!(diff.only_in_other, theirs);
+        assert_eq!(a.difference(&a), Some(SetDifference::default()));
+    }
+
+    #[test]
+    fn difference_too_large_is_none() {
+        let a = Iblt::from_hashes(6, hashes(0..100).iter());
+        let b = Iblt::new(6);
+        assert_eq!(a.difference(&b), None);
+    }
+
+    #[test]
+    fn mismatched_sizes_is_none() {
+        assert_eq!(Iblt::new(3).difference(&Iblt::new(6)), None);
+    }
+
+    #[test]
+    fn extreme_counts_from_the_other_end_do_not_overflow() {
+        let mut theirs = Iblt::new(3);
+        for cell in &mut theirs.cells {
+            cell.count = i64::MIN;
+        }
+        let bytes = theirs.to_bytes();
+        let (_, theirs) = Iblt::parse(parse::Input::new(&bytes)).unwrap();
+        let ours = Iblt::from_hashes(3, hashes(0..1).iter());
+        assert_eq!(ours.difference(&theirs), None);
+        assert_eq!(theirs.difference(&ours), None);
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+
+use crate::{patches::PatchLog, Automerge, AutomergeError, ChangeHash};
+
+use super::{DecodeStateError, Message, State, SyncDoc};
+
+/// Manages the sync protocol for one document and many peers
+///
+/// A `SyncManager` owns a document and a [`State`] for each peer it is synchronizing with. Peers
+/// are identified by any `P` the application likes, e.g. a connection ID. Messages received from
+/// peers are passed to [`Self::receive()`] and messages to send are obtained from
+/// [`Self::outgoing()`]. Changes received from one peer are forwarded to the other peers the next
+/// time [`Self::outgoing()`] is called.
+///
+/// [`Self::outgoing()`] only generates messages for peers which might need one, i.e. peers which
+/// have sent us a message or whose messages were generated before the heads of the document
+/// changed. This makes it cheap to call after every local change and every received message.
+///
+/// ## Example
+///
+/// ```
+/// use automerge::{sync::SyncManager, transaction::Transactable, Automerge, ReadDoc, ROOT};
+/// # fn main() -> Result<(), automerge::AutomergeError> {
+/// let mut hub = SyncManager::new(Automerge::new());
+/// let mut alice = SyncManager::new(Automerge::new());
+/// let mut bob = SyncManager::new(Automerge::new());
+/// hub.add_peer("alice");
+/// hub.add_peer("bob");
+/// alice.add_peer("hub");
+/// bob.add_peer("hub");
+///
+/// let mut tx = alice.doc_mut().transaction();
+/// tx.put(ROOT, "greeting", "hello")?;
+/// tx.commit();
+///
+/// loop {
+///     let mut quiet = true;
+///     for (_, msg) in alice.outgoing() {
+///         quiet = false;
+///         hub.receive("alice", msg)?;
+///     }
+///     for (_, msg) in bob.outgoing() {
+///         quiet = false;
+///         hub.receive("bob", msg)?;
+///     }
+///     for (peer, msg) in hub.outgoing() {
+///         quiet = false;
+///         match peer {
+///             "alice" => alice.receive("hub", msg)?,
+///             _ => bob.receive("hub", msg)?,
+///         }
+///     }
+///     if quiet {
+///         break;
+///     }
+/// }
+/// assert_eq!(bob.doc().get(ROOT, "greeting")?.unwrap().0.to_str(), Some("hello"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SyncManager<P> {
+    doc: Automerge,
+    peers: BTreeMap<P, PeerState>,
+}
+
+#[derive(Debug, Clone)]
+struct PeerState {
+    state: State,
+    /// Whether we have received a message from this peer since we last generated one for it
+    received: bool,
+    /// The heads of the document when we last generated a message for this peer. `None` if we
+    /// have never generated a message for this peer.
+    generated_at: Option<Vec<ChangeHash>>,
+}
+
+impl PeerState {
+    fn new(state: State) -> Self {
+        PeerState {
+            state,
+            received: false,
+            generated_at: None,
+        }
+    }
+}
+
+impl<P: Ord + Clone> SyncManager<P> {
+    pub fn new(doc: Automerge) -> Self {
+        SyncManager {
+            doc,
+            peers: BTreeMap::new(),
+        }
+    }
+
+    pub fn doc(&self) -> &Automerge {
+        &self.doc
+    }
+
+    /// Mutable access to the document
+    ///
+    /// Any changes made to the document will be sent to all peers the next time
+    /// [`Self::outgoing()`] is called.
+    pub fn doc_mut(&mut self) -> &mut Automerge {
+        &mut self.doc
+    }
+
+    pub fn into_doc(self) -> Automerge {
+        self.doc
+    }
+
+    /// Start synchronizing with `peer` using a fresh [`State`]
+    ///
+    /// If we were already synchronizing with `peer` the existing state is replaced.
+    pub fn add_peer(&mut self, peer: P) {
+        self.add_peer_with_state(peer, State::new());
+    }
+
+    /// Start synchronizing with `peer` using `state`
+    pub fn add_peer_with_state(&mut self, peer: P, state: State) {
+        self.peers.insert(peer, PeerState::new(state));
+    }
+
+    /// Start synchronizing with `peer` using a state previously obtained from
+    /// [`Self::persist_peer()`]
+    pub fn restore_peer(&mut self, peer: P, encoded: &[u8]) -> Result<(), DecodeStateError> {
+        let state = State::decode(encoded)?;
+        self.add_peer_with_state(peer, state);
+        Ok(())
+    }
+
+    /// Stop synchronizing with `peer`, returning its state if there was one
+    pub fn remove_peer(&mut self, peer: &P) -> Option<State> {
+        self.peers.remove(peer).map(|p| p.state)
+    }
+
+    /// The encoded state of `peer`, suitable for passing to [`Self::restore_peer()`] in a later
+    /// session
+    pub fn persist_peer(&self, peer: &P) -> Option<Vec<u8>> {
+        self.peers.get(peer).map(|p| p.state.encode())
+    }
+
+    /// The encoded states of all peers
+    pub fn persist_all(&self) -> Vec<(P, Vec<u8>)> {
+        self.peers
+            .iter()
+            .map(|(peer, p)| (peer.clone(), p.state.encode()))
+            .collect()
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = &P> {
+        self.peers.keys()
+    }
+
+    pub fn state(&self, peer: &P) -> Option<&State> {
+        self.peers.get(peer).map(|p| &p.state)
+    }
+
+    /// Receive a message from `peer`
+    ///
+    /// If `peer` is not known it is added with a fresh [`State`].
+    pub fn receive(&mut self, peer: P, message: Message) -> Result<(), AutomergeError> {
+        let mut patch_log = PatchLog::null();
+        self.receive_log_patches(peer, message, &mut patch_log)
+    }
+
+    /// Receive a message from `peer`, logging any changes made to the document to `patch_log`
+    pub fn receive_log_patches(
+        &mut self,
+        peer: P,
+        message: Message,
+        patch_log: &mut PatchLog,
+    ) -> Result<(), AutomergeError> {
+        let peer_state = self
+            .peers
+            .entry(peer)
+            .or_insert_with(|| PeerState::new(State::new()));
+        peer_state.received = true;
+        self.doc
+            .receive_sync_message_log_patches(&mut peer_state.state, message, patch_log)
+    }
+
+    /// Generate the messages which should be sent to each peer
+    pub fn outgoing(&mut self) -> Vec<(P, Message)> {
+        let heads = self.doc.get_heads();
+        let mut result = Vec::new();
+        for (peer, peer_state) in self.peers.iter_mut() {
+            let needs_message = peer_state.received
+                || peer_state
+                    .generated_at
+                    .as_ref()
+                    .map(|generated_at| generated_at != &heads)
+                    .unwrap_or(true);
+            if !needs_message {
+                continue;
+            }
+            peer_state.received = false;
+            peer_state.generated_at = Some(heads.clone());
+            if let Some(msg) = self.doc.generate_sync_message(&mut peer_state.state) {
+                result.push((peer.clone(), msg));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{transaction::Transactable, ReadDoc, ROOT};
+
+    fn put(manager: &mut SyncManager<&'static str>, key: &str, value: i64) {
+        let mut tx = manager.doc_mut().transaction();
+        tx.put(ROOT, key, value).unwrap();
+        tx.commit();
+    }
+
+    /// Deliver messages between a hub and some spokes until everyone is quiet, returning the
+    /// number of messages sent
+    fn run(
+        hub: &mut SyncManager<&'static str>,
+        spokes: &mut [(&'static str, SyncManager<&'static str>)],
+    ) -> usize {
+        let mut sent = 0;
+        for _ in 0..20 {
+            let mut quiet = true;
+            for (name, spoke) in spokes.iter_mut() {
+                for (_, msg) in spoke.outgoing() {
+                    quiet = false;
+                    sent += 1;
+                    hub.receive(name, msg).unwrap();
+                }
+            }
+            for (peer, msg) in hub.outgoing() {
+                quiet = false;
+                sent += 1;
+                let (_, spoke) = spokes.iter_mut().find(|(name, _)| *name == peer).unwrap();
+                spoke.receive("hub", msg).unwrap();
+            }
+            if quiet {
+                return sent;
+            }
+        }
+        panic!("failed to sync");
+    }
+
+    fn spokes(names: &[&'static str]) -> Vec<(&'static str, SyncManager<&'static str>)> {
+        names
+            .iter()
+            .map(|name| {
+                let mut manager = SyncManager::new(Automerge::new());
+                manager.add_peer("hub");
+                (*name, manager)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn forwards_changes_between_peers() {
+        let mut hub = SyncManager::new(Automerge::new());
+        hub.add_peer("alice");
+        hub.add_peer("bob");
+        let mut spokes = spokes(&["alice", "bob"]);
+
+        put(&mut spokes[0].1, "alice", 1);
+        put(&mut spokes[1].1, "bob", 2);
+        run(&mut hub, &mut spokes);
+
+        for (_, spoke) in &spokes {
+            assert_eq!(spoke.doc().get_heads(), hub.doc().get_heads());
+            assert!(spoke.doc().get(ROOT, "alice").unwrap().is_some());
+            assert!(spoke.doc().get(ROOT, "bob").unwrap().is_some());
+        }
+
+        put(&mut hub, "hub", 3);
+        run(&mut hub, &mut spokes);
+        for (_, spoke) in &spokes {
+            assert_eq!(spoke.doc().get_heads(), hub.doc().get_heads());
+        }
+    }
+
+    #[test]
+    fn outgoing_is_empty_when_nothing_changed() {
+        let mut hub = SyncManager::new(Automerge::new());
+        hub.add_peer("alice");
+        let mut spokes = spokes(&["alice"]);
+        put(&mut spokes[0].1, "alice", 1);
+        run(&mut hub, &mut spokes);
+
+        assert!(hub.outgoing().is_empty());
+        assert!(spokes[0].1.outgoing().is_empty());
+    }
+
+    #[test]
+    fn unknown_peers_are_added_on_receive() {
+        let mut hub = SyncManager::new(Automerge::new());
+        let mut spokes = spokes(&["alice"]);
+        put(&mut spokes[0].1, "alice", 1);
+        run(&mut hub, &mut spokes);
+        assert_eq!(hub.peers().collect::<Vec<_>>(), vec![&"alice"]);
+        assert_eq!(hub.doc().get_heads(), spokes[0].1.doc().get_heads());
+    }
+
+    #[test]
+    fn persist_and_restore_peers() {
+        let mut hub = SyncManager::new(Automerge::new());
+        hub.add_peer("alice");
+        let mut spokes = spokes(&["alice"]);
+        put(&mut spokes[0].1, "alice", 1);
+        run(&mut hub, &mut spokes);
+
+        let persisted = hub.persist_all();
+        assert_eq!(persisted.len(), 1);
+        let spoke_state = spokes[0].1.persist_peer(&"hub").unwrap();
+
+        let mut hub = SyncManager::new(hub.into_doc());
+        for (peer, state) in persisted {
+            hub.restore_peer(peer, &state).unwrap();
+        }
+        let (name, spoke) = spokes.pop().unwrap();
+        let mut spoke = SyncManager::new(spoke.into_doc());
+        spoke.restore_peer("hub", &spoke_state).unwrap();
+        assert_eq!(
+            spoke.state(&"hub").unwrap().shared_heads,
+            hub.doc().get_heads()
+        );
+
+        put(&mut spoke, "alice", 2);
+        let mut spokes = vec![(name, spoke)];
+        run(&mut hub, &mut spokes);
+        assert_eq!(hub.doc().get_heads(), spokes[0].1.doc().get_heads());
+
+        assert!(hub.remove_peer(&"alice").is_some());
+        assert!(hub.persist_peer(&"alice").is_none());
+    }
+}
@@ -0,0 +1,463 @@
+//! # automerge-repo network protocol
+//!
+//! The JavaScript `automerge-repo` library synchronizes many documents between peers using a
+//! small CBOR encoded protocol on top of the sync protocol in the [parent module](crate::sync).
+//! This module implements that protocol so that Rust peers can talk to JavaScript peers.
+//!
+//! [`Repo`] is a transport agnostic state machine. The application is responsible for moving
+//! encoded [`RepoMessage`]s between peers:
+//!
+//! * When connecting to a peer send it [`Repo::join_message()`]
+//! * Pass every message received from a peer to [`Repo::receive()`] (or
+//!   [`Repo::receive_bytes()`])
+//! * After receiving messages or changing documents send the messages returned by
+//!   [`Repo::outgoing()`] to the peer identified by [`RepoMessage::target_id()`]
+//! * Call [`Repo::take_events()`] to find out about peers connecting and disconnecting,
+//!   documents changing or being unavailable, and ephemeral messages
+//!
+//! Every connected peer is sent every document this repo has, and any document a peer sends us
+//! is accepted.
+//!
+//! ## Example
+//!
+//! ```
+//! use automerge::{sync::{repo::Repo, DocumentId}, transaction::Transactable, Automerge, ROOT};
+//! # fn main() -> Result<(), automerge::sync::repo::RepoError> {
+//! let mut server = Repo::new("server");
+//! let mut client = Repo::new("client");
+//!
+//! let mut doc = Automerge::new();
+//! let mut tx = doc.transaction();
+//! tx.put(ROOT, "hello", "world").unwrap();
+//! tx.commit();
+//! let document_id = DocumentId::random();
+//! server.create_document(document_id, doc);
+//!
+//! client.request_document(document_id);
+//! server.receive_bytes(&client.join_message().encode())?;
+//! loop {
+//!     let to_client = server.outgoing();
+//!     let to_server = client.outgoing();
+//!     if to_client.is_empty() && to_server.is_empty() {
+//!         break;
+//!     }
+//!     for msg in to_client {
+//!         client.receive_bytes(&msg.encode())?;
+//!     }
+//!     for msg in to_server {
+//!         server.receive_bytes(&msg.encode())?;
+//!     }
+//! }
+//! assert_eq!(
+//!     client.document(&document_id).unwrap().get_heads(),
+//!     server.document(&document_id).unwrap().get_heads(),
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Automerge, AutomergeError};
+
+use super::{DocumentId, Message, ReadMessageError, SyncManager};
+
+mod cbor;
+mod message;
+
+pub use cbor::CborError;
+pub use message::{DecodeRepoMessageError, PeerId, PeerMetadata, RepoMessage, PROTOCOL_V1};
+
+/// Something which happened as a result of receiving messages
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepoEvent {
+    /// The handshake with a peer completed
+    PeerConnected {
+        peer_id: PeerId,
+        peer_metadata: Option<PeerMetadata>,
+    },
+    /// A peer left or was disconnected
+    PeerDisconnected { peer_id: PeerId },
+    /// A document was created or changed by a message from a peer
+    DocumentChanged { document_id: DocumentId },
+    /// Every connected peer told us they don't have a document we requested
+    DocumentUnavailable { document_id: DocumentId },
+    /// A peer sent us an ephemeral message
+    Ephemeral {
+        sender_id: PeerId,
+        document_id: DocumentId,
+        data: Vec<u8>,
+    },
+    /// A peer sent us an error message
+    Error { message: String },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RepoError {
+    #[error(transparent)]
+    Decode(#[from] DecodeRepoMessageError),
+    #[error(transparent)]
+    ReadMessage(#[from] ReadMessageError),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+    #[error("received a message from {0} before completing the handshake")]
+    UnknownPeer(PeerId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DocStatus {
+    /// We have (some version of) the document
+    Ready,
+    /// We asked for the document and are waiting for peers to send it
+    Requesting { unavailable_from: BTreeSet<PeerId> },
+    /// No connected peer had the document
+    Unavailable,
+}
+
+#[derive(Debug, Clone)]
+struct DocEntry {
+    sync: SyncManager<PeerId>,
+    status: DocStatus,
+}
+
+/// A transport agnostic implementation of the automerge-repo network protocol
+///
+/// See the [module level documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct Repo {
+    peer_id: PeerId,
+    metadata: PeerMetadata,
+    peers: BTreeMap<PeerId, Option<PeerMetadata>>,
+    documents: BTreeMap<DocumentId, DocEntry>,
+    pending: Vec<RepoMessage>,
+    events: Vec<RepoEvent>,
+    session_id: String,
+    ephemeral_count: u64,
+}
+
+impl Repo {
+    pub fn new<P: Into<PeerId>>(peer_id: P) -> Self {
+        Repo {
+            peer_id: peer_id.into(),
+            metadata: PeerMetadata {
+                storage_id: None,
+                is_ephemeral: true,
+            },
+            peers: BTreeMap::new(),
+            documents: BTreeMap::new(),
+            pending: Vec::new(),
+            events: Vec::new(),
+            session_id: uuid::Uuid::new_v4().simple().to_string(),
+            ephemeral_count: 0,
+        }
+    }
+
+    /// Set the metadata sent to peers during the handshake
+    pub fn with_metadata(mut self, metadata: PeerMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    /// The message to send to a peer when we connect to it
+    pub fn join_message(&self) -> RepoMessage {
+        RepoMessage::Join {
+            sender_id: self.peer_id.clone(),
+            supported_protocol_versions: vec![PROTOCOL_V1.to_string()],
+            peer_metadata: Some(self.metadata.clone()),
+        }
+    }
+
+    /// The peers we have completed the handshake with
+    pub fn connected_peers(&self) -> impl Iterator<Item = &PeerId> {
+        self.peers.keys()
+    }
+
+    /// Add a document to the repo, it will be sent to all connected peers
+    ///
+    /// If a document with the same ID already exists it is replaced.
+    pub fn create_document(&mut self, document_id: DocumentId, doc: Automerge) {
+        let entry = self.new_entry(doc, DocStatus::Ready);
+        self.documents.insert(document_id, entry);
+    }
+
+    /// Ask connected peers for a document we don't have
+    ///
+    /// If we already have the document this does nothing. Once the document arrives a
+    /// [`RepoEvent::DocumentChanged`] event is emitted, if every connected peer says they don't
+    /// have it a [`RepoEvent::DocumentUnavailable`] event is emitted.
+    pub fn request_document(&mut self, document_id: DocumentId) {
+        if let Some(entry) = self.documents.get_mut(&document_id) {
+            if entry.status == DocStatus::Unavailable {
+                entry.status = DocStatus::Requesting {
+                    unavailable_from: BTreeSet::new(),
+                };
+            }
+            return;
+        }
+        let entry = self.new_entry(
+            Automerge::new(),
+            DocStatus::Requesting {
+                unavailable_from: BTreeSet::new(),
+            },
+        );
+        self.documents.insert(document_id, entry);
+    }
+
+    pub fn document(&self, document_id: &DocumentId) -> Option<&Automerge> {
+        self.documents.get(document_id).map(|e| e.sync.doc())
+    }
+
+    /// Mutable access to a document, changes will be sent to peers by the next call to
+    /// [`Self::outgoing()`]
+    pub fn document_mut(&mut self, document_id: &DocumentId) -> Option<&mut Automerge> {
+        self.documents
+            .get_mut(document_id)
+            .map(|e| e.sync.doc_mut())
+    }
+
+    pub fn document_ids(&self) -> impl Iterator<Item = &DocumentId> {
+        self.documents.keys()
+    }
+
+    /// Send an ephemeral message about `document_id` to every connected peer
+    pub fn broadcast_ephemeral(&mut self, document_id: DocumentId, data: Vec<u8>) {
+        for peer in self.peers.keys() {
+            self.ephemeral_count += 1;
+            self.pending.push(RepoMessage::Ephemeral {
+                sender_id: self.peer_id.clone(),
+                target_id: peer.clone(),
+                count: self.ephemeral_count,
+                session_id: self.session_id.clone(),
+                document_id,
+                data: data.clone(),
+            });
+        }
+    }
+
+    /// Forget about a peer, e.g. because the connection to it was closed
+    pub fn disconnect(&mut self, peer_id: &PeerId) {
+        if self.peers.remove(peer_id).is_some() {
+            for entry in self.documents.values_mut() {
+                entry.sync.remove_peer(peer_id);
+            }
+            self.events.push(RepoEvent::PeerDisconnected {
+                peer_id: peer_id.clone(),
+            });
+        }
+    }
+
+    /// Decode and receive a message
+    pub fn receive_bytes(&mut self, bytes: &[u8]) -> Result<(), RepoError> {
+        self.receive(RepoMessage::decode(bytes)?)
+    }
+
+    /// Receive a message from a peer
+    pub fn receive(&mut self, message: RepoMessage) -> Result<(), RepoError> {
+        if let Some(target) = message.target_id() {
+            if target != &self.peer_id {
+                tracing::debug!(?target, "ignoring message for another peer");
+                return Ok(());
+            }
+        }
+        match message {
+            RepoMessage::Join {
+                sender_id,
+                supported_protocol_versions,
+                peer_metadata,
+            } => {
+                if supported_protocol_versions.iter().any(|v| v == PROTOCOL_V1) {
+                    self.pending.push(RepoMessage::Peer {
+                        sender_id: self.peer_id.clone(),
+                        target_id: sender_id.clone(),
+                        selected_protocol_version: PROTOCOL_V1.to_string(),
+                        peer_metadata: Some(self.metadata.clone()),
+                    });
+                    self.add_peer(sender_id, peer_metadata);
+                } else {
+                    self.pending.push(RepoMessage::Error {
+                        message: format!(
+                            "unsupported protocol versions {:?}",
+                            supported_protocol_versions
+                        ),
+                    });
+                }
+            }
+            RepoMessage::Peer {
+                sender_id,
+                peer_metadata,
+                ..
+            } => self.add_peer(sender_id, peer_metadata),
+            RepoMessage::Leave { sender_id } => self.disconnect(&sender_id),
+            RepoMessage::Error { message } => self.events.push(RepoEvent::Error { message }),
+            RepoMessage::Request {
+                sender_id,
+                document_id,
+                data,
+                ..
+            } => {
+                self.check_peer(&sender_id)?;
+                let have_doc = self
+                    .documents
+                    .get(&document_id)
+                    .map(|e| e.status == DocStatus::Ready)
+                    .unwrap_or(false);
+                if have_doc {
+                    self.receive_sync(sender_id, document_id, &data)?;
+                } else {
+                    self.pending.push(RepoMessage::DocUnavailable {
+                        sender_id: self.peer_id.clone(),
+                        target_id: sender_id,
+                        document_id,
+                    });
+                }
+            }
+            RepoMessage::Sync {
+                sender_id,
+                document_id,
+                data,
+                ..
+            } => {
+                self.check_peer(&sender_id)?;
+                match self.documents.get_mut(&document_id) {
+                    None => {
+                        let entry = self.new_entry(Automerge::new(), DocStatus::Ready);
+                        self.documents.insert(document_id, entry);
+                    }
+                    Some(entry) if entry.status == DocStatus::Unavailable => {
+                        // The sender has the document after all, ask for it again so our replies
+                        // to the sender go out until it arrives
+                        entry.status = DocStatus::Requesting {
+                            unavailable_from: BTreeSet::new(),
+                        };
+                    }
+                    Some(_) => {}
+                }
+                self.receive_sync(sender_id, document_id, &data)?;
+            }
+            RepoMessage::DocUnavailable {
+                sender_id,
+                document_id,
+                ..
+            } => {
+                let connected = &self.peers;
+                if let Some(entry) = self.documents.get_mut(&document_id) {
+                    if let DocStatus::Requesting { unavailable_from } = &mut entry.status {
+                        unavailable_from.insert(sender_id);
+                        if connected.keys().all(|p| unavailable_from.contains(p)) {
+                            entry.status = DocStatus::Unavailable;
+                            self.events
+                                .push(RepoEvent::DocumentUnavailable { document_id });
+                        }
+                    }
+                }
+            }
+            RepoMessage::Ephemeral {
+                sender_id,
+                document_id,
+                data,
+                ..
+            } => self.events.push(RepoEvent::Ephemeral {
+                sender_id,
+                document_id,
+                data,
+            }),
+            // Newer peers send message types we don't know about, e.g. `remote-heads-changed`
+            RepoMessage::Unknown { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// The messages which should be sent to peers
+    pub fn outgoing(&mut self) -> Vec<RepoMessage> {
+        let mut result = std::mem::take(&mut self.pending);
+        for (document_id, entry) in self.documents.iter_mut() {
+            for (peer, msg) in entry.sync.outgoing() {
+                let data = msg.encode();
+                let msg = match &entry.status {
+                    DocStatus::Ready => RepoMessage::Sync {
+                        sender_id: self.peer_id.clone(),
+                        target_id: peer,
+                        document_id: *document_id,
+                        data,
+                    },
+                    DocStatus::Requesting { unavailable_from }
+                        if !unavailable_from.contains(&peer) =>
+                    {
+                        RepoMessage::Request {
+                            sender_id: self.peer_id.clone(),
+                            target_id: peer,
+                            document_id: *document_id,
+                            data,
+                        }
+                    }
+                    _ => continue,
+                };
+                result.push(msg);
+            }
+        }
+        result
+    }
+
+    /// Take the events which have happened since the last call to this method
+    pub fn take_events(&mut self) -> Vec<RepoEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn new_entry(&self, doc: Automerge, status: DocStatus) -> DocEntry {
+        let mut sync = SyncManager::new(doc);
+        for peer in self.peers.keys() {
+            sync.add_peer(peer.clone());
+        }
+        DocEntry { sync, status }
+    }
+
+    fn add_peer(&mut self, peer_id: PeerId, peer_metadata: Option<PeerMetadata>) {
+        for entry in self.documents.values_mut() {
+            entry.sync.add_peer(peer_id.clone());
+        }
+        self.peers.insert(peer_id.clone(), peer_metadata.clone());
+        self.events.push(RepoEvent::PeerConnected {
+            peer_id,
+            peer_metadata,
+        });
+    }
+
+    fn check_peer(&self, peer_id: &PeerId) -> Result<(), RepoError> {
+        if self.peers.contains_key(peer_id) {
+            Ok(())
+        } else {
+            Err(RepoError::UnknownPeer(peer_id.clone()))
+        }
+    }
+
+    fn receive_sync(
+        &mut self,
+        sender_id: PeerId,
+        document_id: DocumentId,
+        data: &[u8],
+    ) -> Result<(), RepoError> {
+        let msg = Message::decode(data)?;
+        let entry = self
+            .documents
+            .get_mut(&document_id)
+            .expect("document should exist");
+        let before = entry.sync.doc().get_heads();
+        entry.sync.receive(sender_id, msg)?;
+        let after = entry.sync.doc().get_heads();
+        if before != after {
+            // A peer can push a document we gave up on requesting, once we have it we serve it
+            if matches!(
+                entry.status,
+                DocStatus::Requesting { .. } | DocStatus::Unavailable
+            ) {
+                entry.status = DocStatus::Ready;
+            }
+            self.events.push(RepoEvent::DocumentChanged { document_id });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,429 @@
+//! Just enough CBOR (RFC 8949) to encode and decode the automerge-repo network messages
+//!
+//! Encoding always uses definite lengths and the shortest encoding of integers, which is what
+//! the `cbor-x` library automerge-repo uses produces. Decoding accepts any well formed CBOR,
+//! including indefinite length items and tags (which are ignored), so that we can skip over
+//! fields we don't understand.
+
+// Deeply nested input is almost certainly malicious, don't blow the stack on it
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Uint(u64),
+    /// A negative integer, stored as `-1 - n`
+    Neg(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+    Undefined,
+    Float(f64),
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum CborError {
+    #[error("unexpected end of input")]
+    UnexpectedEof,
+    #[error("invalid CBOR at offset {0}")]
+    Invalid(usize),
+    #[error("CBOR nested too deeply")]
+    TooDeep,
+    #[error("invalid UTF-8 in text string")]
+    InvalidUtf8,
+    #[error("{0} bytes of trailing data")]
+    TrailingData(usize),
+}
+
+impl Value {
+    pub(crate) fn text<S: Into<String>>(s: S) -> Self {
+        Value::Text(s.into())
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Uint(u) => Some(*u),
+            // cbor-x encodes large integers as floats
+            Value::Float(f) if *f >= 0.0 && f.fract() == 0.0 && *f <= u64::MAX as f64 => {
+                Some(*f as u64)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// Look up a text key in a map
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Uint(u) => write_head(out, 0, *u),
+            Value::Neg(n) => write_head(out, 1, *n),
+            Value::Bytes(b) => {
+                write_head(out, 2, b.len() as u64);
+                out.extend(b);
+            }
+            Value::Text(s) => {
+                write_head(out, 3, s.len() as u64);
+                out.extend(s.as_bytes());
+            }
+            Value::Array(items) => {
+                write_head(out, 4, items.len() as u64);
+                for item in items {
+                    item.encode_into(out);
+                }
+            }
+            Value::Map(entries) => {
+                write_head(out, 5, entries.len() as u64);
+                for (k, v) in entries {
+                    k.encode_into(out);
+                    v.encode_into(out);
+                }
+            }
+            Value::Bool(false) => out.push(0xf4),
+            Value::Bool(true) => out.push(0xf5),
+            Value::Null => out.push(0xf6),
+            Value::Undefined => out.push(0xf7),
+            Value::Float(f) => {
+                out.push(0xfb);
+                out.extend(f.to_be_bytes());
+            }
+        }
+    }
+
+    pub(crate) fn decode(input: &[u8]) -> Result<Self, CborError> {
+        let mut decoder = Decoder { input, offset: 0 };
+        let value = decoder.value(0)?;
+        if decoder.offset != input.len() {
+            return Err(CborError::TrailingData(input.len() - decoder.offset));
+        }
+        Ok(value)
+    }
+}
+
+fn write_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    if value < 24 {
+        out.push(major | value as u8);
+    } else if value <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(value as u8);
+    } else if value <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend((value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend((value as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend(value.to_be_bytes());
+    }
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    offset: usize,
+}
+
+/// The argument of an item head, `None` for indefinite lengths
+type Argument = Option<u64>;
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CborError> {
+        if self.input.len() - self.offset < n {
+            return Err(CborError::UnexpectedEof);
+        }
+        let result = &self.input[self.offset..self.offset + n];
+        self.offset += n;
+        Ok(result)
+    }
+
+    fn byte(&mut self) -> Result<u8, CborError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn peek(&self) -> Result<u8, CborError> {
+        self.input
+            .get(self.offset)
+            .copied()
+            .ok_or(CborError::UnexpectedEof)
+    }
+
+    fn head(&mut self) -> Result<(u8, u8, Argument), CborError> {
+        let start = self.offset;
+        let initial = self.byte()?;
+        let major = initial >> 5;
+        let info = initial & 0x1f;
+        let arg = match info {
+            0..=23 => Some(info as u64),
+            24 => Some(self.byte()? as u64),
+            25 => Some(u16::from_be_bytes(self.take(2)?.try_into().unwrap()) as u64),
+            26 => Some(u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64),
+            27 => Some(u64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+            31 if matches!(major, 2..=5 | 7) => None,
+            _ => return Err(CborError::Invalid(start)),
+        };
+        Ok((major, info, arg))
+    }
+
+    fn length(&self, arg: u64) -> Result<usize, CborError> {
+        // Every item is at least one byte so a length longer than the remaining input is bogus
+        let len = usize::try_from(arg).map_err(|_| CborError::UnexpectedEof)?;
+        if len > self.input.len() - self.offset {
+            Err(CborError::UnexpectedEof)
+        } else {
+            Ok(len)
+        }
+    }
+
+    fn is_break(&mut self) -> Result<bool, CborError> {
+        if self.peek()? == 0xff {
+            self.offset += 1;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn string_bytes(&mut self, major: u8, arg: Argument) -> Result<Vec<u8>, CborError> {
+        match arg {
+            Some(len) => {
+                let len = self.length(len)?;
+                Ok(self.take(len)?.to_vec())
+            }
+            None => {
+                let mut result = Vec::new();
+                while !self.is_break()? {
+                    let start = self.offset;
+                    let (chunk_major, _, chunk_arg) = self.head()?;
+                    match chunk_arg {
+                        Some(len) if chunk_major == major => {
+                            let len = self.length(len)?;
+                            result.extend(self.take(len)?);
+                        }
+                        _ => return Err(CborError::Invalid(start)),
+                    }
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, CborError> {
+        if depth > MAX_DEPTH {
+            return Err(CborError::TooDeep);
+        }
+        let start = self.offset;
+        let (major, info, arg) = self.head()?;
+        match major {
+            0 => Ok(Value::Uint(arg.unwrap())),
+            1 => Ok(Value::Neg(arg.unwrap())),
+            2 => Ok(Value::Bytes(self.string_bytes(2, arg)?)),
+            3 => String::from_utf8(self.string_bytes(3, arg)?)
+                .map(Value::Text)
+                .map_err(|_| CborError::InvalidUtf8),
+            4 => {
+                let mut items = Vec::new();
+                match arg {
+                    Some(len) => {
+                        for _ in 0..self.length(len)? {
+                            items.push(self.value(depth + 1)?);
+                        }
+                    }
+                    None => {
+                        while !self.is_break()? {
+                            items.push(self.value(depth + 1)?);
+                        }
+                    }
+                }
+                Ok(Value::Array(items))
+            }
+            5 => {
+                let mut entries = Vec::new();
+                match arg {
+                    Some(len) => {
+                        for _ in 0..self.length(len)? {
+                            let k = self.value(depth + 1)?;
+                            let v = self.value(depth + 1)?;
+                            entries.push((k, v));
+                        }
+                    }
+                    None => {
+                        while !self.is_break()? {
+                            let k = self.value(depth + 1)?;
+                            let v = self.value(depth + 1)?;
+                            entries.push((k, v));
+                        }
+                    }
+                }
+                Ok(Value::Map(entries))
+            }
+            // Tags carry semantic information we don't need, just decode the tagged item
+            6 => self.value(depth + 1),
+            7 => match (info, arg) {
+                (20, _) => Ok(Value::Bool(false)),
+                (21, _) => Ok(Value::Bool(true)),
+                (22, _) => Ok(Value::Null),
+                (23, _) => Ok(Value::Undefined),
+                (25, Some(bits)) => Ok(Value::Float(f16_to_f64(bits as u16))),
+                (26, Some(bits)) => Ok(Value::Float(f32::from_bits(bits as u32) as f64)),
+                (27, Some(bits)) => Ok(Value::Float(f64::from_bits(bits))),
+                _ => Err(CborError::Invalid(start)),
+            },
+            _ => unreachable!("major type is three bits"),
+        }
+    }
+}
+
+fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let fraction = (bits & 0x3ff) as f64;
+    let magnitude = match exponent {
+        0 => fraction * 2f64.powi(-24),
+        31 if fraction == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + fraction / 1024.0) * 2f64.powi(exponent - 15),
+    };
+    sign * magnitude
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(value: Value, expected: &[u8]) {
+        assert_eq!(value.encode(), expected);
+        assert_eq!(Value::decode(expected).unwrap(), value);
+    }
+
+    // Examples from appendix A of RFC 8949
+    #[test]
+    fn rfc_examples() {
+        roundtrip(Value::Uint(0), &[0x00]);
+        roundtrip(Value::Uint(23), &[0x17]);
+        roundtrip(Value::Uint(24), &[0x18, 0x18]);
+        roundtrip(Value::Uint(1000), &[0x19, 0x03, 0xe8]);
+        roundtrip(Value::Uint(1000000), &[0x1a, 0x00, 0x0f, 0x42, 0x40]);
+        roundtrip(
+            Value::Uint(1000000000000),
+            &[0x1b, 0x00, 0x00, 0x00, 0xe8, 0xd4, 0xa5, 0x10, 0x00],
+        );
+        roundtrip(Value::Neg(9), &[0x29]);
+        roundtrip(Value::text("IETF"), &[0x64, 0x49, 0x45, 0x54, 0x46]);
+        roundtrip(Value::Bytes(vec![1, 2, 3, 4]), &[0x44, 1, 2, 3, 4]);
+        roundtrip(
+            Value::Array(vec![Value::Uint(1), Value::Uint(2), Value::Uint(3)]),
+            &[0x83, 0x01, 0x02, 0x03],
+        );
+        roundtrip(
+            Value::Map(vec![
+                (Value::text("a"), Value::Uint(1)),
+                (
+                    Value::text("b"),
+                    Value::Array(vec![Value::Uint(2), Value::Uint(3)]),
+                ),
+            ]),
+            &[0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x82, 0x02, 0x03],
+        );
+        roundtrip(Value::Bool(true), &[0xf5]);
+        roundtrip(Value::Null, &[0xf6]);
+        assert_eq!(
+            Value::decode(&[0xf9, 0x3c, 0x00]).unwrap(),
+            Value::Float(1.0)
+        );
+        assert_eq!(
+            Value::decode(&[0xfa, 0x47, 0xc3, 0x50, 0x00]).unwrap(),
+            Value::Float(100000.0)
+        );
+    }
+
+    #[test]
+    fn indefinite_lengths_and_tags() {
+        // (_ h'0102', h'030405')
+        assert_eq!(
+            Value::decode(&[0x5f, 0x42, 0x01, 0x02, 0x43, 0x03, 0x04, 0x05, 0xff]).unwrap(),
+            Value::Bytes(vec![1, 2, 3, 4, 5])
+        );
+        // {_ "a": 1, "b": [_ 2, 3]}
+        assert_eq!(
+            Value::decode(&[0xbf, 0x61, 0x61, 0x01, 0x61, 0x62, 0x9f, 0x02, 0x03, 0xff, 0xff])
+                .unwrap(),
+            Value::Map(vec![
+                (Value::text("a"), Value::Uint(1)),
+                (
+                    Value::text("b"),
+                    Value::Array(vec![Value::Uint(2), Value::Uint(3)])
+                ),
+            ])
+        );
+        // 64(h'0102'), a tagged Uint8Array
+        assert_eq!(
+            Value::decode(&[0xd8, 0x40, 0x42, 0x01, 0x02]).unwrap(),
+            Value::Bytes(vec![1, 2])
+        );
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert_eq!(Value::decode(&[]), Err(CborError::UnexpectedEof));
+        assert_eq!(Value::decode(&[0x44, 1, 2]), Err(CborError::UnexpectedEof));
+        assert_eq!(
+            Value::decode(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Err(CborError::UnexpectedEof)
+        );
+        assert_eq!(Value::decode(&[0x1c]), Err(CborError::Invalid(0)));
+        assert_eq!(
+            Value::decode(&[0x01, 0x02]),
+            Err(CborError::TrailingData(1))
+        );
+        assert_eq!(
+            Value::decode(&[0x62, 0xff, 0xfe]),
+            Err(CborError::InvalidUtf8)
+        );
+        assert_eq!(Value::decode(&[0x81; 100]), Err(CborError::TooDeep));
+    }
+}
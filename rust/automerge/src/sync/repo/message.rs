@@ -0,0 +1,339 @@
+use super::cbor::{CborError, Value};
+use crate::sync::DocumentId;
+
+/// The identifier of a peer in the automerge-repo network protocol
+pub type PeerId = String;
+
+/// The only protocol version defined by automerge-repo so far
+pub const PROTOCOL_V1: &str = "1";
+
+/// Information a peer shares about itself during the handshake
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerMetadata {
+    /// The ID of the storage the peer persists documents to, if any
+    pub storage_id: Option<String>,
+    /// Whether the peer will forget everything when it disconnects
+    pub is_ephemeral: bool,
+}
+
+/// A message in the automerge-repo network protocol
+///
+/// Messages are encoded as CBOR maps with a `type` field, this is the same encoding the
+/// JavaScript `automerge-repo` library uses.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepoMessage {
+    /// Sent by the connecting peer to start the handshake
+    Join {
+        sender_id: PeerId,
+        supported_protocol_versions: Vec<String>,
+        peer_metadata: Option<PeerMetadata>,
+    },
+    /// Sent in response to a [`RepoMessage::Join`] to complete the handshake
+    Peer {
+        sender_id: PeerId,
+        target_id: PeerId,
+        selected_protocol_version: String,
+        peer_metadata: Option<PeerMetadata>,
+    },
+    /// Sent by a peer which is about to disconnect
+    Leave { sender_id: PeerId },
+    /// Sent when something has gone wrong, e.g. no common protocol version
+    Error { message: String },
+    /// A sync message for a document the sender does not have yet
+    Request {
+        sender_id: PeerId,
+        target_id: PeerId,
+        document_id: DocumentId,
+        data: Vec<u8>,
+    },
+    /// A sync message for a document
+    Sync {
+        sender_id: PeerId,
+        target_id: PeerId,
+        document_id: DocumentId,
+        data: Vec<u8>,
+    },
+    /// Sent in response to a [`RepoMessage::Request`] for a document the sender doesn't have
+    DocUnavailable {
+        sender_id: PeerId,
+        target_id: PeerId,
+        document_id: DocumentId,
+    },
+    /// Application data which is not stored in the document, e.g. cursor positions
+    Ephemeral {
+        sender_id: PeerId,
+        target_id: PeerId,
+        count: u64,
+        session_id: String,
+        document_id: DocumentId,
+        data: Vec<u8>,
+    },
+    /// A message with a type this implementation doesn't understand
+    ///
+    /// The protocol is extended with new message types over time (e.g. `remote-heads-changed`),
+    /// peers are expected to ignore messages they don't understand rather than disconnecting.
+    /// `encoded` is the whole message as it was received so that it can be passed on unchanged.
+    Unknown {
+        message_type: String,
+        encoded: Vec<u8>,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeRepoMessageError {
+    #[error(transparent)]
+    Cbor(#[from] CborError),
+    #[error("message was not a map")]
+    NotAMap,
+    #[error("missing or invalid field `{0}`")]
+    BadField(&'static str),
+}
+
+fn text(value: &Value, field: &'static str) -> Result<String, DecodeRepoMessageError> {
+    value
+        .get(field)
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or(DecodeRepoMessageError::BadField(field))
+}
+
+fn document_id(value: &Value) -> Result<DocumentId, DecodeRepoMessageError> {
+    value
+        .get("documentId")
+        .and_then(Value::as_str)
+        .and_then(|s| s.parse().ok())
+        .ok_or(DecodeRepoMessageError::BadField("documentId"))
+}
+
+fn bytes(value: &Value, field: &'static str) -> Result<Vec<u8>, DecodeRepoMessageError> {
+    value
+        .get(field)
+        .and_then(Value::as_bytes)
+        .map(Vec::from)
+        .ok_or(DecodeRepoMessageError::BadField(field))
+}
+
+fn peer_metadata(value: &Value) -> Result<Option<PeerMetadata>, DecodeRepoMessageError> {
+    match value.get("peerMetadata") {
+        None | Some(Value::Null) | Some(Value::Undefined) => Ok(None),
+        Some(meta @ Value::Map(_)) => {
+            let storage_id = match meta.get("storageId") {
+                None | Some(Value::Null) | Some(Value::Undefined) => None,
+                Some(Value::Text(s)) => Some(s.clone()),
+                Some(_) => return Err(DecodeRepoMessageError::BadField("storageId")),
+            };
+            let is_ephemeral = match meta.get("isEphemeral") {
+                None => false,
+                Some(v) => v
+                    .as_bool()
+                    .ok_or(DecodeRepoMessageError::BadField("isEphemeral"))?,
+            };
+            Ok(Some(PeerMetadata {
+                storage_id,
+                is_ephemeral,
+            }))
+        }
+        Some(_) => Err(DecodeRepoMessageError::BadField("peerMetadata")),
+    }
+}
+
+fn encode_peer_metadata(meta: &PeerMetadata) -> Value {
+    let mut entries = Vec::new();
+    if let Some(storage_id) = &meta.storage_id {
+        entries.push((Value::text("storageId"), Value::text(storage_id.as_str())));
+    }
+    entries.push((Value::text("isEphemeral"), Value::Bool(meta.is_ephemeral)));
+    Value::Map(entries)
+}
+
+impl RepoMessage {
+    /// The `type` field of the encoded message
+    pub fn message_type(&self) -> &str {
+        match self {
+            Self::Join { .. } => "join",
+            Self::Peer { .. } => "peer",
+            Self::Leave { .. } => "leave",
+            Self::Error { .. } => "error",
+            Self::Request { .. } => "request",
+            Self::Sync { .. } => "sync",
+            Self::DocUnavailable { .. } => "doc-unavailable",
+            Self::Ephemeral { .. } => "ephemeral",
+            Self::Unknown { message_type, .. } => message_type,
+        }
+    }
+
+    /// The peer this message is addressed to, if it is addressed to a specific peer
+    pub fn target_id(&self) -> Option<&PeerId> {
+        match self {
+            Self::Peer { target_id, .. }
+            | Self::Request { target_id, .. }
+            | Self::Sync { target_id, .. }
+            | Self::DocUnavailable { target_id, .. }
+            | Self::Ephemeral { target_id, .. } => Some(target_id),
+            Self::Join { .. } | Self::Leave { .. } | Self::Error { .. } | Self::Unknown { .. } => {
+                None
+            }
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut entries = vec![(Value::text("type"), Value::text(self.message_type()))];
+        let mut field = |name: &str, value: Value| entries.push((Value::text(name), value));
+        match self {
+            Self::Join {
+                sender_id,
+                supported_protocol_versions,
+                peer_metadata,
+            } => {
+                field("senderId", Value::text(sender_id.as_str()));
+                if let Some(meta) = peer_metadata {
+                    field("peerMetadata", encode_peer_metadata(meta));
+                }
+                field(
+                    "supportedProtocolVersions",
+                    Value::Array(
+                        supported_protocol_versions
+                            .iter()
+                            .map(|v| Value::text(v.as_str()))
+                            .collect(),
+                    ),
+                );
+            }
+            Self::Peer {
+                sender_id,
+                target_id,
+                selected_protocol_version,
+                peer_metadata,
+            } => {
+                field("senderId", Value::text(sender_id.as_str()));
+                if let Some(meta) = peer_metadata {
+                    field("peerMetadata", encode_peer_metadata(meta));
+                }
+                field(
+                    "selectedProtocolVersion",
+                    Value::text(selected_protocol_version.as_str()),
+                );
+                field("targetId", Value::text(target_id.as_str()));
+            }
+            Self::Leave { sender_id } => {
+                field("senderId", Value::text(sender_id.as_str()));
+            }
+            Self::Error { message } => {
+                field("message", Value::text(message.as_str()));
+            }
+            Self::Request {
+                sender_id,
+                target_id,
+                document_id,
+                data,
+            }
+            | Self::Sync {
+                sender_id,
+                target_id,
+                document_id,
+                data,
+            } => {
+                field("senderId", Value::text(sender_id.as_str()));
+                field("targetId", Value::text(target_id.as_str()));
+                field("documentId", Value::Text(document_id.to_string()));
+                field("data", Value::Bytes(data.clone()));
+            }
+            Self::DocUnavailable {
+                sender_id,
+                target_id,
+                document_id,
+            } => {
+                field("senderId", Value::text(sender_id.as_str()));
+                field("targetId", Value::text(target_id.as_str()));
+                field("documentId", Value::Text(document_id.to_string()));
+            }
+            Self::Ephemeral {
+                sender_id,
+                target_id,
+                count,
+                session_id,
+                document_id,
+                data,
+            } => {
+                field("senderId", Value::text(sender_id.as_str()));
+                field("targetId", Value::text(target_id.as_str()));
+                field("count", Value::Uint(*count));
+                field("sessionId", Value::text(session_id.as_str()));
+                field("documentId", Value::Text(document_id.to_string()));
+                field("data", Value::Bytes(data.clone()));
+            }
+            Self::Unknown { encoded, .. } => return encoded.clone(),
+        }
+        Value::Map(entries).encode()
+    }
+
+    pub fn decode(input: &[u8]) -> Result<Self, DecodeRepoMessageError> {
+        let value = Value::decode(input)?;
+        if !matches!(value, Value::Map(_)) {
+            return Err(DecodeRepoMessageError::NotAMap);
+        }
+        let msg_type = text(&value, "type")?;
+        match msg_type.as_str() {
+            "join" => Ok(Self::Join {
+                sender_id: text(&value, "senderId")?,
+                supported_protocol_versions: value
+                    .get("supportedProtocolVersions")
+                    .and_then(Value::as_array)
+                    .and_then(|versions| {
+                        versions
+                            .iter()
+                            .map(|v| v.as_str().map(String::from))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or(DecodeRepoMessageError::BadField(
+                        "supportedProtocolVersions",
+                    ))?,
+                peer_metadata: peer_metadata(&value)?,
+            }),
+            "peer" => Ok(Self::Peer {
+                sender_id: text(&value, "senderId")?,
+                target_id: text(&value, "targetId")?,
+                selected_protocol_version: text(&value, "selectedProtocolVersion")?,
+                peer_metadata: peer_metadata(&value)?,
+            }),
+            "leave" => Ok(Self::Leave {
+                sender_id: text(&value, "senderId")?,
+            }),
+            "error" => Ok(Self::Error {
+                message: text(&value, "message")?,
+            }),
+            "request" => Ok(Self::Request {
+                sender_id: text(&value, "senderId")?,
+                target_id: text(&value, "targetId")?,
+                document_id: document_id(&value)?,
+                data: bytes(&value, "data")?,
+            }),
+            "sync" => Ok(Self::Sync {
+                sender_id: text(&value, "senderId")?,
+                target_id: text(&value, "targetId")?,
+                document_id: document_id(&value)?,
+                data: bytes(&value, "data")?,
+            }),
+            "doc-unavailable" => Ok(Self::DocUnavailable {
+                sender_id: text(&value, "senderId")?,
+                target_id: text(&value, "targetId")?,
+                document_id: document_id(&value)?,
+            }),
+            "ephemeral" => Ok(Self::Ephemeral {
+                sender_id: text(&value, "senderId")?,
+                target_id: text(&value, "targetId")?,
+                count: value
+                    .get("count")
+                    .and_then(Value::as_u64)
+                    .ok_or(DecodeRepoMessageError::BadField("count"))?,
+                session_id: text(&value, "sessionId")?,
+                document_id: document_id(&value)?,
+                data: bytes(&value, "data")?,
+            }),
+            _ => Ok(Self::Unknown {
+                message_type: msg_type,
+                encoded: input.to_vec(),
+            }),
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::BTreeSet;
 
 #[cfg(doc)]
 use super::SyncDoc;
-use super::{encode_hashes, BloomFilter, Capability};
+use super::{encode_hashes, BloomFilter, BloomParams, Capability, Iblt};
 use crate::storage::parse;
 use crate::ChangeHash;
 
@@ -60,6 +60,12 @@ pub struct State {
 
     /// The capabilities the other side has said they have
     pub their_capabilities: Option<Vec<Capability>>,
+
+    /// See [`Self::bloom_params()`]
+    pub(crate) bloom_params: BloomParams,
+
+    /// See [`Self::iblt_cells()`]
+    pub(crate) iblt_cells: Option<usize>,
 }
 
 /// A summary of the changes that the sender of the message already has.
@@ -72,6 +78,48 @@ pub struct Have {
     /// A bloom filter summarising all of the changes that the sender of the message has added
     /// since the last sync.
     pub bloom: BloomFilter,
+    /// See [`Self::iblt()`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) iblt: Option<Iblt>,
+}
+
+impl Have {
+    pub fn new(last_sync: Vec<ChangeHash>, bloom: BloomFilter) -> Self {
+        Have {
+            last_sync,
+            bloom,
+            iblt: None,
+        }
+    }
+
+    /// Send `iblt` alongside the bloom filter, see [`Self::iblt()`]
+    pub fn with_iblt(self, iblt: Iblt) -> Self {
+        Have {
+            iblt: Some(iblt),
+            ..self
+        }
+    }
+
+    /// An optional invertible bloom lookup table summarising the same changes as
+    /// [`Self::bloom`]. This is only sent to peers which advertise [`Capability::Iblt`].
+    pub fn iblt(&self) -> Option<&Iblt> {
+        self.iblt.as_ref()
+    }
+
+    /// The bytes of the length delimited filter field of an encoded `Have`
+    ///
+    /// Older implementations only parse a bloom filter from this field and ignore anything after
+    /// it, so the IBLT (if any) is appended after the bloom filter.
+    pub(crate) fn filter_bytes(&self) -> Vec<u8> {
+        match &self.iblt {
+            Some(iblt) => {
+                let mut bytes = self.bloom.to_bytes_with_header();
+                bytes.extend(iblt.to_bytes());
+                bytes
+            }
+            None => self.bloom.to_bytes(),
+        }
+    }
 }
 
 impl State {
@@ -79,6 +127,34 @@ impl State {
         Default::default()
     }
 
+    /// The parameters to use for the bloom filters we send to the other end
+    ///
+    /// This is not persisted by [`Self::encode()`].
+    pub fn bloom_params(&self) -> BloomParams {
+        self.bloom_params
+    }
+
+    pub fn set_bloom_params(&mut self, params: BloomParams) {
+        self.bloom_params = params;
+    }
+
+    /// The number of cells in the [`Iblt`] we send alongside each bloom filter, if any
+    ///
+    /// If this is `Some` then we advertise [`Capability::Iblt`] and, if the other end advertises
+    /// it too, send an IBLT with this many cells. This allows the other end to compute exactly
+    /// which changes we are missing, avoiding the extra round trips caused by bloom filter false
+    /// positives, as long as the number of differing changes is smaller than roughly two thirds
+    /// of the number of cells.
+    ///
+    /// This is not persisted by [`Self::encode()`].
+    pub fn iblt_cells(&self) -> Option<usize> {
+        self.iblt_cells
+    }
+
+    pub fn set_iblt_cells(&mut self, num_cells: Option<usize>) {
+        self.iblt_cells = num_cells;
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         let mut buf = vec![SYNC_STATE_TYPE];
         encode_hashes(&mut buf, &self.shared_heads);
@@ -116,6 +192,8 @@ impl State {
                 in_flight: false,
                 have_responded: false,
                 their_capabilities: None,
+                bloom_params: BloomParams::default(),
+                iblt_cells: None,
             },
         ))
     }
@@ -126,4 +204,20 @@ impl State {
             .map(|caps| caps.contains(&Capability::MessageV2))
             .unwrap_or(false)
     }
+
+    pub(crate) fn supports_iblt(&self) -> bool {
+        self.their_capabilities
+            .as_ref()
+            .map(|caps| caps.contains(&Capability::Iblt))
+            .unwrap_or(false)
+    }
+
+    /// The capabilities we advertise to the other end
+    pub(crate) fn our_capabilities(&self) -> Vec<Capability> {
+        let mut caps = vec![Capability::MessageV1, Capability::MessageV2];
+        if self.iblt_cells.is_some() {
+            caps.push(Capability::Iblt);
+        }
+        caps
+    }
 }
@@ -7,7 +7,9 @@ use crate::marks::{ExpandMark, Mark, MarkSet};
 use crate::patches::PatchLog;
 use crate::types::Clock;
 use crate::{hydrate, AutomergeError};
-use crate::{Automerge, ChangeHash, Cursor, ObjType, Parents, Prop, ReadDoc, ScalarValue, Value};
+use crate::{
+    Automerge, ChangeHash, Cursor, ObjType, Parents, Prop, ReadAt, ReadDoc, ScalarValue, Value,
+};
 
 use super::{CommitOptions, Transactable, TransactionArgs, TransactionInner};
 
@@ -128,8 +130,9 @@ impl<'a> ReadDoc for Transaction<'a> {
         self.doc.keys_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn keys_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Keys<'_> {
-        self.doc.keys_for(obj.as_ref(), self.get_scope(Some(heads)))
+    fn keys_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Keys<'_> {
+        self.doc
+            .keys_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn map_range<'b, O: AsRef<ExId>, R: RangeBounds<String> + 'b>(
@@ -141,14 +144,17 @@ impl<'a> ReadDoc for Transaction<'a> {
             .map_range_for(obj.as_ref(), range, self.get_scope(None))
     }
 
-    fn map_range_at<'b, O: AsRef<ExId>, R: RangeBounds<String> + 'b>(
+    fn map_range_at<'b, 'h, O: AsRef<ExId>, R: RangeBounds<String> + 'b, H: Into<ReadAt<'h>>>(
         &'b self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> MapRange<'b, R> {
-        self.doc
-            .map_range_for(obj.as_ref(), range, self.get_scope(Some(heads)))
+        self.doc.map_range_for(
+            obj.as_ref(),
+            range,
+            Some(self.doc.clock_for_read(heads.into())),
+        )
     }
 
     fn list_range<O: AsRef<ExId>, R: RangeBounds<usize>>(
@@ -160,32 +166,35 @@ impl<'a> ReadDoc for Transaction<'a> {
             .list_range_for(obj.as_ref(), range, self.get_scope(None))
     }
 
-    fn list_range_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+    fn list_range_at<'h, O: AsRef<ExId>, R: RangeBounds<usize>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         range: R,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> ListRange<'_, R> {
-        self.doc
-            .list_range_for(obj.as_ref(), range, self.get_scope(Some(heads)))
+        self.doc.list_range_for(
+            obj.as_ref(),
+            range,
+            Some(self.doc.clock_for_read(heads.into())),
+        )
     }
 
     fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
         self.doc.values_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn values_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Values<'_> {
+    fn values_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> Values<'_> {
         self.doc
-            .values_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .values_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
         self.doc.length_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize {
+    fn length_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(&self, obj: O, heads: H) -> usize {
         self.doc
-            .length_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .length_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn object_type<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjType, AutomergeError> {
@@ -196,25 +205,26 @@ impl<'a> ReadDoc for Transaction<'a> {
         self.doc.text_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn text_at<O: AsRef<ExId>>(
+    fn text_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<String, AutomergeError> {
-        self.doc.text_for(obj.as_ref(), self.get_scope(Some(heads)))
+        self.doc
+            .text_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
         self.doc.spans_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn spans_at<O: AsRef<ExId>>(
+    fn spans_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Spans<'_>, AutomergeError> {
         self.doc
-            .spans_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .spans_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn get_cursor<O: AsRef<ExId>>(
@@ -241,13 +251,13 @@ impl<'a> ReadDoc for Transaction<'a> {
         self.doc.marks_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn marks_at<O: AsRef<ExId>>(
+    fn marks_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<Mark<'_>>, AutomergeError> {
         self.doc
-            .marks_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .marks_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn hydrate<O: AsRef<ExId>>(
@@ -277,14 +287,17 @@ impl<'a> ReadDoc for Transaction<'a> {
             .get_for(obj.as_ref(), prop.into(), self.get_scope(None))
     }
 
-    fn get_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
-        self.doc
-            .get_for(obj.as_ref(), prop.into(), self.get_scope(Some(heads)))
+        self.doc.get_for(
+            obj.as_ref(),
+            prop.into(),
+            Some(self.doc.clock_for_read(heads.into())),
+        )
     }
 
     fn get_all<O: AsRef<ExId>, P: Into<Prop>>(
@@ -296,27 +309,30 @@ impl<'a> ReadDoc for Transaction<'a> {
             .get_all_for(obj.as_ref(), prop.into(), self.get_scope(None))
     }
 
-    fn get_all_at<O: AsRef<ExId>, P: Into<Prop>>(
+    fn get_all_at<'h, O: AsRef<ExId>, P: Into<Prop>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
         prop: P,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError> {
-        self.doc
-            .get_all_for(obj.as_ref(), prop.into(), self.get_scope(Some(heads)))
+        self.doc.get_all_for(
+            obj.as_ref(),
+            prop.into(),
+            Some(self.doc.clock_for_read(heads.into())),
+        )
     }
 
     fn parents<O: AsRef<ExId>>(&self, obj: O) -> Result<Parents<'_>, AutomergeError> {
         self.doc.parents_for(obj.as_ref(), self.get_scope(None))
     }
 
-    fn parents_at<O: AsRef<ExId>>(
+    fn parents_at<'h, O: AsRef<ExId>, H: Into<ReadAt<'h>>>(
         &self,
         obj: O,
-        heads: &[ChangeHash],
+        heads: H,
     ) -> Result<Parents<'_>, AutomergeError> {
         self.doc
-            .parents_for(obj.as_ref(), self.get_scope(Some(heads)))
+            .parents_for(obj.as_ref(), Some(self.doc.clock_for_read(heads.into())))
     }
 
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
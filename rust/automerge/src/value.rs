@@ -1,3 +1,4 @@
+use crate::columnar::column_range::REDACTED_TYPE_CODE;
 use crate::error;
 use crate::types::ObjType;
 use serde::{Deserialize, Serialize, Serializer};
@@ -584,6 +585,23 @@ impl ScalarValue {
         matches!(self, Self::Null)
     }
 
+    /// The marker which [`Automerge::redact()`](crate::Automerge::redact) stores in place of a
+    /// redacted value
+    ///
+    /// This is encoded as a value of an unknown type, so older versions of this library load
+    /// redacted documents.
+    pub fn redacted() -> Self {
+        ScalarValue::Unknown {
+            type_code: REDACTED_TYPE_CODE,
+            bytes: Vec::new(),
+        }
+    }
+
+    /// Whether this is the marker [`ScalarValue::redacted()`] returns
+    pub fn is_redacted(&self) -> bool {
+        matches!(self, Self::Unknown { type_code: REDACTED_TYPE_CODE, bytes } if bytes.is_empty())
+    }
+
     pub fn into_bytes(self) -> Result<Vec<u8>, Self> {
         match self {
             ScalarValue::Bytes(b) => Ok(b),
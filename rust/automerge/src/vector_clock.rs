@@ -0,0 +1,201 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::clock::{Clock, ClockData};
+use crate::indexed_cache::IndexedCache;
+use crate::storage::versioned;
+use crate::{ActorId, ChangeHash};
+
+/// A vector clock describing a state of a document
+///
+/// For every actor which has made changes visible in the state, a vector clock records the
+/// sequence number of the actor's last included change and the highest op counter in that change.
+/// Obtain one with [`Automerge::vector_clock()`](crate::Automerge::vector_clock).
+///
+/// Vector clocks can be passed to the `*_at` methods of [`ReadDoc`](crate::ReadDoc) in place of
+/// heads, which avoids walking the change graph for every read. They are partially ordered by
+/// happened-before: `a < b` if every change included in `a` is also included in `b`, and
+/// `a.partial_cmp(&b)` is `None` if the states are concurrent.
+///
+/// This can be persisted using [`Self::to_bytes()`] and `TryFrom<&[u8]>`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorClock(BTreeMap<ActorId, ClockData>);
+
+impl VectorClock {
+    pub(crate) fn from_clock(clock: &Clock, actors: &IndexedCache<ActorId>) -> Self {
+        VectorClock(
+            clock
+                .iter()
+                .map(|(actor, data)| (actors.get(*actor).clone(), *data))
+                .collect(),
+        )
+    }
+
+    /// The internal clock for a document with the given actors
+    ///
+    /// Actors the document doesn't know about can't have made any of its ops and are skipped.
+    pub(crate) fn to_clock(&self, actors: &IndexedCache<ActorId>) -> Clock {
+        let mut clock = Clock::new();
+        for (actor, data) in &self.0 {
+            if let Some(index) = actors.lookup(actor) {
+                clock.include(index, *data);
+            }
+        }
+        clock
+    }
+
+    /// The number of actors in the clock
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the clock describes the empty document
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The actors in the clock, in ascending order
+    pub fn actors(&self) -> impl Iterator<Item = &ActorId> {
+        self.0.keys()
+    }
+
+    /// The sequence number of the last change by `actor` included in the clock
+    pub fn seq(&self, actor: &ActorId) -> Option<u64> {
+        self.0.get(actor).map(|d| d.seq)
+    }
+
+    /// The highest op counter of the last change by `actor` included in the clock
+    pub fn max_op(&self, actor: &ActorId) -> Option<u64> {
+        self.0.get(actor).map(|d| d.max_op)
+    }
+
+    /// Serialize this clock to a byte array.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // The number of entries followed by the entries sorted by actor ID, each entry is the
+        // actor ID, the seq and the max op
+        let mut encoder = versioned::Encoder::new();
+        encoder.uleb(self.0.len() as u64);
+        for (actor, data) in &self.0 {
+            encoder.len_prefixed(actor.to_bytes());
+            encoder.uleb(data.seq);
+            encoder.uleb(data.max_op);
+        }
+        encoder.finish()
+    }
+
+    /// Whether every change included in `self` is included in `other`
+    fn included_in(&self, other: &Self) -> bool {
+        self.0.iter().all(|(actor, data)| {
+            other
+                .0
+                .get(actor)
+                .map(|o| o.max_op >= data.max_op)
+                .unwrap_or(false)
+        })
+    }
+}
+
+impl PartialOrd for VectorClock {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.included_in(other), other.included_in(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (false, false) => None,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VectorClockFromBytesError {
+    #[error("no version tag")]
+    NoVersion,
+    #[error("invalid version tag")]
+    InvalidVersion(u8),
+    #[error("invalid number of entries: {0}")]
+    ParseNumEntries(String),
+    #[error("invalid Actor ID length: {0}")]
+    ParseActorLen(String),
+    #[error("Not enough bytes in actor ID")]
+    ParseActor,
+    #[error("invalid seq: {0}")]
+    ParseSeq(String),
+    #[error("invalid max op: {0}")]
+    ParseMaxOp(String),
+    #[error("trailing bytes")]
+    TrailingBytes,
+}
+
+impl From<versioned::VersionError> for VectorClockFromBytesError {
+    fn from(e: versioned::VersionError) -> Self {
+        match e {
+            versioned::VersionError::NoVersion => Self::NoVersion,
+            versioned::VersionError::InvalidVersion(v) => Self::InvalidVersion(v),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for VectorClock {
+    type Error = VectorClockFromBytesError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut decoder = versioned::Decoder::new(value)?;
+        let len = decoder
+            .uleb()
+            .map_err(|e| VectorClockFromBytesError::ParseNumEntries(e.to_string()))?;
+        let mut entries = BTreeMap::new();
+        for _ in 0..len {
+            let actor_len = decoder
+                .uleb()
+                .map_err(|e| VectorClockFromBytesError::ParseActorLen(e.to_string()))?;
+            let actor = decoder
+                .take(actor_len)
+                .ok_or(VectorClockFromBytesError::ParseActor)?;
+            let seq = decoder
+                .uleb()
+                .map_err(|e| VectorClockFromBytesError::ParseSeq(e.to_string()))?;
+            let max_op = decoder
+                .uleb()
+                .map_err(|e| VectorClockFromBytesError::ParseMaxOp(e.to_string()))?;
+            entries.insert(ActorId::from(actor), ClockData { max_op, seq });
+        }
+        if !decoder.is_empty() {
+            return Err(VectorClockFromBytesError::TrailingBytes);
+        }
+        Ok(VectorClock(entries))
+    }
+}
+
+/// The state of a document to read at, either the heads of the state or its [`VectorClock`]
+///
+/// The `*_at` methods of [`ReadDoc`](crate::ReadDoc) accept anything which converts into this,
+/// so `&[ChangeHash]`, `&Vec<ChangeHash>` and `&VectorClock` can all be passed directly.
+#[derive(Debug, Clone, Copy)]
+pub enum ReadAt<'a> {
+    Heads(&'a [ChangeHash]),
+    Clock(&'a VectorClock),
+}
+
+impl<'a> From<&'a [ChangeHash]> for ReadAt<'a> {
+    fn from(heads: &'a [ChangeHash]) -> Self {
+        ReadAt::Heads(heads)
+    }
+}
+
+impl<'a> From<&'a Vec<ChangeHash>> for ReadAt<'a> {
+    fn from(heads: &'a Vec<ChangeHash>) -> Self {
+        ReadAt::Heads(heads)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [ChangeHash; N]> for ReadAt<'a> {
+    fn from(heads: &'a [ChangeHash; N]) -> Self {
+        ReadAt::Heads(heads)
+    }
+}
+
+impl<'a> From<&'a VectorClock> for ReadAt<'a> {
+    fn from(clock: &'a VectorClock) -> Self {
+        ReadAt::Clock(clock)
+    }
+}
@@ -1,7 +1,6 @@
 use crate::types::{ObjId, Op};
-use fxhash::FxHasher;
 use std::fmt::Write;
-use std::{borrow::Cow, collections::HashMap, hash::BuildHasherDefault};
+use std::{borrow::Cow, collections::HashMap};
 
 use rand::Rng;
 
@@ -43,11 +42,7 @@ pub(crate) struct GraphVisualisation<'a> {
 
 impl<'a> GraphVisualisation<'a> {
     pub(super) fn construct(
-        trees: &'a HashMap<
-            crate::types::ObjId,
-            crate::op_tree::OpTree,
-            BuildHasherDefault<FxHasher>,
-        >,
+        trees: impl Iterator<Item = (&'a crate::types::ObjId, &'a crate::op_tree::OpTree)>,
         osd: &'a crate::op_set::OpSetData,
     ) -> GraphVisualisation<'a> {
         let mut nodes = HashMap::new();
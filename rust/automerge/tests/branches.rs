@@ -0,0 +1,149 @@
+use automerge::{
+    error::BranchError, transaction::Transactable, AutoCommit, BranchesFromBytesError, ReadDoc,
+    ROOT,
+};
+
+#[test]
+fn commits_on_a_branch_are_isolated() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "base", 1).unwrap();
+    doc.commit();
+    let heads = doc.get_heads();
+    doc.create_branch("main", &heads).unwrap();
+    doc.create_branch("feature", &heads).unwrap();
+
+    doc.checkout_branch("feature").unwrap();
+    assert_eq!(doc.current_branch(), Some("feature"));
+    doc.put(ROOT, "feature", true).unwrap();
+    doc.commit();
+    let feature_heads = doc.get_heads();
+    assert_ne!(feature_heads, heads);
+
+    doc.checkout_branch("main").unwrap();
+    assert_eq!(doc.get_heads(), heads);
+    assert!(doc.get(ROOT, "feature").unwrap().is_none());
+    doc.put(ROOT, "main", true).unwrap();
+    // the open transaction is committed to "main" when switching branches
+    doc.checkout_branch("feature").unwrap();
+    assert!(doc.get(ROOT, "main").unwrap().is_none());
+    assert!(doc.get(ROOT, "feature").unwrap().is_some());
+    assert_eq!(doc.branch_heads("feature"), Some(feature_heads));
+
+    doc.integrate();
+    assert_eq!(doc.current_branch(), None);
+    assert!(doc.get(ROOT, "main").unwrap().is_some());
+    assert!(doc.get(ROOT, "feature").unwrap().is_some());
+}
+
+#[test]
+fn merge_branches() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "base", 1).unwrap();
+    doc.commit();
+    let heads = doc.get_heads();
+    doc.create_branch("main", &heads).unwrap();
+    doc.create_branch("feature", &heads).unwrap();
+    doc.checkout_branch("feature").unwrap();
+    doc.put(ROOT, "feature", true).unwrap();
+    doc.checkout_branch("main").unwrap();
+    doc.put(ROOT, "main", true).unwrap();
+    doc.commit();
+
+    doc.update_diff_cursor();
+    let merged = doc.merge_branch("feature", "main").unwrap();
+    assert_eq!(merged.len(), 2);
+    assert_eq!(doc.get_heads(), merged);
+    assert!(doc.get(ROOT, "feature").unwrap().is_some());
+    assert_eq!(doc.diff_incremental().len(), 1);
+
+    // the source branch is unchanged and merging again keeps only the latest heads
+    doc.checkout_branch("feature").unwrap();
+    assert!(doc.get(ROOT, "main").unwrap().is_none());
+    doc.put(ROOT, "more", 2).unwrap();
+    doc.commit();
+    let feature_heads = doc.get_heads();
+    let merged = doc.merge_branch("main", "feature").unwrap();
+    assert_eq!(merged.len(), 2);
+    let merged = doc.merge_branch("feature", "main").unwrap();
+    assert_eq!(merged, doc.branch_heads("feature").unwrap());
+    assert_ne!(merged, feature_heads);
+}
+
+#[test]
+fn save_and_load_branches() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.commit();
+    let first = doc.get_heads();
+    doc.put(ROOT, "b", 2).unwrap();
+    doc.commit();
+    let second = doc.get_heads();
+    doc.create_branch("first", &first).unwrap();
+    doc.create_branch("second", &second).unwrap();
+    doc.create_branch("empty", &[]).unwrap();
+
+    let mut loaded = AutoCommit::load(&doc.save()).unwrap();
+    loaded.load_branches(&doc.save_branches()).unwrap();
+    assert_eq!(loaded.branches(), doc.branches());
+    assert_eq!(
+        loaded
+            .branches()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>(),
+        vec!["empty", "first", "second"]
+    );
+
+    loaded.checkout_branch("first").unwrap();
+    assert!(loaded.get(ROOT, "b").unwrap().is_none());
+
+    assert!(matches!(
+        AutoCommit::new().load_branches(&doc.save_branches()),
+        Err(BranchError::Automerge(_))
+    ));
+    let mut truncated = doc.save_branches();
+    truncated.pop();
+    assert!(matches!(
+        loaded.load_branches(&truncated),
+        Err(BranchError::Load(BranchesFromBytesError::ParseHead))
+    ));
+}
+
+#[test]
+fn branch_errors() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.commit();
+    let heads = doc.get_heads();
+    doc.create_branch("main", &heads).unwrap();
+    assert!(matches!(
+        doc.create_branch("main", &heads),
+        Err(BranchError::BranchExists(_))
+    ));
+    assert!(matches!(
+        doc.checkout_branch("missing"),
+        Err(BranchError::NoSuchBranch(_))
+    ));
+    assert!(matches!(
+        doc.merge_branch("missing", "main"),
+        Err(BranchError::NoSuchBranch(_))
+    ));
+
+    let mut other = AutoCommit::new();
+    other.put(ROOT, "b", 1).unwrap();
+    other.commit();
+    assert!(matches!(
+        doc.create_branch("other", &other.get_heads()),
+        Err(BranchError::Automerge(_))
+    ));
+
+    doc.checkout_branch("main").unwrap();
+    assert!(matches!(
+        doc.delete_branch("main"),
+        Err(BranchError::CurrentBranch(_))
+    ));
+    doc.isolate(&heads);
+    assert_eq!(doc.current_branch(), None);
+    doc.delete_branch("main").unwrap();
+    assert!(doc.branches().is_empty());
+}
@@ -0,0 +1,144 @@
+use automerge::{
+    transaction::Transactable, AutoCommit, Automerge, Change, Compression, ObjType, SaveOptions,
+    ROOT,
+};
+
+fn large_doc() -> AutoCommit {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    for i in 0..200 {
+        doc.splice_text(&text, i, 0, "a").unwrap();
+    }
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..200 {
+        doc.insert(&list, i, (i % 7) as i64).unwrap();
+    }
+    doc.commit();
+    doc
+}
+
+/// The chunk type which follows the magic bytes and checksum of a chunk
+fn chunk_type(chunk: &[u8]) -> u8 {
+    chunk[8]
+}
+
+fn all_compressions() -> Vec<Compression> {
+    let compressions = vec![
+        Compression::None,
+        Compression::Deflate(1),
+        Compression::default(),
+        Compression::Deflate(9),
+    ];
+    #[cfg(feature = "zstd")]
+    let compressions = [
+        compressions,
+        vec![Compression::Zstd(1), Compression::Zstd(19)],
+    ]
+    .concat();
+    compressions
+}
+
+#[test]
+fn documents_load_with_any_compression() {
+    let mut doc = large_doc();
+    let uncompressed = doc.save_with_options(SaveOptions {
+        compression: Compression::None,
+        ..Default::default()
+    });
+    for compression in all_compressions() {
+        let saved = doc.save_with_options(SaveOptions {
+            compression,
+            ..Default::default()
+        });
+        if compression != Compression::None {
+            assert!(saved.len() < uncompressed.len(), "{:?}", compression);
+        }
+        #[cfg(feature = "zstd")]
+        if let Compression::Zstd(_) = compression {
+            assert_eq!(chunk_type(&saved), 3);
+        }
+        if let Compression::None | Compression::Deflate(_) = compression {
+            assert_eq!(chunk_type(&saved), 0);
+        }
+        let loaded = Automerge::load(&saved).unwrap();
+        assert_eq!(loaded.get_heads(), doc.get_heads());
+        assert_eq!(loaded.save_nocompress(), uncompressed);
+    }
+}
+
+#[test]
+fn changes_compress_with_any_compression() {
+    let mut doc = large_doc();
+    let change = doc.get_changes(&[])[0].clone();
+    for compression in all_compressions() {
+        let mut change = change.clone();
+        let compressed = change.compress(compression).into_owned();
+        assert_eq!(change.bytes(), compressed);
+        if compression == Compression::None {
+            assert_eq!(compressed, change.raw_bytes());
+            assert_eq!(chunk_type(&compressed), 1);
+        } else {
+            assert!(compressed.len() < change.raw_bytes().len());
+        }
+        #[cfg(feature = "zstd")]
+        if let Compression::Zstd(_) = compression {
+            assert_eq!(chunk_type(&compressed), 4);
+        }
+        if let Compression::Deflate(_) = compression {
+            assert_eq!(chunk_type(&compressed), 2);
+        }
+        let loaded = Change::try_from(&compressed[..]).unwrap();
+        assert_eq!(loaded.hash(), change.hash());
+        assert_eq!(loaded.raw_bytes(), change.raw_bytes());
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_deflate_flag_disables_compression() {
+    let mut doc = large_doc();
+    let saved = doc.save_with_options(SaveOptions {
+        deflate: false,
+        ..Default::default()
+    });
+    assert_eq!(saved, doc.save_nocompress());
+    let saved = doc.save_with_options(SaveOptions {
+        deflate: true,
+        ..Default::default()
+    });
+    assert_eq!(saved, doc.save());
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read(path).unwrap()
+}
+
+#[cfg(not(feature = "zstd"))]
+#[test]
+fn zstd_data_requires_the_feature() {
+    use automerge::{error::LoadError, AutomergeError, LoadChangeError};
+
+    let doc = fixture("zstd_document.automerge");
+    assert!(matches!(
+        Automerge::load(&doc),
+        Err(AutomergeError::Load(LoadError::UnsupportedCompression))
+    ));
+    let change = fixture("zstd_change.automerge");
+    assert!(matches!(
+        Change::try_from(&change[..]),
+        Err(LoadChangeError::UnsupportedCompression)
+    ));
+    assert!(matches!(
+        Automerge::new().load_incremental(&change),
+        Err(AutomergeError::Load(LoadError::UnsupportedCompression))
+    ));
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn zstd_fixtures_load_with_the_feature() {
+    let doc = Automerge::load(&fixture("zstd_document.automerge")).unwrap();
+    let change = Change::try_from(&fixture("zstd_change.automerge")[..]).unwrap();
+    assert_eq!(doc.get_heads(), vec![change.hash()]);
+}
@@ -0,0 +1,87 @@
+use automerge::{
+    patches::TextRepresentation, transaction::Transactable, AutoCommit, Automerge, AutomergeError,
+    ObjType, PatchAction, ReadDoc, ROOT,
+};
+
+fn assert_patches_convert(a: &Automerge, b: &Automerge) {
+    let patches = a.diff_documents(b, TextRepresentation::String).unwrap();
+    let mut hydrated = a.hydrate(None);
+    hydrated.apply_patches(patches).unwrap();
+    assert_eq!(hydrated, b.hydrate(None));
+}
+
+#[test]
+fn diff_documents_with_shared_history() {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "hello").unwrap();
+    doc1.put(ROOT, "kept", 1).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+    doc1.splice_text(&text, 5, 0, " world").unwrap();
+    doc1.put(ROOT, "only_in_1", true).unwrap();
+    doc2.splice_text(&text, 0, 1, "j").unwrap();
+    doc2.put(ROOT, "kept", 2).unwrap();
+    doc2.commit();
+
+    let patches = doc1.diff_documents(&mut doc2).unwrap();
+    // text patches refer to the shared text object
+    assert!(patches.iter().any(|p| p.obj == text));
+    assert_patches_convert(doc1.document(), doc2.document());
+    assert_patches_convert(doc2.document(), doc1.document());
+
+    // neither document is modified
+    assert_eq!(doc1.text(&text).unwrap(), "hello world");
+    assert_eq!(doc2.text(&text).unwrap(), "jello");
+    assert!(doc1.diff_documents(&mut doc1.clone()).unwrap().is_empty());
+}
+
+#[test]
+fn diff_unrelated_documents() {
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "a").unwrap();
+    doc1.insert(&list, 1, "b").unwrap();
+    doc1.put(ROOT, "same", "value").unwrap();
+    doc1.put(ROOT, "removed", 1).unwrap();
+    doc1.commit();
+
+    let mut doc2 = AutoCommit::new();
+    let list2 = doc2.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc2.insert(&list2, 0, "a").unwrap();
+    doc2.insert(&list2, 1, "c").unwrap();
+    doc2.put(ROOT, "same", "value").unwrap();
+    let map = doc2.put_object(ROOT, "map", ObjType::Map).unwrap();
+    doc2.put(&map, "nested", 3).unwrap();
+    doc2.commit();
+
+    let patches = doc1.diff_documents(&mut doc2).unwrap();
+    assert!(!patches.iter().any(|p| matches!(
+        &p.action,
+        PatchAction::PutMap { key, .. } if key == "same"
+    )));
+    assert_patches_convert(doc1.document(), doc2.document());
+    assert_patches_convert(doc2.document(), doc1.document());
+    assert_patches_convert(&Automerge::new(), doc2.document());
+    assert!(Automerge::new()
+        .diff_documents(&Automerge::new(), TextRepresentation::String)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn diff_documents_with_conflicting_actor_history() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.clone();
+    doc1.put(ROOT, "b", 1).unwrap();
+    doc1.commit();
+    doc2.put(ROOT, "b", 2).unwrap();
+    doc2.commit();
+
+    assert!(matches!(
+        doc1.diff_documents(&mut doc2),
+        Err(AutomergeError::DuplicateSeqNumber(2, _))
+    ));
+}
@@ -0,0 +1,149 @@
+use automerge::{
+    hydrate_list, hydrate_map,
+    transaction::{CommitOptions, Transactable},
+    ActorId, AutoCommit, AutoSerde, ObjType, OpType, Prop, ReadDoc, ROOT,
+};
+
+#[test]
+fn history_resolves_props_and_objects() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "abc").unwrap();
+    doc.commit();
+    doc.splice_text(&text, 1, 1, "").unwrap();
+    doc.put(ROOT, "key", "value").unwrap();
+    doc.commit();
+
+    let entries = doc.iter_history().collect::<Vec<_>>();
+    assert_eq!(entries.len(), 2);
+
+    let first = entries[0].ops();
+    assert_eq!(first[0].obj, ROOT);
+    assert_eq!(first[0].prop, Prop::Map("text".into()));
+    assert_eq!(first[0].action, OpType::Make(ObjType::Text));
+    assert_eq!(first[0].id, text);
+    assert_eq!(
+        first[1..]
+            .iter()
+            .map(|op| op.prop.clone())
+            .collect::<Vec<_>>(),
+        vec![Prop::Seq(0), Prop::Seq(1), Prop::Seq(2)]
+    );
+    assert!(first[1..].iter().all(|op| op.insert && op.obj == text));
+
+    let second = entries[1].ops();
+    assert_eq!(second[0].action, OpType::Delete);
+    assert_eq!(second[0].prop, Prop::Seq(1));
+    assert_eq!(second[0].pred, vec![first[2].id.clone()]);
+    assert_eq!(second[1].prop, Prop::Map("key".into()));
+    assert_eq!(second[1].action, OpType::Put("value".into()));
+    assert_eq!(entries[1].change().hash(), doc.get_heads()[0]);
+}
+
+#[test]
+fn history_filters() {
+    let actor1 = ActorId::from(b"actor1");
+    let actor2 = ActorId::from(b"actor2");
+    let mut doc1 = AutoCommit::new().with_actor(actor1.clone());
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.commit_with(CommitOptions::default().with_time(10));
+    let map = doc1.put_object(ROOT, "map", ObjType::Map).unwrap();
+    doc1.commit_with(CommitOptions::default().with_time(20));
+
+    let mut doc2 = doc1.fork().with_actor(actor2.clone());
+    doc2.insert(&list, 0, 1).unwrap();
+    doc2.commit_with(CommitOptions::default().with_time(30));
+    doc2.put(&map, "key", 1).unwrap();
+    doc2.commit_with(CommitOptions::default().with_time(40));
+    doc1.merge(&mut doc2).unwrap();
+
+    let times = |history: automerge::history::HistoryIter<'_>| {
+        history.map(|e| e.change().timestamp()).collect::<Vec<_>>()
+    };
+    assert_eq!(times(doc1.iter_history()), vec![10, 20, 30, 40]);
+    assert_eq!(times(doc1.iter_history().by_actor(actor2)), vec![30, 40]);
+    assert_eq!(
+        times(doc1.iter_history().in_time_range(15..=30)),
+        vec![20, 30]
+    );
+    assert_eq!(times(doc1.iter_history().touching(list.clone())), vec![30]);
+    assert_eq!(times(doc1.iter_history().touching(ROOT)), vec![10, 20]);
+    assert_eq!(
+        times(
+            doc1.iter_history()
+                .by_actor(actor1)
+                .touching(list)
+                .touching(map)
+        ),
+        Vec::<i64>::new()
+    );
+}
+
+#[test]
+fn heads_at_time() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit_with(CommitOptions::default().with_time(10));
+    let c1 = doc1.get_heads();
+    let mut doc2 = doc1.fork();
+    doc1.put(ROOT, "b", 2).unwrap();
+    doc1.commit_with(CommitOptions::default().with_time(20));
+    doc2.put(ROOT, "a", 3).unwrap();
+    doc2.commit_with(CommitOptions::default().with_time(15));
+    let c3 = doc2.get_heads()[0];
+    doc1.merge(&mut doc2).unwrap();
+    // a change whose clock is behind the changes it depends on
+    doc1.put(ROOT, "c", 4).unwrap();
+    doc1.commit_with(CommitOptions::default().with_time(12));
+    let c4 = doc1.get_heads();
+
+    assert!(doc1.heads_at_time(9).is_empty());
+    assert_eq!(doc1.heads_at_time(10), c1);
+    // the change at 12 is excluded because it depends on the change at 20
+    assert_eq!(doc1.heads_at_time(15), vec![c3]);
+    assert_eq!(doc1.heads_at_time(20), c4);
+
+    let view = doc1.view_at_time(15);
+    assert_eq!(view.heads(), &[c3]);
+    assert_eq!(view.get(ROOT, "a").unwrap().unwrap().0, 3.into());
+    assert!(view.get(ROOT, "b").unwrap().is_none());
+    assert!(view.get(ROOT, "c").unwrap().is_none());
+    assert_eq!(view.keys(ROOT).collect::<Vec<_>>(), vec!["a"]);
+}
+
+#[test]
+fn view_at_heads() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "a").unwrap();
+    doc.put(ROOT, "key", "before").unwrap();
+    doc.commit();
+    let heads = doc.get_heads();
+    doc.insert(&list, 1, "b").unwrap();
+    doc.put(ROOT, "key", "after").unwrap();
+    doc.commit();
+    let latest = doc.get_heads();
+
+    fn list_len<R: ReadDoc>(doc: &R, list: &automerge::ObjId) -> usize {
+        doc.length(list)
+    }
+
+    let view = doc.view_at(&heads);
+    assert_eq!(view.heads(), heads.as_slice());
+    assert_eq!(list_len(&view, &list), 1);
+    assert_eq!(view.get(ROOT, "key").unwrap().unwrap().0, "before".into());
+    assert_eq!(
+        serde_json::to_value(AutoSerde::from(&view)).unwrap(),
+        serde_json::json!({"key": "before", "list": ["a"]})
+    );
+    assert_eq!(
+        view.hydrate(ROOT, None).unwrap(),
+        hydrate_map! {"key" => "before", "list" => hydrate_list!["a"]}.into()
+    );
+    // explicit heads still take priority over the heads of the view
+    assert_eq!(view.length_at(&list, &latest), 2);
+    assert_eq!(
+        view.hydrate(ROOT, Some(&latest)).unwrap(),
+        hydrate_map! {"key" => "after", "list" => hydrate_list!["a", "b"]}.into()
+    );
+}
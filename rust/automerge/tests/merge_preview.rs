@@ -0,0 +1,103 @@
+use automerge::{
+    transaction::Transactable, ActorId, AutoCommit, MergeConflict, ObjType, PatchAction, Prop,
+    ReadDoc, ScalarValue, Value, ROOT,
+};
+
+#[test]
+fn preview_merge_matches_merge() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"actor1"));
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "a").unwrap();
+    doc1.put(ROOT, "key", "original").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"actor2"));
+    doc1.put(ROOT, "key", "mine").unwrap();
+    doc1.put(ROOT, "other", 1).unwrap();
+    doc1.commit();
+    doc2.put(ROOT, "key", "theirs").unwrap();
+    doc2.put(&list, 0, "b").unwrap();
+    doc2.insert(&list, 1, "c").unwrap();
+    doc2.commit();
+    let heads = doc1.get_heads();
+    let saved = doc1.save();
+
+    let preview = doc1.preview_merge(&mut doc2).unwrap();
+
+    // the document is untouched
+    assert_eq!(doc1.get_heads(), heads);
+    assert_eq!(doc1.save(), saved);
+    assert_eq!(doc1.length(&list), 1);
+
+    let mut merged = doc1.fork();
+    merged.merge(&mut doc2).unwrap();
+    let values = merged
+        .get_all(ROOT, "key")
+        .unwrap()
+        .into_iter()
+        .map(|(v, id)| (v.into_owned(), id))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        preview.conflicts,
+        vec![MergeConflict {
+            obj: ROOT,
+            prop: Prop::Map("key".into()),
+            values,
+        }]
+    );
+    assert_eq!(preview.conflicts[0].values.len(), 2);
+
+    doc1.update_diff_cursor();
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(preview.patches, doc1.diff_incremental());
+}
+
+#[test]
+fn preview_merge_without_conflicts() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+    doc2.put(ROOT, "b", 2).unwrap();
+    doc2.commit();
+
+    let preview = doc1.preview_merge(&mut doc2).unwrap();
+    assert!(preview.conflicts.is_empty());
+    assert_eq!(preview.patches.len(), 1);
+    assert!(matches!(
+        &preview.patches[0].action,
+        PatchAction::PutMap { key, value: (Value::Scalar(v), _), conflict: false }
+            if key == "b" && v.as_ref() == &ScalarValue::Int(2)
+    ));
+
+    // merging something we already have does nothing
+    let preview = doc2.preview_merge(&mut doc1).unwrap();
+    assert!(preview.patches.is_empty());
+    assert!(preview.conflicts.is_empty());
+}
+
+#[test]
+fn preview_apply_changes_reports_sequence_conflicts() {
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "a").unwrap();
+    doc1.insert(&list, 1, "b").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+    doc1.put(&list, 1, "mine").unwrap();
+    doc1.commit();
+    doc2.put(&list, 1, "theirs").unwrap();
+    doc2.commit();
+
+    let heads = doc1.get_heads();
+    let changes = doc2
+        .get_changes(&[])
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    let preview = doc1.preview_apply_changes(changes).unwrap();
+    assert_eq!(doc1.get_heads(), heads);
+    assert_eq!(preview.conflicts.len(), 1);
+    assert_eq!(preview.conflicts[0].obj, list);
+    assert_eq!(preview.conflicts[0].prop, Prop::Seq(1));
+    assert_eq!(preview.conflicts[0].values.len(), 2);
+}
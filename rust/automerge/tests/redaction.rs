@@ -0,0 +1,232 @@
+use automerge::{
+    marks::{ExpandMark, Mark},
+    transaction::Transactable,
+    ActorId, AutoCommit, AutomergeError, ObjType, ReadDoc, RedactionRecord, ScalarValue, Value,
+    ROOT,
+};
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|w| w == needle.as_bytes())
+}
+
+#[test]
+fn redact_by_key() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "user", "alice").unwrap();
+    doc.put(ROOT, "password", "hunter2").unwrap();
+    doc.commit();
+    let old_heads = doc.get_heads();
+    doc.delete(ROOT, "password").unwrap();
+    doc.put(ROOT, "note", "oops").unwrap();
+    doc.commit();
+    assert!(contains(&doc.save_nocompress(), "hunter2"));
+
+    let (mut redacted, record) = doc.redact(|op| op.key == Some("password")).unwrap();
+    assert_eq!(record.ops().len(), 1);
+    assert_eq!(record.rewritten().len(), 2);
+    let new_heads = redacted.get_heads();
+    assert_eq!(record.new_hash(&doc.get_heads()[0]), Some(new_heads[0]));
+
+    assert!(!contains(&redacted.save_nocompress(), "hunter2"));
+    for change in redacted.get_changes(&[]) {
+        assert!(!contains(change.raw_bytes(), "hunter2"));
+    }
+    // the original is untouched
+    assert!(contains(&doc.save_nocompress(), "hunter2"));
+
+    assert_eq!(redacted.get(ROOT, "password").unwrap(), None);
+    assert_eq!(
+        redacted.get(ROOT, "user").unwrap().unwrap().0,
+        Value::from("alice")
+    );
+    let old_heads = vec![record.new_hash(&old_heads[0]).unwrap()];
+    let (value, id) = redacted
+        .get_at(ROOT, "password", &old_heads)
+        .unwrap()
+        .unwrap();
+    assert_eq!(value, Value::from(ScalarValue::redacted()));
+    assert_eq!(id, record.ops()[0]);
+
+    let mut loaded = AutoCommit::load(&redacted.save()).unwrap();
+    assert_eq!(loaded.get_heads(), redacted.get_heads());
+}
+
+#[test]
+fn redact_by_value_and_id() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "public").unwrap();
+    doc.insert(&list, 1, "secret").unwrap();
+    doc.put(ROOT, "count", ScalarValue::counter(1)).unwrap();
+    doc.put(ROOT, "other", "secret").unwrap();
+    doc.commit();
+
+    let (mut redacted, record) = doc
+        .redact(|op| op.obj == list && op.value.to_str() == Some("secret"))
+        .unwrap();
+    assert_eq!(record.ops().len(), 1);
+    assert_eq!(redacted.length(&list), 2);
+    assert_eq!(
+        redacted.get(&list, 1).unwrap().unwrap().0,
+        Value::from(ScalarValue::redacted())
+    );
+    assert_eq!(
+        redacted.get(ROOT, "other").unwrap().unwrap().0,
+        Value::from("secret")
+    );
+
+    let (_, id) = doc.get(ROOT, "other").unwrap().unwrap();
+    let (mut redacted, record) = redacted.redact(|op| op.id == id).unwrap();
+    assert_eq!(record.ops(), &[id]);
+    assert!(redacted.get(ROOT, "other").unwrap().unwrap().0.is_scalar());
+    assert!(!contains(&redacted.save_nocompress(), "secret"));
+    // counters can't be redacted and keep working
+    redacted.increment(ROOT, "count", 2).unwrap();
+    assert_eq!(
+        redacted.get(ROOT, "count").unwrap().unwrap().0,
+        Value::counter(3)
+    );
+}
+
+#[test]
+fn redact_mark_values() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    doc.mark(
+        &text,
+        Mark::new("comment".to_string(), "hunter2", 0, 5),
+        ExpandMark::None,
+    )
+    .unwrap();
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 6, 11),
+        ExpandMark::None,
+    )
+    .unwrap();
+    doc.unmark(&text, "bold", 8, 9, ExpandMark::None).unwrap();
+    doc.commit();
+
+    let mut marks_seen = Vec::new();
+    let (mut redacted, record) = doc
+        .redact(|op| {
+            if let Some(mark) = op.mark {
+                marks_seen.push(mark.to_string());
+            }
+            op.mark == Some("comment")
+        })
+        .unwrap();
+    // the unmark has a null value so it isn't offered
+    assert_eq!(marks_seen, vec!["comment", "bold"]);
+    assert_eq!(record.ops().len(), 1);
+    assert!(!contains(&redacted.save_nocompress(), "hunter2"));
+    let marks = redacted.marks(&text).unwrap();
+    let comment = marks.iter().find(|m| m.name() == "comment").unwrap();
+    assert_eq!((comment.start, comment.end), (0, 5));
+    assert_eq!(comment.value(), &ScalarValue::redacted());
+    assert_eq!(
+        marks.iter().filter(|m| m.name() == "bold").count(),
+        doc.marks(&text)
+            .unwrap()
+            .iter()
+            .filter(|m| m.name() == "bold")
+            .count()
+    );
+}
+
+#[test]
+fn peers_converge_on_redacted_history() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"actor1"));
+    doc1.put(ROOT, "password", "hunter2").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"actor2"));
+    doc2.put(ROOT, "theirs", 1).unwrap();
+    doc2.commit();
+    doc1.put(ROOT, "mine", 2).unwrap();
+    doc1.commit();
+
+    let (mut redacted1, record) = doc1.redact(|op| op.key == Some("password")).unwrap();
+    let record = RedactionRecord::try_from(record.to_bytes().as_slice()).unwrap();
+    let mut redacted2 = doc2.apply_redaction(&record).unwrap();
+    assert_eq!(
+        redacted2.get_changes(&[])[0].hash(),
+        redacted1.get_changes(&[])[0].hash()
+    );
+
+    redacted1.merge(&mut redacted2).unwrap();
+    redacted2.merge(&mut redacted1).unwrap();
+    assert_eq!(redacted1.get_heads(), redacted2.get_heads());
+    assert_eq!(redacted1.get_changes(&[]).len(), 3);
+    assert!(!contains(&redacted1.save_nocompress(), "hunter2"));
+    assert_eq!(redacted1.hydrate(ROOT, None), redacted2.hydrate(ROOT, None));
+
+    // applying a redaction twice changes nothing
+    let again = redacted1.apply_redaction(&record).unwrap().get_heads();
+    assert_eq!(again, redacted1.get_heads());
+}
+
+#[test]
+fn redacted_documents_ignore_the_original_changes() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"actor1"));
+    doc1.put(ROOT, "password", "hunter2").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"actor2"));
+    let (mut redacted, record) = doc1.redact(|op| op.key == Some("password")).unwrap();
+    let heads = redacted.get_heads();
+
+    // The original changes are ignored
+    redacted.merge(&mut doc1).unwrap();
+    redacted.load_incremental(&doc1.save()).unwrap();
+    assert_eq!(redacted.get_heads(), heads);
+    assert!(!contains(&redacted.save_nocompress(), "hunter2"));
+
+    // Changes made on top of them are rewritten to depend on the replacements
+    doc2.put(ROOT, "theirs", 1).unwrap();
+    doc2.commit();
+    redacted.merge(&mut doc2).unwrap();
+    assert_eq!(redacted.get_changes(&[]).len(), 2);
+    assert_eq!(
+        redacted.get(ROOT, "theirs").unwrap().unwrap().0,
+        Value::from(1)
+    );
+    assert!(!contains(&redacted.save_nocompress(), "hunter2"));
+
+    // which gives them the hashes the peer gets once it applies the redaction
+    let mut redacted2 = doc2.apply_redaction(&record).unwrap();
+    assert_eq!(redacted2.get_heads(), redacted.get_heads());
+}
+
+#[test]
+fn peers_must_apply_the_redaction_before_receiving_rewritten_changes() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"actor1"));
+    doc1.put(ROOT, "password", "hunter2").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"actor2"));
+    let (mut redacted, record) = doc1.redact(|op| op.key == Some("password")).unwrap();
+
+    assert!(matches!(
+        doc2.merge(&mut redacted),
+        Err(AutomergeError::DuplicateSeqNumber(1, _))
+    ));
+    let mut doc2 = doc2.apply_redaction(&record).unwrap();
+    doc2.merge(&mut redacted).unwrap();
+    assert_eq!(doc2.get_heads(), redacted.get_heads());
+}
+
+#[test]
+fn later_redactions_replace_earlier_ones() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "password", "hunter2").unwrap();
+    doc.put(ROOT, "pin", "1234").unwrap();
+    doc.commit();
+    let (mut once, _) = doc.redact(|op| op.key == Some("password")).unwrap();
+    let (mut twice, _) = once.redact(|op| op.key == Some("pin")).unwrap();
+    let heads = twice.get_heads();
+    twice.merge(&mut doc).unwrap();
+    twice.merge(&mut once).unwrap();
+    assert_eq!(twice.get_heads(), heads);
+    assert!(!contains(&twice.save_nocompress(), "1234"));
+}
@@ -0,0 +1,160 @@
+use automerge::{
+    marks::{ExpandMark, Mark},
+    transaction::Transactable,
+    AutoCommit, Automerge, ChangeHash, ObjType, ReadDoc, ROOT,
+};
+use proptest::{prop_assert, sample::Index};
+
+/// A saved document followed by a change chunk for each of `extra` further changes
+fn doc_and_changes(extra: usize) -> (AutoCommit, Vec<u8>, Vec<Vec<u8>>) {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.commit();
+    doc.put(ROOT, "b", 2).unwrap();
+    doc.commit();
+    let saved = doc.save();
+    let mut changes = Vec::new();
+    for i in 0..extra {
+        let heads = doc.get_heads();
+        doc.put(ROOT, format!("key{}", i), i as i64).unwrap();
+        doc.commit();
+        changes.push(doc.get_changes(&heads)[0].raw_bytes().to_vec());
+    }
+    (doc, saved, changes)
+}
+
+fn hashes(doc: &mut AutoCommit) -> Vec<ChangeHash> {
+    doc.get_changes(&[]).iter().map(|c| c.hash()).collect()
+}
+
+#[test]
+fn repair_of_valid_data_recovers_everything() {
+    let (mut doc, saved, changes) = doc_and_changes(2);
+    let data = [saved, changes.concat()].concat();
+    let (repaired, report) = Automerge::repair(&data);
+    assert_eq!(repaired.get_heads(), doc.get_heads());
+    assert_eq!(report.recovered.len(), 4);
+    assert!(report.orphaned.is_empty());
+    assert!(report.lost.is_empty());
+    assert!(report.corrupt.is_empty());
+    assert_eq!(repaired.save(), doc.save());
+}
+
+#[test]
+fn repair_skips_corrupt_regions() {
+    let (mut doc, saved, changes) = doc_and_changes(4);
+    let all = hashes(&mut doc);
+
+    // garbage between two valid change chunks
+    let garbage = vec![0xff; 17];
+    // a change chunk with a flipped bit in its ops
+    let mut damaged = changes[2].clone();
+    let last = damaged.len() - 1;
+    damaged[last] ^= 1;
+
+    let mut data = saved.clone();
+    data.extend(&changes[0]);
+    let garbage_start = data.len();
+    data.extend(&garbage);
+    data.extend(&changes[1]);
+    let damaged_start = data.len();
+    data.extend(&damaged);
+    data.extend(&changes[3]);
+
+    let (mut repaired, report) = AutoCommit::repair(&data);
+    assert_eq!(report.recovered, all[..4].to_vec());
+    assert_eq!(report.lost, vec![all[4]]);
+    assert_eq!(report.orphaned, vec![all[5]]);
+    assert_eq!(
+        report.corrupt,
+        vec![
+            garbage_start..garbage_start + garbage.len(),
+            damaged_start..damaged_start + damaged.len()
+        ]
+    );
+    assert_eq!(repaired.get_heads(), vec![all[3]]);
+    assert_eq!(repaired.get(ROOT, "key1").unwrap().unwrap().0, 1.into());
+    assert!(repaired.get(ROOT, "key3").unwrap().is_none());
+}
+
+#[test]
+fn repair_finds_chunks_swallowed_by_a_corrupt_length() {
+    let (mut doc, _, changes) = doc_and_changes(2);
+    let all = hashes(&mut doc);
+    // The length of the first chunk follows the 4 magic bytes, 4 checksum bytes and chunk type.
+    // Making it longer means the first chunk appears to contain the second.
+    let mut first = changes[0].clone();
+    first[9] += 1;
+    let data = [first, changes[1].clone(), vec![0]].concat();
+    let (_, report) = Automerge::repair(&data);
+    assert_eq!(report.orphaned, vec![all[3]]);
+    assert_eq!(report.lost, vec![all[2]]);
+    assert_eq!(
+        report.corrupt,
+        vec![0..changes[0].len(), data.len() - 1..data.len()]
+    );
+}
+
+#[test]
+fn repair_accepts_document_chunks_with_a_bad_checksum_if_the_heads_match() {
+    let (mut doc, mut saved, _) = doc_and_changes(0);
+    saved[4] ^= 0xff;
+    assert!(Automerge::load(&saved).is_err());
+    let (repaired, report) = Automerge::repair(&saved);
+    assert_eq!(repaired.get_heads(), doc.get_heads());
+    assert_eq!(report.recovered, hashes(&mut doc));
+    assert!(report.lost.is_empty());
+}
+
+#[test]
+fn repair_reports_the_heads_of_undecodable_document_chunks() {
+    let (mut doc, mut saved, changes) = doc_and_changes(1);
+    let heads = hashes(&mut doc)[1..2].to_vec();
+    // corrupt the last byte of the document, which is in the ops columns
+    let last = saved.len() - 1;
+    saved[last] ^= 0xff;
+    let data = [saved.clone(), changes[0].clone()].concat();
+    let (repaired, report) = Automerge::repair(&data);
+    assert!(report.recovered.is_empty());
+    assert_eq!(report.orphaned, hashes(&mut doc)[2..].to_vec());
+    assert_eq!(report.lost, heads);
+    assert_eq!(report.corrupt, vec![0..saved.len()]);
+    assert!(repaired.get_heads().is_empty());
+}
+
+/// A saved document using every kind of op, so that flipped bytes land in all of the op columns
+fn varied_save() -> Vec<u8> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "counter", automerge::ScalarValue::counter(1))
+        .unwrap();
+    doc.put(ROOT, "bytes", vec![1_u8, 2, 3]).unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, 1.5).unwrap();
+    doc.insert(&list, 1, true).unwrap();
+    doc.commit();
+    doc.increment(ROOT, "counter", 2).unwrap();
+    doc.delete(&list, 0).unwrap();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    doc.mark(
+        &text,
+        Mark::new("bold".into(), true, 0, 5),
+        ExpandMark::After,
+    )
+    .unwrap();
+    doc.commit();
+    doc.save()
+}
+
+proptest::proptest! {
+    #[test]
+    fn repair_does_not_panic_on_flipped_bytes(position: Index, mask in 1_u8..) {
+        let mut data = varied_save();
+        let i = position.index(data.len());
+        data[i] ^= mask;
+        let (repaired, report) = Automerge::repair(&data);
+        for hash in &report.recovered {
+            prop_assert!(repaired.get_change_by_hash(hash).is_some());
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use automerge::{
+    error::SquashError,
+    sync::{self, SyncDoc},
+    transaction::{CommitOptions, Transactable},
+    ActorId, AutoCommit, Automerge, ObjType, ReadDoc, ROOT,
+};
+
+fn sync(
+    a: &mut AutoCommit,
+    b: &mut AutoCommit,
+    a_to_b: &mut sync::State,
+    b_to_a: &mut sync::State,
+) {
+    loop {
+        let mut quiet = true;
+        if let Some(msg) = a.sync().generate_sync_message(a_to_b) {
+            quiet = false;
+            b.sync().receive_sync_message(b_to_a, msg).unwrap();
+        }
+        if let Some(msg) = b.sync().generate_sync_message(b_to_a) {
+            quiet = false;
+            a.sync().receive_sync_message(a_to_b, msg).unwrap();
+        }
+        if quiet {
+            break;
+        }
+    }
+}
+
+#[test]
+fn squash_local_changes_into_one() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.commit();
+    let since = doc.get_heads();
+    for (i, c) in "hello".chars().enumerate() {
+        doc.splice_text(&text, i, 0, &c.to_string()).unwrap();
+        doc.commit_with(
+            CommitOptions::default()
+                .with_message(c.to_string())
+                .with_time(i as i64),
+        );
+    }
+    let before = doc.hydrate(ROOT, None);
+    assert_eq!(doc.get_changes(&since).len(), 5);
+
+    let hash = doc.squash_local(&since, []).unwrap().unwrap();
+    assert_eq!(doc.get_heads(), vec![hash]);
+    assert_eq!(doc.hydrate(ROOT, None), before);
+    assert_eq!(doc.text(&text).unwrap(), "hello");
+    let changes = doc.get_changes(&since);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].deps(), since.as_slice());
+    assert_eq!(
+        changes[0].message().map(String::as_str),
+        Some("h\ne\nl\nl\no")
+    );
+    assert_eq!(changes[0].timestamp(), 4);
+
+    // the object IDs are unchanged and we can keep editing
+    doc.splice_text(&text, 5, 0, "!").unwrap();
+    assert_eq!(doc.text(&text).unwrap(), "hello!");
+    let loaded = AutoCommit::load(&doc.save()).unwrap();
+    assert_eq!(loaded.text(&text).unwrap(), "hello!");
+
+    // nothing left to squash
+    let heads = doc.get_heads();
+    assert_eq!(doc.squash_local(&heads, []).unwrap(), None);
+}
+
+#[test]
+fn squashed_changes_sync_with_peers() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"actor1"));
+    let mut doc2 = AutoCommit::new().with_actor(ActorId::from(b"actor2"));
+    let mut s1 = sync::State::new();
+    let mut s2 = sync::State::new();
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+    let since = doc1.get_heads();
+
+    doc1.put(ROOT, "b", 2).unwrap();
+    doc1.commit();
+    doc1.put(ROOT, "c", 3).unwrap();
+    doc1.commit();
+    doc2.put(ROOT, "d", 4).unwrap();
+    doc2.commit();
+    doc1.squash_local(&since, [&s1]).unwrap().unwrap();
+
+    sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+    assert_eq!(doc1.get_heads(), doc2.get_heads());
+    assert_eq!(doc2.get_changes(&since).len(), 2);
+    assert_eq!(doc1.hydrate(ROOT, None), doc2.hydrate(ROOT, None));
+}
+
+#[test]
+fn refuse_to_squash_shared_changes() {
+    let mut doc1 = Automerge::new();
+    let mut doc2 = Automerge::new();
+    let mut s1 = sync::State::new();
+    let mut s2 = sync::State::new();
+    let mut tx = doc1.transaction();
+    tx.put(ROOT, "a", 1).unwrap();
+    tx.commit();
+    let since = doc1.get_heads();
+    let mut tx = doc1.transaction();
+    tx.put(ROOT, "b", 2).unwrap();
+    tx.commit();
+    let shared = doc1.get_heads();
+    let msg = doc1.generate_sync_message(&mut s1).unwrap();
+    doc2.receive_sync_message(&mut s2, msg).unwrap();
+    let msg = doc2.generate_sync_message(&mut s2).unwrap();
+    doc1.receive_sync_message(&mut s1, msg).unwrap();
+    let msg = doc1.generate_sync_message(&mut s1).unwrap();
+    assert!(s1.sent_hashes.contains(&shared[0]));
+    doc2.receive_sync_message(&mut s2, msg).unwrap();
+
+    let mut tx = doc1.transaction();
+    tx.put(ROOT, "c", 3).unwrap();
+    tx.commit();
+    assert!(matches!(
+        doc1.squash_local(&since, [&s1]),
+        Err(SquashError::Shared(h)) if h == shared[0]
+    ));
+    // without the sync state the same changes can be squashed
+    assert!(doc1.clone().squash_local(&since, []).unwrap().is_some());
+    // only the changes the peer hasn't seen
+    assert_eq!(doc1.squash_local(&shared, [&s1]).unwrap(), None);
+}
+
+#[test]
+fn refuse_to_squash_changes_from_other_actors() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    let since = doc1.get_heads();
+    let mut doc2 = doc1.fork();
+    doc2.put(ROOT, "b", 2).unwrap();
+    doc2.commit();
+    doc1.put(ROOT, "c", 3).unwrap();
+    doc1.commit();
+    doc1.merge(&mut doc2).unwrap();
+    doc1.put(ROOT, "d", 4).unwrap();
+    doc1.commit();
+    assert!(matches!(
+        doc1.squash_local(&since, []),
+        Err(SquashError::NotLocal(_))
+    ));
+
+    // a change depending on the other actor's change can't be squashed with the one before it
+    let theirs = doc2.get_heads();
+    let mut doc3 = doc1.fork_at(&since).unwrap();
+    let actor = doc3.get_actor().clone();
+    doc3.put(ROOT, "e", 5).unwrap();
+    doc3.commit();
+    doc3.merge(&mut doc2).unwrap();
+    doc3.put(ROOT, "f", 6).unwrap();
+    doc3.commit();
+    assert_eq!(doc3.get_actor(), &actor);
+    assert!(matches!(
+        doc3.squash_local(&theirs, []),
+        Err(SquashError::Concurrent(_))
+    ));
+}
+
+#[test]
+fn squash_moves_cursors_at_the_heads() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.commit();
+    let since = doc.get_heads();
+    doc.save();
+    doc.put(ROOT, "b", 2).unwrap();
+    doc.commit();
+    doc.put(ROOT, "c", 3).unwrap();
+    doc.commit();
+    let heads = doc.get_heads();
+    doc.create_branch("main", &heads).unwrap();
+    doc.create_branch("old", &since).unwrap();
+    doc.update_diff_cursor();
+
+    let hash = doc.squash_local(&since, []).unwrap().unwrap();
+    assert_eq!(doc.branch_heads("main"), Some(vec![hash]));
+    assert_eq!(doc.branch_heads("old"), Some(since.clone()));
+    assert!(doc.diff_incremental().is_empty());
+
+    // saved changes are shared
+    doc.put(ROOT, "d", 4).unwrap();
+    doc.commit();
+    doc.save_incremental();
+    doc.put(ROOT, "e", 5).unwrap();
+    doc.commit();
+    assert!(matches!(
+        doc.squash_local(&[hash], []),
+        Err(SquashError::Shared(_))
+    ));
+}
@@ -0,0 +1,54 @@
+use automerge::{transaction::Transactable, AutoCommit, Compression, ObjType, SaveOptions, ROOT};
+
+#[test]
+fn storage_stats_match_saved_document() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..100 {
+        doc.insert(&list, i, i as i64).unwrap();
+    }
+    doc.put(ROOT, "key", "one").unwrap();
+    doc.commit();
+    doc.put(ROOT, "key", "two").unwrap();
+    doc.delete(&list, 0).unwrap();
+    doc.commit();
+
+    let stats = doc.storage_stats();
+    assert_eq!(stats.total_size, doc.save().len());
+    assert!(stats.op_columns.iter().any(|c| c.name == "keyStr"));
+    assert!(stats.change_columns.iter().any(|c| c.name == "actor"));
+    for column in stats.op_columns.iter().chain(stats.change_columns.iter()) {
+        assert!(column.compressed_size <= column.raw_size, "{:?}", column);
+    }
+
+    assert_eq!(stats.ops.total, 103);
+    assert_eq!(stats.ops.make, 1);
+    assert_eq!(stats.ops.put, 102);
+    assert_eq!(stats.ops.tombstones, 2);
+
+    assert_eq!(stats.objects.len(), 2);
+    assert_eq!(stats.objects[0].obj, ROOT);
+    assert_eq!(stats.objects[0].obj_type, ObjType::Map);
+    assert_eq!(stats.objects[0].ops.total, 3);
+    assert_eq!(stats.objects[1].obj, list);
+    assert_eq!(stats.objects[1].obj_type, ObjType::List);
+    assert_eq!(stats.objects[1].ops.total, 100);
+    assert!(stats.objects[1].encoded_size > stats.objects[0].encoded_size);
+}
+
+#[test]
+fn storage_stats_without_compression() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, &"a".repeat(500)).unwrap();
+    let options = || SaveOptions {
+        compression: Compression::None,
+        ..Default::default()
+    };
+    let stats = doc.storage_stats_with_options(options());
+    assert_eq!(stats.total_size, doc.save_with_options(options()).len());
+    for column in stats.op_columns.iter().chain(stats.change_columns.iter()) {
+        assert_eq!(column.compressed_size, column.raw_size);
+    }
+    assert!(stats.total_size > doc.storage_stats().total_size);
+}
@@ -0,0 +1,112 @@
+use automerge::{
+    transaction::Transactable, ActorId, AutoCommit, ObjType, ReadDoc, VectorClock,
+    VectorClockFromBytesError, ROOT,
+};
+
+#[test]
+fn vector_clock_compares_by_happened_before() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"actor1"));
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    let base = doc1.get_heads();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"actor2"));
+    doc1.put(ROOT, "b", 2).unwrap();
+    doc1.commit();
+    let left = doc1.get_heads();
+    doc2.put(ROOT, "c", 3).unwrap();
+    doc2.commit();
+    let right = doc2.get_heads();
+    doc1.merge(&mut doc2).unwrap();
+    let merged = doc1.get_heads();
+
+    let base = doc1.vector_clock(&base);
+    let left = doc1.vector_clock(&left);
+    let right = doc1.vector_clock(&right);
+    let merged = doc1.vector_clock(&merged);
+
+    assert_eq!(base.seq(&ActorId::from(b"actor1")), Some(1));
+    assert_eq!(left.seq(&ActorId::from(b"actor1")), Some(2));
+    assert_eq!(left.seq(&ActorId::from(b"actor2")), None);
+    assert_eq!(merged.len(), 2);
+    assert!(doc1.vector_clock(&[]).is_empty());
+
+    assert!(base < left);
+    assert!(left > base);
+    assert!(left < merged);
+    assert_eq!(left.partial_cmp(&right), None);
+    assert_eq!(
+        base.partial_cmp(&base.clone()),
+        Some(std::cmp::Ordering::Equal)
+    );
+}
+
+#[test]
+fn vector_clock_round_trips_through_bytes() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.commit();
+    let mut other = doc.fork();
+    other.put(ROOT, "b", 2).unwrap();
+    other.commit();
+    doc.merge(&mut other).unwrap();
+
+    let heads = doc.get_heads();
+    let clock = doc.vector_clock(&heads);
+    let bytes = clock.to_bytes();
+    assert_eq!(VectorClock::try_from(bytes.as_slice()).unwrap(), clock);
+    assert_eq!(
+        VectorClock::try_from(VectorClock::default().to_bytes().as_slice()).unwrap(),
+        VectorClock::default()
+    );
+
+    assert!(matches!(
+        VectorClock::try_from(&[][..]),
+        Err(VectorClockFromBytesError::NoVersion)
+    ));
+    assert!(matches!(
+        VectorClock::try_from(&bytes[..bytes.len() - 1]),
+        Err(VectorClockFromBytesError::ParseMaxOp(_))
+    ));
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(matches!(
+        VectorClock::try_from(trailing.as_slice()),
+        Err(VectorClockFromBytesError::TrailingBytes)
+    ));
+}
+
+#[test]
+fn read_at_vector_clock() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello").unwrap();
+    doc.put(ROOT, "key", "before").unwrap();
+    doc.commit();
+    let heads = doc.get_heads();
+    let clock = doc.vector_clock(&heads);
+    doc.splice_text(&text, 5, 0, " world").unwrap();
+    doc.put(ROOT, "key", "after").unwrap();
+    doc.commit();
+
+    let saved = VectorClock::try_from(clock.to_bytes().as_slice()).unwrap();
+    for at in [&clock, &saved] {
+        assert_eq!(doc.text_at(&text, at).unwrap(), "hello");
+        assert_eq!(doc.length_at(&text, at), 5);
+        assert_eq!(
+            doc.get_at(ROOT, "key", at).unwrap().unwrap().0,
+            "before".into()
+        );
+        assert_eq!(
+            doc.keys_at(ROOT, at).collect::<Vec<_>>(),
+            doc.keys_at(ROOT, &heads).collect::<Vec<_>>()
+        );
+    }
+
+    // a clock from another document only sees the actors this document knows about
+    let mut unrelated = AutoCommit::new();
+    unrelated.put(ROOT, "key", "unrelated").unwrap();
+    unrelated.commit();
+    let unrelated_heads = unrelated.get_heads();
+    let unrelated_clock = unrelated.vector_clock(&unrelated_heads);
+    assert!(doc.get_at(ROOT, "key", &unrelated_clock).unwrap().is_none());
+}